@@ -0,0 +1,123 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub struct Entry {
+    pub name: String,
+    pub is_dir: bool,
+}
+
+// Directory listing shown by `EditorMode::FileBrowser`
+pub struct FileBrowser {
+    pub dir: PathBuf,
+    pub entries: Vec<Entry>,
+    pub selected: usize,
+    pub show_hidden: bool,
+}
+
+impl FileBrowser {
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        let mut browser = Self {
+            dir: fs::canonicalize(dir)?,
+            entries: Vec::new(),
+            selected: 0,
+            show_hidden: false,
+        };
+        browser.reload()?;
+        Ok(browser)
+    }
+
+    // Re-read the directory: `..` first, then folders, then files, each sorted by name
+    fn reload(&mut self) -> io::Result<()> {
+        let mut entries: Vec<Entry> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| Entry {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                // Follow symlinks so a linked folder can be entered
+                is_dir: entry.path().is_dir(),
+            })
+            .filter(|entry| self.show_hidden || !entry.name.starts_with('.'))
+            .collect();
+        entries.sort_by(|a, b| {
+            b.is_dir
+                .cmp(&a.is_dir)
+                .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+        });
+        if self.dir.parent().is_some() {
+            entries.insert(0, Entry { name: "..".to_string(), is_dir: true });
+        }
+        self.entries = entries;
+        self.selected = self.selected.min(self.entries.len().saturating_sub(1));
+        Ok(())
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .map(|entry| {
+                if entry.is_dir {
+                    format!("{}/", entry.name)
+                } else {
+                    entry.name.clone()
+                }
+            })
+            .collect()
+    }
+
+    pub fn move_selection(&mut self, delta: isize) {
+        let last = self.entries.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    // Enter the selected folder, or return the selected file's path
+    pub fn activate(&mut self) -> io::Result<Option<PathBuf>> {
+        let Some(entry) = self.entries.get(self.selected) else {
+            return Ok(None);
+        };
+        if entry.name == ".." {
+            self.go_up()?;
+            return Ok(None);
+        }
+        let path = self.dir.join(&entry.name);
+        if !entry.is_dir {
+            return Ok(Some(path));
+        }
+        self.change_dir(path, None)?;
+        Ok(None)
+    }
+
+    // Go to the parent folder with the one we came from selected
+    pub fn go_up(&mut self) -> io::Result<()> {
+        let Some(parent) = self.dir.parent().map(Path::to_path_buf) else {
+            return Ok(());
+        };
+        let previous = self.dir.file_name().map(|n| n.to_string_lossy().into_owned());
+        self.change_dir(parent, previous)
+    }
+
+    pub fn toggle_hidden(&mut self) -> io::Result<()> {
+        let current = self.entries.get(self.selected).map(|e| e.name.clone());
+        self.show_hidden = !self.show_hidden;
+        self.reload()?;
+        self.select(current);
+        Ok(())
+    }
+
+    // Switch folders, staying put if the new one can't be read
+    fn change_dir(&mut self, dir: PathBuf, select: Option<String>) -> io::Result<()> {
+        let previous = std::mem::replace(&mut self.dir, dir);
+        if let Err(e) = self.reload() {
+            self.dir = previous;
+            return Err(e);
+        }
+        self.selected = 0;
+        self.select(select);
+        Ok(())
+    }
+
+    fn select(&mut self, name: Option<String>) {
+        if let Some(idx) = name.and_then(|name| self.entries.iter().position(|e| e.name == name)) {
+            self.selected = idx;
+        }
+    }
+}
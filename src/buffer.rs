@@ -0,0 +1,551 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::Range;
+
+use regex::Regex;
+
+// Lines longer than this (in bytes) make drawing and editing noticeably slow, as in
+// minified files
+pub const LONG_LINE_LEN: usize = 10_000;
+
+// Command pattern for undo/redo. Columns count characters, like everywhere else.
+#[derive(Clone, Debug)]
+pub enum EditCommand {
+    InsertChar { row: usize, col: usize, ch: char },
+    DeleteChar { row: usize, col: usize, ch: char },
+    InsertNewline { row: usize, col: usize },
+    DeleteNewline { row: usize, deleted_line: String },
+    // A run of text added or removed at a position; it may contain newlines
+    InsertText { row: usize, col: usize, text: String },
+    DeleteText { row: usize, col: usize, text: String },
+    // A whole line removed from a buffer that keeps at least one other line
+    DeleteLine { row: usize, content: String },
+    // Whole lines starting at `start` rewritten in one step
+    ReplaceLines { start: usize, old: Vec<String>, new: Vec<String> },
+    // Several commands undone and redone together
+    Group(Vec<EditCommand>),
+}
+
+impl EditCommand {
+    pub fn undo(&self, buffer: &mut TextBuffer) {
+    match self {
+        EditCommand::InsertChar { row, col, .. } => {
+            buffer.delete_char(*row, *col);
+        }
+        EditCommand::DeleteChar { row, col, ch } => {
+            buffer.insert_char(*row, *col, *ch);
+        }
+        EditCommand::InsertNewline { row, col: _ } => {
+            if *row + 1 < buffer.lines.len() {
+                let line = buffer.lines.remove(*row + 1);
+                buffer.lines[*row].push_str(&line);
+            }
+        }
+        EditCommand::DeleteNewline { row, deleted_line } => {
+            // `row` is the line that was joined onto the one above it
+            if *row > 0 && *row <= buffer.lines.len() {
+                let joined = &mut buffer.lines[*row - 1];
+                joined.truncate(joined.len() - deleted_line.len());
+                buffer.lines.insert(*row, deleted_line.clone());
+            }
+        }
+        EditCommand::InsertText { row, col, text } => {
+            buffer.delete_text(*row, *col, text);
+        }
+        EditCommand::DeleteText { row, col, text } => {
+            buffer.insert_text(*row, *col, text);
+        }
+        EditCommand::DeleteLine { row, content } => {
+            if *row <= buffer.lines.len() {
+                buffer.lines.insert(*row, content.clone());
+            }
+        }
+        EditCommand::ReplaceLines { start, old, new } => {
+            buffer.lines.splice(*start..*start + new.len(), old.iter().cloned());
+        }
+        EditCommand::Group(commands) => {
+            for command in commands.iter().rev() {
+                command.undo(buffer);
+            }
+        }
+    }
+}
+
+pub fn redo(&self, buffer: &mut TextBuffer) {
+    match self {
+        EditCommand::InsertChar { row, col, ch } => {
+            buffer.insert_char(*row, *col, *ch);
+        }
+        EditCommand::DeleteChar { row, col, .. } => {
+            buffer.delete_char(*row, *col);
+        }
+        EditCommand::InsertNewline { row, col } => {
+            buffer.insert_newline(*row, *col);
+        }
+        EditCommand::DeleteNewline { row, .. } => {
+            buffer.delete_newline(*row);
+        }
+        EditCommand::InsertText { row, col, text } => {
+            buffer.insert_text(*row, *col, text);
+        }
+        EditCommand::DeleteText { row, col, text } => {
+            buffer.delete_text(*row, *col, text);
+        }
+        EditCommand::DeleteLine { row, .. } => {
+            if *row < buffer.lines.len() && buffer.lines.len() > 1 {
+                buffer.lines.remove(*row);
+            }
+        }
+        EditCommand::ReplaceLines { start, old, new } => {
+            buffer.lines.splice(*start..*start + old.len(), new.iter().cloned());
+        }
+        EditCommand::Group(commands) => {
+            for command in commands {
+                command.redo(buffer);
+            }
+        }
+    }
+}
+}
+
+#[derive(Clone)]
+pub struct TextBuffer {
+    pub lines: Vec<String>,
+}
+
+impl Default for TextBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextBuffer {
+    pub fn new() -> Self {
+        Self {
+            lines: vec![String::new()],
+        }
+    }
+
+    pub fn from_string(content: String) -> Self {
+        let lines: Vec<String> = content.lines().map(|s| s.to_string()).collect();
+        Self {
+            lines: if lines.is_empty() {
+                vec![String::new()]
+            } else {
+                lines
+            },
+        }
+    }
+
+    // Stream the text, lines separated by `\n`, without building it as one string first
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        for (idx, line) in self.lines.iter().enumerate() {
+            if idx > 0 {
+                writer.write_all(b"\n")?;
+            }
+            writer.write_all(line.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn has_long_lines(&self) -> bool {
+        self.lines.iter().any(|line| line.len() > LONG_LINE_LEN)
+    }
+
+    fn insert_char(&mut self, row: usize, col: usize, ch: char) {
+        if let Some(line) = self.lines.get_mut(row) {
+            line.insert(byte_index(line, col), ch);
+        }
+    }
+
+    // Remove the character at `col`
+    fn delete_char(&mut self, row: usize, col: usize) -> Option<char> {
+        let line = self.lines.get_mut(row)?;
+        let idx = byte_index(line, col);
+        (idx < line.len()).then(|| line.remove(idx))
+    }
+
+    fn insert_newline(&mut self, row: usize, col: usize) {
+        if let Some(line) = self.lines.get_mut(row) {
+            let new_line = line.split_off(byte_index(line, col));
+            self.lines.insert(row + 1, new_line);
+        }
+    }
+
+    fn delete_newline(&mut self, row: usize) -> Option<String> {
+        if row > 0 && row < self.lines.len() {
+            let line = self.lines.remove(row);
+            self.lines[row - 1].push_str(&line);
+            Some(line)
+        } else {
+            None
+        }
+    }
+
+    // Insert `text` at a position, splitting the line at each newline in it
+    fn insert_text(&mut self, row: usize, col: usize, text: &str) {
+        let Some(line) = self.lines.get_mut(row) else {
+            return;
+        };
+        let tail = line.split_off(byte_index(line, col));
+        let mut pieces = text.split('\n');
+        line.push_str(pieces.next().unwrap_or(""));
+        let mut new_lines: Vec<String> = pieces.map(str::to_string).collect();
+        match new_lines.last_mut() {
+            Some(last) => last.push_str(&tail),
+            None => line.push_str(&tail),
+        }
+        self.lines.splice(row + 1..row + 1, new_lines);
+    }
+
+    // Remove `text`, which must be what the buffer holds at the position, joining the
+    // lines it spanned
+    fn delete_text(&mut self, row: usize, col: usize, text: &str) {
+        let (end_row, end_col) = end_of_text(row, col, text);
+        if end_row >= self.lines.len() {
+            return;
+        }
+        let (start, end) = (&self.lines[row], &self.lines[end_row]);
+        let joined = format!("{}{}", &start[..byte_index(start, col)], &end[byte_index(end, end_col)..]);
+        self.lines.splice(row..=end_row, [joined]);
+    }
+
+    // `col` moved back to the end of the line if it's past it
+    pub fn floor_col(&self, row: usize, col: usize) -> usize {
+        col.min(self.line_len(row))
+    }
+
+    // Length of a line in characters, 0 past the end
+    pub fn line_len(&self, row: usize) -> usize {
+        self.lines.get(row).map_or(0, |line| line.chars().count())
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn get_line(&self, row: usize) -> Option<&String> {
+        self.lines.get(row)
+    }
+
+    // Text between two positions (start inclusive, end exclusive), joined with newlines
+    pub fn text_range(&self, start: (usize, usize), end: (usize, usize)) -> String {
+        let slice = |row: usize, from: usize, to: Option<usize>| -> &str {
+            let line = self.lines.get(row).map(|l| l.as_str()).unwrap_or("");
+            let to = to.map_or(line.len(), |to| byte_index(line, to));
+            &line[byte_index(line, from).min(to)..to]
+        };
+        if start.0 == end.0 {
+            return slice(start.0, start.1, Some(end.1)).to_string();
+        }
+        let mut text = slice(start.0, start.1, None).to_string();
+        for row in start.0 + 1..end.0 {
+            text.push('\n');
+            text.push_str(slice(row, 0, None));
+        }
+        text.push('\n');
+        text.push_str(slice(end.0, 0, Some(end.1)));
+        text
+    }
+
+    fn is_blank(&self, row: usize) -> bool {
+        self.lines.get(row).is_some_and(|l| l.trim().is_empty())
+    }
+
+    // Next blank line after the paragraph at `row`, or the last line
+    pub fn next_blank_line(&self, row: usize) -> usize {
+        let last = self.lines.len() - 1;
+        let mut row = row.min(last);
+        while row < last && self.is_blank(row) {
+            row += 1;
+        }
+        while row < last && !self.is_blank(row) {
+            row += 1;
+        }
+        row
+    }
+
+    // Previous blank line before the paragraph at `row`, or the first line
+    pub fn prev_blank_line(&self, row: usize) -> usize {
+        let mut row = row.min(self.lines.len() - 1);
+        while row > 0 && self.is_blank(row) {
+            row -= 1;
+        }
+        while row > 0 && !self.is_blank(row) {
+            row -= 1;
+        }
+        row
+    }
+
+    // Column of the first non-whitespace character, or 0 for blank lines
+    pub fn first_non_whitespace_col(&self, row: usize) -> usize {
+        self.lines
+            .get(row)
+            .and_then(|line| line.chars().position(|c| !c.is_whitespace()))
+            .unwrap_or(0)
+    }
+
+    // Find `query` at or after the start position. Returns (row, col, wrapped), where
+    // `wrapped` means the match was found by continuing from the top of the buffer.
+    // A literal query with line breaks matches across lines.
+    pub fn search(
+        &self,
+        query: &str,
+        start_row: usize,
+        start_col: usize,
+        options: SearchOptions,
+    ) -> Option<(usize, usize, bool)> {
+        if query.is_empty() {
+            return None;
+        }
+        let pattern = SearchPattern::new(query, options.kind).ok()?;
+        // Matching works on bytes; the columns going in and out count characters
+        let start_col = self.lines.get(start_row).map_or(0, |line| byte_index(line, start_col));
+        let found = |row: usize, col: usize, wrapped: bool| Some((row, char_index(&self.lines[row], col), wrapped));
+
+        // Search from current position to end
+        for row in start_row..self.lines.len() {
+            let search_col = if row == start_row { start_col } else { 0 };
+            if let Some(col) = self.match_on_row(row, search_col..self.lines[row].len(), &pattern, options, false) {
+                return found(row, col, false);
+            }
+        }
+        if !options.wrap {
+            return None;
+        }
+
+        // Wrap around: search from the beginning to matches starting before the start
+        // position, including one the start position is inside
+        for row in 0..=start_row.min(self.lines.len().saturating_sub(1)) {
+            let end_col = if row == start_row { start_col } else { self.lines[row].len() };
+            if let Some(col) = self.match_on_row(row, 0..end_col, &pattern, options, false) {
+                return found(row, col, true);
+            }
+        }
+
+        None
+    }
+
+    // Find the match after the one at (row, col), starting one character past it so
+    // repeated find-next always moves on. With wrapping, a single match in the buffer
+    // finds itself again.
+    pub fn search_after(
+        &self,
+        query: &str,
+        row: usize,
+        col: usize,
+        options: SearchOptions,
+    ) -> Option<(usize, usize, bool)> {
+        self.lines.get(row)?;
+        // A match starting at the line break moves on to the next line
+        if col + 1 > self.line_len(row) && row + 1 < self.lines.len() {
+            return self.search(query, row + 1, 0, options);
+        }
+        self.search(query, row, col + 1, options)
+    }
+
+    // The first (or with `last`, the last) match of `pattern` starting on `row` within
+    // `range`. The end of the line counts as within a range that reaches it, for a query
+    // starting with a line break.
+    fn match_on_row(
+        &self,
+        row: usize,
+        range: Range<usize>,
+        pattern: &SearchPattern,
+        options: SearchOptions,
+        last: bool,
+    ) -> Option<usize> {
+        let line = &self.lines[row];
+        let query = match pattern {
+            SearchPattern::Literal(query) if query.contains('\n') => query,
+            _ => {
+                let mut matches = matches_in_line(line, range, pattern, options).map(|span| span.start);
+                return if last { matches.last() } else { matches.next() };
+            }
+        };
+        let end = if range.end == line.len() { range.end + 1 } else { range.end };
+        self.multiline_match(row, query, options).filter(|col| (range.start..end).contains(col))
+    }
+
+    // Column on `row` where `query`, which spans lines, matches: the end of this line,
+    // any whole lines, then the start of a later one. There's at most one such column.
+    fn multiline_match(&self, row: usize, query: &str, options: SearchOptions) -> Option<usize> {
+        let parts: Vec<&str> = query.split('\n').collect();
+        let lines = self.lines.get(row..row + parts.len())?;
+        let last = parts.len() - 1;
+        let matched = lines[0].ends_with(parts[0])
+            && parts[1..last].iter().zip(&lines[1..last]).all(|(part, line)| line == part)
+            && lines[last].starts_with(parts[last]);
+        if !matched {
+            return None;
+        }
+        let col = lines[0].len() - parts[0].len();
+        let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+        let bounded = !options.whole_word
+            || (!lines[0][..col].chars().next_back().is_some_and(is_word_char)
+                && !lines[last][parts[last].len()..].chars().next().is_some_and(is_word_char));
+        bounded.then_some(col)
+    }
+
+    // Byte ranges of `row` covered by matches of `pattern`, in order, for drawing. These are
+    // the matches search lands on, overlapping ones and the whole-word filter included.
+    // Matches spanning line breaks cover the end of their first row, whole rows and the
+    // start of the last.
+    pub fn match_spans(&self, row: usize, pattern: &SearchPattern, options: SearchOptions) -> Vec<Range<usize>> {
+        let Some(line) = self.lines.get(row) else {
+            return Vec::new();
+        };
+        let query = match pattern {
+            SearchPattern::Literal("") => return Vec::new(),
+            SearchPattern::Literal(query) if query.contains('\n') => query,
+            _ => {
+                return matches_in_line(line, 0..line.len(), pattern, options).filter(|span| !span.is_empty()).collect();
+            }
+        };
+        let parts: Vec<&str> = query.split('\n').collect();
+        let mut spans: Vec<Range<usize>> = (0..parts.len().min(row + 1))
+            .filter(|&offset| self.multiline_match(row - offset, query, options).is_some())
+            .map(|offset| match offset {
+                0 => line.len() - parts[0].len()..line.len(),
+                _ if offset == parts.len() - 1 => 0..parts[offset].len(),
+                _ => 0..line.len(),
+            })
+            .filter(|span| !span.is_empty())
+            .collect();
+        spans.sort_by_key(|span| span.start);
+        spans
+    }
+
+    // Find the last match starting before the start position, so repeating from a match
+    // moves on to the one before it. `wrapped` means the search continued from the bottom.
+    pub fn search_backward(
+        &self,
+        query: &str,
+        start_row: usize,
+        start_col: usize,
+        options: SearchOptions,
+    ) -> Option<(usize, usize, bool)> {
+        if query.is_empty() || self.lines.is_empty() {
+            return None;
+        }
+        let pattern = SearchPattern::new(query, options.kind).ok()?;
+        let start_row = start_row.min(self.lines.len() - 1);
+        let start_col = byte_index(&self.lines[start_row], start_col);
+        let found = |row: usize, col: usize, wrapped: bool| Some((row, char_index(&self.lines[row], col), wrapped));
+
+        for row in (0..=start_row).rev() {
+            let end_col = if row == start_row { start_col } else { self.lines[row].len() };
+            if let Some(col) = self.match_on_row(row, 0..end_col, &pattern, options, true) {
+                return found(row, col, false);
+            }
+        }
+        if !options.wrap {
+            return None;
+        }
+
+        // Wrap around: search up from the end to matches starting at or after the start
+        for row in (start_row..self.lines.len()).rev() {
+            let search_col = if row == start_row { start_col } else { 0 };
+            if let Some(col) = self.match_on_row(row, search_col..self.lines[row].len(), &pattern, options, true) {
+                return found(row, col, true);
+            }
+        }
+
+        None
+    }
+}
+
+// Position just after `text` when it is inserted at (row, col)
+pub fn end_of_text(row: usize, col: usize, text: &str) -> (usize, usize) {
+    match text.rsplit_once('\n') {
+        Some((before, last)) => (row + before.matches('\n').count() + 1, last.chars().count()),
+        None => (row, col + text.chars().count()),
+    }
+}
+
+// Byte offset in `line` of character column `col`, or the line's length for a column
+// at or past its end. Strings are indexed by byte, so this is where columns turn into
+// offsets for slicing, inserting or removing.
+pub fn byte_index(line: &str, col: usize) -> usize {
+    line.char_indices().nth(col).map_or(line.len(), |(idx, _)| idx)
+}
+
+// Character column of byte offset `idx` in `line`
+pub fn char_index(line: &str, idx: usize) -> usize {
+    line[..idx.min(line.len())].chars().count()
+}
+
+#[derive(Clone, Copy)]
+pub struct SearchOptions {
+    // Continue from the top of the buffer after reaching the end
+    pub wrap: bool,
+    // Only match where the query isn't part of a longer word
+    pub whole_word: bool,
+    pub kind: SearchKind,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SearchKind {
+    // The query is plain text
+    Literal,
+    // The query is a regular expression, matched within single lines
+    Regex,
+}
+
+// A search query ready for matching, so a regex is compiled once rather than per line
+pub enum SearchPattern<'a> {
+    Literal(&'a str),
+    Regex(Regex),
+}
+
+impl<'a> SearchPattern<'a> {
+    pub fn new(query: &'a str, kind: SearchKind) -> Result<Self, regex::Error> {
+        match kind {
+            SearchKind::Literal => Ok(SearchPattern::Literal(query)),
+            SearchKind::Regex => Regex::new(query).map(SearchPattern::Regex),
+        }
+    }
+
+    // The first match in `line` starting at or after byte `start`. A regex still sees the
+    // text before `start`, so anchors and word boundaries work there.
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        match self {
+            SearchPattern::Literal(query) => {
+                line[start..].find(query).map(|col| start + col..start + col + query.len())
+            }
+            SearchPattern::Regex(regex) => regex.find_at(line, start).map(|found| found.range()),
+        }
+    }
+}
+
+// Byte ranges of the matches of `pattern` in `line` that start within `range`, in order.
+// Each search resumes one character after the last match's start, so matches overlapping
+// it (`aa` at 1 in `aaaa`) are found too.
+fn matches_in_line<'a>(
+    line: &'a str,
+    range: Range<usize>,
+    pattern: &'a SearchPattern,
+    options: SearchOptions,
+) -> impl Iterator<Item = Range<usize>> + 'a {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+    let mut start = range.start;
+    std::iter::from_fn(move || {
+        if start > line.len() {
+            return None;
+        }
+        let span = pattern.find_at(line, start).filter(|span| span.start < range.end)?;
+        start = span.start + line[span.start..].chars().next().map_or(1, char::len_utf8);
+        Some(span)
+    })
+    .filter(move |span| {
+        !options.whole_word
+            || (!line[..span.start].chars().next_back().is_some_and(is_word_char)
+                && !line[span.end..].chars().next().is_some_and(is_word_char))
+    })
+}
+
+impl fmt::Display for TextBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.lines.join("\n"))
+    }
+}
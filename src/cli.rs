@@ -0,0 +1,65 @@
+// Command-line arguments: `[options] [+LINE] file...`
+#[derive(Default)]
+pub struct Args {
+    // Files to open with the 1-based line from a preceding `+LINE`
+    pub files: Vec<(String, Option<usize>)>,
+    pub no_line_numbers: bool,
+    pub read_only: bool,
+    pub tab_width: Option<usize>,
+    pub language: Option<String>,
+    // File of commands to run on the first buffer before the editor starts
+    pub commands: Option<String>,
+    // Run the commands and save instead of starting the interface
+    pub batch: bool,
+    pub help: bool,
+}
+
+pub const USAGE: &str = "\
+Usage: axis [options] [+LINE] [file...]
+
+Options:
+  +LINE                Start on LINE in the file that follows
+  -R, --read-only      Open files without allowing edits
+  --no-line-numbers    Hide the line number gutter
+  --tab-width N        Indentation width (overrides detection)
+  --lang NAME          Highlight as NAME (rust, python, javascript, java, c, bash, markdown, html, plain)
+  --commands FILE      Run the commands in FILE (goto N, search TEXT, replace FROM TO,
+                       replace-regex PATTERN TO, save)
+  --batch              Save the result of --commands and exit without opening the
+                       interface; piped input is written to standard output
+  -h, --help           Show this help
+";
+
+pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    let mut line = None;
+    let mut only_files = false;
+    while let Some(arg) = args.next() {
+        if only_files || !arg.starts_with(['-', '+']) || arg == "-" {
+            parsed.files.push((arg, line.take()));
+            continue;
+        }
+        if let Some(n) = arg.strip_prefix('+') {
+            line = Some(n.parse().map_err(|_| format!("invalid line number: {}", arg))?);
+            continue;
+        }
+        match arg.as_str() {
+            "--" => only_files = true,
+            "-R" | "--read-only" => parsed.read_only = true,
+            "--no-line-numbers" => parsed.no_line_numbers = true,
+            "--batch" => parsed.batch = true,
+            "-h" | "--help" => parsed.help = true,
+            "--tab-width" => {
+                let value = args.next().ok_or("--tab-width needs a value")?;
+                match value.parse::<usize>() {
+                    Ok(width) if (1..=16).contains(&width) => parsed.tab_width = Some(width),
+                    _ => return Err(format!("invalid tab width: {}", value)),
+                }
+            }
+            "--lang" => parsed.language = Some(args.next().ok_or("--lang needs a value")?),
+            "--commands" => parsed.commands = Some(args.next().ok_or("--commands needs a file")?),
+            _ => return Err(format!("unknown option: {}", arg)),
+        }
+    }
+    Ok(parsed)
+}
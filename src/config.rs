@@ -0,0 +1,540 @@
+use crossterm::style::Color;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const CONFIG_FILE: &str = "config.toml";
+
+// What to do with the final newline of a file when it is opened
+#[derive(Clone, Copy, PartialEq)]
+pub enum TrailingNewline {
+    Preserve,
+    Always,
+    Never,
+}
+
+// Whether the status bar shows the time, and on which clock
+#[derive(Clone, Copy, PartialEq)]
+pub enum Clock {
+    Off,
+    TwentyFourHour,
+    TwelveHour,
+}
+
+// How far PageUp/PageDown move: a share of the visible lines, below 1 to keep some
+// lines of context on screen, or a fixed number of lines
+#[derive(Clone, Copy, PartialEq)]
+pub enum PageScroll {
+    Fraction(f32),
+    Lines(usize),
+}
+
+// Which side of the gutter line numbers line up on
+#[derive(Clone, Copy, PartialEq)]
+pub enum GutterAlign {
+    Left,
+    Right,
+}
+
+#[derive(Clone)]
+pub struct Config {
+    pub split_ratio: f32,
+    // Indentation used when a file's own style can't be detected
+    pub tab_width: usize,
+    pub use_spaces: bool,
+    // Also warn about indentation mixing tabs and spaces on save, not just on open
+    pub indent_check_on_save: bool,
+    // Insert the closing bracket/quote when typing an opener
+    pub auto_close: bool,
+    // Left at the start of a line and Right at its end continue onto the neighbouring line
+    pub wrap_cursor: bool,
+    pub trailing_newline: TrailingNewline,
+    // Write back a byte order mark that a file was opened with
+    pub keep_bom: bool,
+    // Ask before opening a file bigger than this many megabytes; 0 never asks
+    pub large_file_mb: u64,
+    // Save the open files and split on quit, and reopen them when started without files
+    pub restore_last_session: bool,
+    // 1-based columns marked with a ruler; empty turns rulers off
+    pub rulers: Vec<usize>,
+    // Highlight text past the line-length limit (`max_line_width`, else the first ruler)
+    pub highlight_long_lines: bool,
+    pub max_line_width: Option<usize>,
+    // Counterpart extensions for the alternate-file toggle, e.g. "c" -> ["h"]
+    pub alternates: Vec<(String, Vec<String>)>,
+    // Also pair src/foo.rs with tests/foo.rs
+    pub rust_test_alternate: bool,
+    // Command used to run a file, by language name; `{file}` is replaced with its path,
+    // quoted for the shell
+    pub runners: Vec<(String, String)>,
+    // Shell used for commands, e.g. "bash -c"; defaults to sh -c (cmd /C on Windows)
+    pub shell: Option<String>,
+    // Continue searching from the top after the end of the buffer
+    pub search_wrap: bool,
+    // `page_scroll = 0.9` for a fraction of the view, `page_scroll = 20` for lines
+    pub page_scroll: PageScroll,
+    // (language, trigger, body) expanded by Alt-X. In a body `\t` is one indent
+    // level and `$0` marks where the cursor goes.
+    pub snippets: Vec<(String, String, String)>,
+    // Line-number gutter: blank columns before the numbers, the text after them, and
+    // colours for the active pane and for other panes and `~` rows
+    pub gutter_padding: usize,
+    pub gutter_separator: String,
+    pub gutter_align: GutterAlign,
+    pub gutter_color: Color,
+    pub gutter_inactive_color: Color,
+    // Split dividers are drawn in this colour against the focused pane, and the other
+    // pane's text is dimmed unless `dim_inactive_pane` is off
+    pub divider_color: Color,
+    pub dim_inactive_pane: bool,
+    pub show_line_numbers: bool,
+    // Draw brackets without a partner in red
+    pub bracket_check: bool,
+    // Text column width in zen mode, which hides line numbers and centers the text
+    pub zen_width: usize,
+    // Draw tab-separated text with elastic tabstops, lining up each column of cells
+    // across neighbouring lines. Only the drawing changes: the file keeps its tabs, and
+    // the cursor steps over a tab in one move however wide it is drawn.
+    pub elastic_tabstops: bool,
+    pub clock: Clock,
+    // Settings from `[lang.NAME]` sections, applied when a file of that language opens
+    pub languages: Vec<(String, LanguageSettings)>,
+    // Regex highlighting for languages the editor doesn't know, from sections like
+    //
+    //     [highlight.ini]
+    //     extensions = "ini, cfg"
+    //     comment = "[;#].*"
+    //     keyword = "^\[.*\]"
+    //     type = "^[\w.]+"
+    //
+    // Each other key is a token type and its value a pattern, taken as written
+    // (backslashes aren't escapes). Where matches overlap the earlier rule wins.
+    pub highlight_rules: Vec<HighlightRules>,
+}
+
+// One `[highlight.NAME]` section
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct HighlightRules {
+    pub name: String,
+    pub extensions: Vec<String>,
+    // Built-in language whose editing rules apply, e.g. "bash" for `#` comments
+    pub language: Option<String>,
+    // (token type, pattern) in the order given
+    pub rules: Vec<(String, String)>,
+}
+
+// Per-language overrides; unset fields fall back to the global settings
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct LanguageSettings {
+    pub show_line_numbers: Option<bool>,
+    pub tab_width: Option<usize>,
+    pub use_spaces: Option<bool>,
+    // Replaces the language's built-in auto-close pairs, e.g. `"()[]{}"`
+    pub auto_close_pairs: Option<Vec<(char, char)>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            split_ratio: 0.5,
+            tab_width: 4,
+            use_spaces: true,
+            indent_check_on_save: false,
+            auto_close: true,
+            wrap_cursor: true,
+            trailing_newline: TrailingNewline::Preserve,
+            keep_bom: true,
+            large_file_mb: 100,
+            restore_last_session: false,
+            rulers: Vec::new(),
+            highlight_long_lines: false,
+            max_line_width: None,
+            alternates: [
+                ("c", "h"),
+                ("h", "c, cpp, cc"),
+                ("cpp", "hpp, h"),
+                ("hpp", "cpp"),
+                ("cc", "hh, h"),
+                ("hh", "cc"),
+            ]
+            .iter()
+            .map(|(ext, partners)| (ext.to_string(), parse_str_list(partners)))
+            .collect(),
+            rust_test_alternate: true,
+            runners: [
+                ("python", if cfg!(windows) { "python {file}" } else { "python3 {file}" }),
+                ("bash", "bash {file}"),
+                ("javascript", "node {file}"),
+                ("java", "java {file}"),
+                ("rust", "cargo run"),
+            ]
+            .iter()
+            .map(|(lang, command)| (lang.to_string(), command.to_string()))
+            .collect(),
+            shell: None,
+            page_scroll: PageScroll::Fraction(1.0),
+            search_wrap: true,
+            snippets: [
+                ("rust", "fn", "fn $0() {\n\t\n}"),
+                ("rust", "for", "for $0 {\n\t\n}"),
+                ("rust", "if", "if $0 {\n\t\n}"),
+                ("rust", "match", "match $0 {\n\t_ => {}\n}"),
+                ("rust", "test", "#[test]\nfn $0() {\n\t\n}"),
+                ("python", "def", "def $0():\n\tpass"),
+                ("python", "for", "for $0:\n\tpass"),
+                ("python", "if", "if $0:\n\tpass"),
+                ("python", "class", "class $0:\n\tdef __init__(self):\n\t\tpass"),
+                ("javascript", "function", "function $0() {\n\t\n}"),
+                ("javascript", "for", "for (let i = 0; i < $0; i++) {\n\t\n}"),
+                ("javascript", "if", "if ($0) {\n\t\n}"),
+                ("c", "for", "for (int i = 0; i < $0; i++) {\n\t\n}"),
+                ("c", "if", "if ($0) {\n\t\n}"),
+                ("c", "main", "int main(void) {\n\t$0\n\treturn 0;\n}"),
+                ("java", "for", "for (int i = 0; i < $0; i++) {\n\t\n}"),
+                ("java", "if", "if ($0) {\n\t\n}"),
+                ("java", "main", "public static void main(String[] args) {\n\t$0\n}"),
+                ("bash", "if", "if [ $0 ]; then\n\t\nfi"),
+                ("bash", "for", "for $0; do\n\t\ndone"),
+            ]
+            .iter()
+            .map(|(lang, trigger, body)| (lang.to_string(), trigger.to_string(), body.to_string()))
+            .collect(),
+            gutter_padding: 0,
+            gutter_separator: " ".to_string(),
+            gutter_align: GutterAlign::Right,
+            gutter_color: Color::Yellow,
+            gutter_inactive_color: Color::DarkGrey,
+            divider_color: Color::Cyan,
+            dim_inactive_pane: true,
+            show_line_numbers: true,
+            bracket_check: true,
+            zen_width: 80,
+            elastic_tabstops: false,
+            clock: Clock::Off,
+            languages: Vec::new(),
+            highlight_rules: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Self {
+        let content = config_dir()
+            .and_then(|dir| fs::read_to_string(dir.join(CONFIG_FILE)).ok())
+            .unwrap_or_default();
+        Self::from_content(&content)
+    }
+
+    // The defaults with the settings in `content` (the config file's text) applied
+    pub fn from_content(content: &str) -> Self {
+        let mut config = Self::default();
+        for (section, key, value) in parse(content) {
+            if section == "alternates" {
+                config.alternates.retain(|(ext, _)| *ext != key);
+                config.alternates.push((key, parse_str_list(&value)));
+                continue;
+            }
+            if let Some(language) = section.strip_prefix("snippets.") {
+                let language = language.trim().to_lowercase();
+                config.snippets.retain(|(lang, trigger, _)| *lang != language || *trigger != key);
+                config.snippets.push((language, key, unescape(&value)));
+                continue;
+            }
+            if let Some(language) = section.strip_prefix("lang.") {
+                let language = language.trim().to_lowercase();
+                let index = match config.languages.iter().position(|(lang, _)| *lang == language) {
+                    Some(index) => index,
+                    None => {
+                        config.languages.push((language, LanguageSettings::default()));
+                        config.languages.len() - 1
+                    }
+                };
+                let settings = &mut config.languages[index].1;
+                match key.as_str() {
+                    "show_line_numbers" => settings.show_line_numbers = Some(value == "true"),
+                    "tab_width" => settings.tab_width = value.parse::<usize>().ok().map(|w| w.clamp(1, 16)),
+                    "use_spaces" => settings.use_spaces = Some(value == "true"),
+                    "auto_close_pairs" => settings.auto_close_pairs = Some(parse_pairs(&value)),
+                    _ => {}
+                }
+                continue;
+            }
+            if let Some(name) = section.strip_prefix("highlight.") {
+                let name = name.trim().to_lowercase();
+                let index = match config.highlight_rules.iter().position(|rules| rules.name == name) {
+                    Some(index) => index,
+                    None => {
+                        config.highlight_rules.push(HighlightRules { name, ..HighlightRules::default() });
+                        config.highlight_rules.len() - 1
+                    }
+                };
+                let rules = &mut config.highlight_rules[index];
+                match key.as_str() {
+                    "extensions" => rules.extensions = parse_str_list(&value),
+                    "language" => rules.language = Some(value),
+                    _ => rules.rules.push((key, value)),
+                }
+                continue;
+            }
+            if section == "runners" {
+                config.runners.retain(|(lang, _)| *lang != key);
+                config.runners.push((key, value));
+                continue;
+            }
+            if !section.is_empty() {
+                continue;
+            }
+            match key.as_str() {
+                "split_ratio" => {
+                    if let Ok(ratio) = value.parse() {
+                        config.split_ratio = ratio;
+                    }
+                }
+                "tab_width" => {
+                    if let Ok(width) = value.parse::<usize>() {
+                        config.tab_width = width.clamp(1, 16);
+                    }
+                }
+                "use_spaces" => config.use_spaces = value == "true",
+                "indent_check_on_save" => config.indent_check_on_save = value == "true",
+                "auto_close" => config.auto_close = value == "true",
+                "wrap_cursor" => config.wrap_cursor = value == "true",
+                "show_line_numbers" => config.show_line_numbers = value == "true",
+                "bracket_check" => config.bracket_check = value == "true",
+                "elastic_tabstops" => config.elastic_tabstops = value == "true",
+                "zen_width" => {
+                    if let Ok(width) = value.parse::<usize>() {
+                        config.zen_width = width.max(20);
+                    }
+                }
+                "trailing_newline" => match value.as_str() {
+                    "preserve" => config.trailing_newline = TrailingNewline::Preserve,
+                    "always" => config.trailing_newline = TrailingNewline::Always,
+                    "never" => config.trailing_newline = TrailingNewline::Never,
+                    _ => {}
+                },
+                "keep_bom" => config.keep_bom = value == "true",
+                "large_file_mb" => {
+                    if let Ok(mb) = value.parse() {
+                        config.large_file_mb = mb;
+                    }
+                }
+                "restore_last_session" => config.restore_last_session = value == "true",
+                "rulers" => config.rulers = parse_list(&value),
+                "highlight_long_lines" => config.highlight_long_lines = value == "true",
+                "shell" => config.shell = Some(value).filter(|v| !v.trim().is_empty()),
+                "page_scroll" => {
+                    if let Ok(lines) = value.parse::<usize>() {
+                        config.page_scroll = PageScroll::Lines(lines.max(1));
+                    } else if let Ok(fraction) = value.parse::<f32>()
+                        && fraction > 0.0
+                        && fraction <= 1.0
+                    {
+                        config.page_scroll = PageScroll::Fraction(fraction);
+                    }
+                }
+                "search_wrap" => config.search_wrap = value == "true",
+                "rust_test_alternate" => config.rust_test_alternate = value == "true",
+                "max_line_width" => config.max_line_width = value.parse().ok().filter(|&n| n > 0),
+                "gutter_padding" => {
+                    if let Ok(padding) = value.parse::<usize>() {
+                        config.gutter_padding = padding.min(8);
+                    }
+                }
+                "gutter_separator" => config.gutter_separator = unescape(&value),
+                "clock" => match value.as_str() {
+                    "off" => config.clock = Clock::Off,
+                    "24h" => config.clock = Clock::TwentyFourHour,
+                    "12h" => config.clock = Clock::TwelveHour,
+                    _ => {}
+                },
+                "gutter_align" => match value.as_str() {
+                    "left" => config.gutter_align = GutterAlign::Left,
+                    "right" => config.gutter_align = GutterAlign::Right,
+                    _ => {}
+                },
+                // Color names as crossterm spells them: "yellow", "dark_grey", ...
+                "gutter_color" => {
+                    if let Ok(color) = Color::try_from(value.as_str()) {
+                        config.gutter_color = color;
+                    }
+                }
+                "gutter_inactive_color" => {
+                    if let Ok(color) = Color::try_from(value.as_str()) {
+                        config.gutter_inactive_color = color;
+                    }
+                }
+                "divider_color" => {
+                    if let Ok(color) = Color::try_from(value.as_str()) {
+                        config.divider_color = color;
+                    }
+                }
+                "dim_inactive_pane" => config.dim_inactive_pane = value == "true",
+                _ => {}
+            }
+        }
+        config
+    }
+
+    pub fn alternate_extensions(&self, ext: &str) -> &[String] {
+        self.alternates
+            .iter()
+            .find(|(e, _)| e.eq_ignore_ascii_case(ext))
+            .map(|(_, partners)| partners.as_slice())
+            .unwrap_or(&[])
+    }
+
+    pub fn runner(&self, language: &str) -> Option<&str> {
+        self.runners
+            .iter()
+            .find(|(lang, _)| lang == language)
+            .map(|(_, command)| command.as_str())
+    }
+
+    // Overrides for `language` (a `Language::name()`), empty if it has no section
+    pub fn language_settings(&self, language: &str) -> LanguageSettings {
+        self.languages
+            .iter()
+            .find(|(lang, _)| lang == language)
+            .map(|(_, settings)| settings.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn snippet(&self, language: &str, trigger: &str) -> Option<&str> {
+        self.snippets
+            .iter()
+            .find(|(lang, t, _)| lang == language && t == trigger)
+            .map(|(_, _, body)| body.as_str())
+    }
+
+    // Lines PageUp/PageDown move in a view of `visible_lines`, at least one
+    pub fn page_lines(&self, visible_lines: usize) -> usize {
+        let lines = match self.page_scroll {
+            PageScroll::Fraction(fraction) => (visible_lines as f32 * fraction).round() as usize,
+            PageScroll::Lines(lines) => lines,
+        };
+        lines.max(1)
+    }
+
+    // Column after which text counts as too long, if the warning is on
+    pub fn line_width_limit(&self) -> Option<usize> {
+        if !self.highlight_long_lines {
+            return None;
+        }
+        self.max_line_width.or_else(|| self.rulers.iter().copied().min())
+    }
+}
+
+// Per-user directory for editor settings and data files
+pub fn config_dir() -> Option<PathBuf> {
+    if cfg!(windows)
+        && let Some(appdata) = std::env::var_os("APPDATA")
+    {
+        return Some(PathBuf::from(appdata).join("axis"));
+    }
+    if let Some(xdg) = std::env::var_os("XDG_CONFIG_HOME") {
+        return Some(PathBuf::from(xdg).join("axis"));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("axis"))
+}
+
+// Minimal TOML-style parser: `[section]` headers, `key = value` pairs and `#` comments.
+// Returns (section, key, value) with surrounding quotes stripped from the value.
+fn parse(content: &str) -> Vec<(String, String, String)> {
+    let mut entries = Vec::new();
+    let mut section = String::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.trim().to_string();
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            entries.push((section.clone(), key.trim().to_string(), value.to_string()));
+        }
+    }
+    entries
+}
+
+// Characters taken two at a time as (open, close); an odd one out is dropped
+fn parse_pairs(value: &str) -> Vec<(char, char)> {
+    let chars: Vec<char> = value.chars().collect();
+    chars.chunks_exact(2).map(|pair| (pair[0], pair[1])).collect()
+}
+
+// `[80, 100]` or `80, 100` as numbers; unparsable entries are skipped
+fn parse_list(value: &str) -> Vec<usize> {
+    value
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(',')
+        .filter_map(|item| item.trim().parse().ok())
+        .filter(|&n| n > 0)
+        .collect()
+}
+
+// `"h, cpp"` or `["h", "cpp"]` as a list of words
+fn parse_str_list(value: &str) -> Vec<String> {
+    value
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(',')
+        .map(|item| item.trim().trim_matches('"').to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+// `\n`, `\t` and `\\` escapes in a quoted value
+fn unescape(value: &str) -> String {
+    let mut result = String::new();
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            result.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some(other) => result.push(other),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+// Write a top-level `key = value` into the config file, replacing an existing entry
+// and leaving the rest of the file (comments, sections) untouched.
+pub fn persist(key: &str, value: &str) -> io::Result<()> {
+    let dir = config_dir().ok_or_else(|| io::Error::other("no config directory"))?;
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(CONFIG_FILE);
+    let content = fs::read_to_string(&path).unwrap_or_default();
+
+    let entry = format!("{} = {}", key, value);
+    let mut lines: Vec<String> = content.lines().map(|l| l.to_string()).collect();
+    let top_level_end = lines
+        .iter()
+        .position(|l| l.trim_start().starts_with('['))
+        .unwrap_or(lines.len());
+    let existing = lines[..top_level_end].iter().position(|l| {
+        l.split_once('=')
+            .is_some_and(|(k, _)| k.trim() == key && !l.trim_start().starts_with('#'))
+    });
+    match existing {
+        Some(idx) => lines[idx] = entry,
+        None => lines.insert(top_level_end, entry),
+    }
+
+    let mut output = lines.join("\n");
+    output.push('\n');
+    fs::write(path, output)
+}
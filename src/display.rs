@@ -0,0 +1,314 @@
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use unicode_width::UnicodeWidthChar;
+
+// Clip a line to `width` columns on a char boundary, leaving room for the
+// truncation marker when it doesn't fit. Returns the visible part and whether it was cut.
+pub fn clip_line(line: &str, width: usize) -> (&str, bool) {
+    if display_width(line) <= width {
+        return (line, false);
+    }
+    let mut used = 0;
+    for (idx, ch) in line.char_indices() {
+        used += char_width(ch);
+        if used > width.saturating_sub(1) {
+            return (&line[..idx], true);
+        }
+    }
+    (line, true)
+}
+
+// Byte index in `line` where drawing starts when its first `columns` screen columns are
+// scrolled out of view. A character the edge falls inside is skipped whole.
+pub fn skip_columns(line: &str, columns: usize) -> usize {
+    let mut used = 0;
+    for (idx, ch) in line.char_indices() {
+        if used >= columns {
+            return idx;
+        }
+        used += char_width(ch);
+    }
+    line.len()
+}
+
+// Caret notation for a control character (`^M`, `^[`, `^?`), or None for anything that
+// can be printed as is. Tabs are left to the terminal.
+pub fn control_notation(ch: char) -> Option<String> {
+    match ch {
+        '\t' => None,
+        '\0'..='\x1f' | '\x7f' => Some(format!("^{}", (ch as u8 ^ 0x40) as char)),
+        // C1 controls have no caret form
+        '\u{80}'..='\u{9f}' => Some(format!("<{:02x}>", ch as u32)),
+        _ => None,
+    }
+}
+
+// Screen columns a character takes, counting control characters by their notation and
+// wide characters such as emoji and CJK as two
+pub fn char_width(ch: char) -> usize {
+    match ch {
+        '\t' => 1,
+        '\0'..='\x1f' | '\x7f' => 2,
+        '\u{80}'..='\u{9f}' => 4,
+        _ => UnicodeWidthChar::width(ch).unwrap_or(1),
+    }
+}
+
+// Names of characters that are invisible or easily mistaken for more common ones
+const CHAR_NAMES: &[(char, &str)] = &[
+    ('\0', "NULL"),
+    ('\t', "CHARACTER TABULATION"),
+    ('\r', "CARRIAGE RETURN"),
+    ('\x1b', "ESCAPE"),
+    (' ', "SPACE"),
+    ('\u{a0}', "NO-BREAK SPACE"),
+    ('\u{ad}', "SOFT HYPHEN"),
+    ('\u{37e}', "GREEK QUESTION MARK"),
+    ('\u{430}', "CYRILLIC SMALL LETTER A"),
+    ('\u{435}', "CYRILLIC SMALL LETTER IE"),
+    ('\u{43e}', "CYRILLIC SMALL LETTER O"),
+    ('\u{440}', "CYRILLIC SMALL LETTER ER"),
+    ('\u{441}', "CYRILLIC SMALL LETTER ES"),
+    ('\u{2002}', "EN SPACE"),
+    ('\u{2003}', "EM SPACE"),
+    ('\u{2009}', "THIN SPACE"),
+    ('\u{200a}', "HAIR SPACE"),
+    ('\u{200b}', "ZERO WIDTH SPACE"),
+    ('\u{200c}', "ZERO WIDTH NON-JOINER"),
+    ('\u{200d}', "ZERO WIDTH JOINER"),
+    ('\u{200e}', "LEFT-TO-RIGHT MARK"),
+    ('\u{200f}', "RIGHT-TO-LEFT MARK"),
+    ('\u{2010}', "HYPHEN"),
+    ('\u{2011}', "NON-BREAKING HYPHEN"),
+    ('\u{2013}', "EN DASH"),
+    ('\u{2014}', "EM DASH"),
+    ('\u{2018}', "LEFT SINGLE QUOTATION MARK"),
+    ('\u{2019}', "RIGHT SINGLE QUOTATION MARK"),
+    ('\u{201c}', "LEFT DOUBLE QUOTATION MARK"),
+    ('\u{201d}', "RIGHT DOUBLE QUOTATION MARK"),
+    ('\u{2028}', "LINE SEPARATOR"),
+    ('\u{202e}', "RIGHT-TO-LEFT OVERRIDE"),
+    ('\u{202f}', "NARROW NO-BREAK SPACE"),
+    ('\u{2060}', "WORD JOINER"),
+    ('\u{2212}', "MINUS SIGN"),
+    ('\u{3000}', "IDEOGRAPHIC SPACE"),
+    ('\u{feff}', "ZERO WIDTH NO-BREAK SPACE"),
+    ('\u{fffd}', "REPLACEMENT CHARACTER"),
+];
+
+// What's at byte `col` of `line`: the code point, its name if it's one worth telling
+// apart, the byte offset and length, and how many columns it takes on screen
+pub fn describe_char(line: &str, col: usize) -> String {
+    let Some(ch) = line.get(col..).and_then(|rest| rest.chars().next()) else {
+        return format!("End of line (byte {})", col);
+    };
+    let name = CHAR_NAMES.iter().find(|(c, _)| *c == ch).map(|(_, name)| format!(" {}", name));
+    let width = match ch {
+        '\t' => "to the next tab stop".to_string(),
+        _ if control_notation(ch).is_some() => format!("{} as {}", char_width(ch), printable(&ch.to_string())),
+        _ => UnicodeWidthChar::width(ch).unwrap_or(0).to_string(),
+    };
+    format!(
+        "U+{:04X}{} at byte {} ({} byte(s)), width {}",
+        ch as u32,
+        name.unwrap_or_default(),
+        col,
+        ch.len_utf8(),
+        width
+    )
+}
+
+pub fn display_width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
+}
+
+// `text` with control characters spelled out, for plain text such as overlay lines
+pub fn printable(text: &str) -> String {
+    text.chars()
+        .map(|ch| control_notation(ch).unwrap_or_else(|| ch.to_string()))
+        .collect()
+}
+
+// Elastic tabstops: every tab ends a cell, and the cells in the same column of
+// consecutive lines are padded to one width so they line up. Returns, for each line,
+// the number of spaces each of its tabs is drawn as. A column is at least `min_width`
+// wide, which keeps indentation tabs readable, and cells are at least 2 spaces apart.
+pub fn elastic_tab_padding(lines: &[&str], min_width: usize) -> Vec<Vec<usize>> {
+    let cells: Vec<Vec<usize>> = lines
+        .iter()
+        .map(|line| {
+            let mut cells: Vec<usize> = line.split('\t').map(display_width).collect();
+            // The text after the last tab isn't a cell
+            cells.pop();
+            cells
+        })
+        .collect();
+    let mut padding: Vec<Vec<usize>> = cells.iter().map(|row| vec![0; row.len()]).collect();
+    let columns = cells.iter().map(Vec::len).max().unwrap_or(0);
+    for col in 0..columns {
+        // A column's block is the run of lines that all have a cell in it
+        let mut row = 0;
+        while row < cells.len() {
+            let start = row;
+            while row < cells.len() && cells[row].len() > col {
+                row += 1;
+            }
+            if row == start {
+                row += 1;
+                continue;
+            }
+            let width = cells[start..row].iter().map(|c| c[col] + 2).max().unwrap_or(0).max(min_width);
+            for (pad, cell) in padding[start..row].iter_mut().zip(&cells[start..row]) {
+                pad[col] = width - cell[col];
+            }
+        }
+    }
+    padding
+}
+
+// `line` with its tabs replaced by `padding` spaces each
+pub fn expand_tabs<'a>(line: &'a str, padding: &[usize]) -> Cow<'a, str> {
+    if padding.is_empty() {
+        return Cow::Borrowed(line);
+    }
+    let mut pads = padding.iter();
+    let mut expanded = String::with_capacity(line.len() + padding.iter().sum::<usize>());
+    for ch in line.chars() {
+        if ch == '\t'
+            && let Some(&pad) = pads.next()
+        {
+            expanded.push_str(&" ".repeat(pad));
+        } else {
+            expanded.push(ch);
+        }
+    }
+    Cow::Owned(expanded)
+}
+
+// Where byte `idx` of `line` ends up in `expand_tabs(line, padding)`
+pub fn expanded_index(line: &str, padding: &[usize], idx: usize) -> usize {
+    let before = &line[..idx.min(line.len())];
+    let extra: usize = before.match_indices('\t').zip(padding).map(|(_, &pad)| pad).sum();
+    let tabs = before.matches('\t').count().min(padding.len());
+    idx + extra - tabs
+}
+
+// Fit a path into `max_width` columns: the home directory becomes `~`, then the
+// folders between the top and the file's parent are shortened to their first letter
+// and finally collapsed into `…`. The file name and its parent are kept whole if possible.
+pub fn shorten_path(path: &Path, max_width: usize) -> String {
+    let sep = std::path::MAIN_SEPARATOR.to_string();
+    let full = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let home = std::env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" }).map(PathBuf::from);
+    let (mut parts, relative): (Vec<String>, &Path) = match home.as_ref().and_then(|h| full.strip_prefix(h).ok()) {
+        Some(rest) => (vec!["~".to_string()], rest),
+        None => (vec![String::new()], full.as_path()),
+    };
+    parts.extend(
+        relative
+            .components()
+            .filter(|c| matches!(c, std::path::Component::Normal(_)))
+            .map(|c| c.as_os_str().to_string_lossy().into_owned()),
+    );
+    let fits = |parts: &[String]| parts.join(&sep).chars().count() <= max_width;
+    if fits(&parts) {
+        return parts.join(&sep);
+    }
+
+    // parts[0] is "~" or the root; the last two are the parent folder and the file
+    let middle = 1..parts.len().saturating_sub(2);
+    for idx in middle.clone() {
+        if let Some(first) = parts[idx].chars().next() {
+            parts[idx] = first.to_string();
+        }
+        if fits(&parts) {
+            return parts.join(&sep);
+        }
+    }
+    for end in middle.clone().rev() {
+        let mut collapsed = parts[..end].to_vec();
+        collapsed.push("…".to_string());
+        collapsed.extend_from_slice(&parts[middle.end..]);
+        if fits(&collapsed) {
+            return collapsed.join(&sep);
+        }
+    }
+
+    let name = parts.last().cloned().unwrap_or_default();
+    if let [.., parent, _] = parts.as_slice() {
+        let short = format!("…{}{}{}", sep, parent, sep) + &name;
+        if short.chars().count() <= max_width {
+            return short;
+        }
+    }
+    if name.chars().count() <= max_width {
+        return name;
+    }
+    // Even the file name is too long: keep its end, where the extension is
+    let keep = max_width.saturating_sub(1);
+    let tail: String = name.chars().skip(name.chars().count() - keep).collect();
+    format!("…{}", tail)
+}
+
+// Time of day for the status bar clock, e.g. "14:05", or "2:05 PM" on a 12-hour clock
+pub fn format_clock(hour: u32, minute: u32, twelve_hour: bool) -> String {
+    if !twelve_hour {
+        return format!("{:02}:{:02}", hour, minute);
+    }
+    let suffix = if hour < 12 { "AM" } else { "PM" };
+    let hour = match hour % 12 {
+        0 => 12,
+        h => h,
+    };
+    format!("{}:{:02} {}", hour, minute, suffix)
+}
+
+// The local (hour, minute). The C library knows the time zone, so ask it.
+#[cfg(unix)]
+pub fn local_time() -> (u32, u32) {
+    // SAFETY: `time` accepts a null pointer, and `localtime_r` only writes to the
+    // zeroed `tm` it is given
+    let tm = unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&now, &mut tm);
+        tm
+    };
+    (tm.tm_hour as u32, tm.tm_min as u32)
+}
+
+// Windows keeps the local time itself. `GetLocalTime` fills a SYSTEMTIME, eight WORDs
+// from the year down to the milliseconds, with the hour and minute at 4 and 5.
+#[cfg(windows)]
+pub fn local_time() -> (u32, u32) {
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetLocalTime(time: *mut [u16; 8]);
+    }
+    let mut time = [0u16; 8];
+    // SAFETY: the array has the size and layout of the SYSTEMTIME the call writes
+    unsafe { GetLocalTime(&mut time) };
+    (u32::from(time[4]), u32::from(time[5]))
+}
+
+// Elsewhere the clock shows UTC
+#[cfg(not(any(unix, windows)))]
+pub fn local_time() -> (u32, u32) {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    ((secs / 3600 % 24) as u32, (secs / 60 % 60) as u32)
+}
+
+// Compact age for the message log, e.g. "42s" or "3m"
+pub fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 3600 {
+        format!("{}m", secs / 60)
+    } else {
+        format!("{}h", secs / 3600)
+    }
+}
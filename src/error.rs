@@ -0,0 +1,21 @@
+use std::io;
+use thiserror::Error;
+
+// Failures of editor operations. They are shown in the message line where a key is
+// handled; only terminal errors end the editor.
+#[derive(Debug, Error)]
+pub enum EditorError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    // The named file or input isn't UTF-8 text
+    #[error("{0} is not valid UTF-8")]
+    Decode(String),
+    // A FIFO, socket or device, which could block or never end when read
+    #[error("{0} is not a regular file")]
+    NotAFile(String),
+    // Input typed at a prompt that couldn't be understood
+    #[error("{0}")]
+    Parse(String),
+    #[error("{0}")]
+    Search(String),
+}
@@ -0,0 +1,188 @@
+use std::fs;
+use std::path::Path;
+
+// Walking stops after this many files so a huge tree can't stall the editor
+const MAX_FILES: usize = 20_000;
+// Folders that are never worth searching even without a .gitignore
+const SKIPPED_DIRS: &[&str] = &["target", "node_modules"];
+const MAX_RESULTS: usize = 500;
+
+// Files under the working directory with fuzzy filtering, for `EditorMode::FuzzyFind`
+pub struct FuzzyFinder {
+    files: Vec<String>,
+    // Indices into `files`, best match first
+    results: Vec<usize>,
+    pub selected: usize,
+}
+
+impl FuzzyFinder {
+    pub fn new(root: &Path) -> Self {
+        let files = list_files(root);
+        let mut finder = Self { files, results: Vec::new(), selected: 0 };
+        finder.filter("");
+        finder
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn filter(&mut self, query: &str) {
+        let mut scored: Vec<(i64, usize)> = self
+            .files
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, path)| fuzzy_score(query, path).map(|score| (score, idx)))
+            .collect();
+        // Stable sort keeps equal scores in path order
+        scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+        self.results = scored.into_iter().take(MAX_RESULTS).map(|(_, idx)| idx).collect();
+        self.selected = 0;
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.results.iter().map(|&idx| self.files[idx].clone()).collect()
+    }
+
+    pub fn move_selection(&mut self, delta: isize) {
+        let last = self.results.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    pub fn selected_path(&self) -> Option<&str> {
+        self.results.get(self.selected).map(|&idx| self.files[idx].as_str())
+    }
+}
+
+// Score `candidate` against `query` as a case-insensitive subsequence, or None if it
+// doesn't match. Consecutive letters, letters at word starts and matches within the
+// file name score higher; longer paths score slightly lower.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let name_start = candidate.rfind('/').map(|i| i + 1).unwrap_or(0);
+    let chars: Vec<(usize, char)> = candidate.char_indices().collect();
+    let mut score = 0i64;
+    let mut pos = 0;
+    let mut prev_match: Option<usize> = None;
+
+    for q in query.chars().filter(|c| !c.is_whitespace()) {
+        let q = q.to_ascii_lowercase();
+        let found = chars[pos..]
+            .iter()
+            .position(|&(_, c)| c.to_ascii_lowercase() == q)?;
+        let idx = pos + found;
+        let (byte, c) = chars[idx];
+
+        score += 1;
+        if prev_match.is_some_and(|p| p + 1 == idx) {
+            score += 8;
+        }
+        let at_word_start = idx == 0
+            || matches!(chars[idx - 1].1, '/' | '_' | '-' | '.' | ' ')
+            || (c.is_uppercase() && chars[idx - 1].1.is_lowercase());
+        if at_word_start {
+            score += 6;
+        }
+        if byte >= name_start {
+            score += 3;
+        }
+        prev_match = Some(idx);
+        pos = idx + 1;
+    }
+    Some(score * 16 - chars.len() as i64)
+}
+
+// Relative paths of the files under `root`, skipping hidden and ignored entries
+pub fn list_files(root: &Path) -> Vec<String> {
+    let ignore = Ignore::load(root);
+    let mut files = Vec::new();
+    walk(root, "", &ignore, &mut files);
+    files.sort();
+    files
+}
+
+fn walk(dir: &Path, prefix: &str, ignore: &Ignore, files: &mut Vec<String>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut entries: Vec<_> = entries.filter_map(|e| e.ok()).collect();
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        if files.len() >= MAX_FILES {
+            return;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        let relative = format!("{}{}", prefix, name);
+        // Don't follow symlinked folders, which could loop
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let is_dir = file_type.is_dir();
+        if ignore.is_ignored(&relative, &name, is_dir) {
+            continue;
+        }
+        if is_dir {
+            if !SKIPPED_DIRS.contains(&name.as_str()) {
+                walk(&entry.path(), &format!("{}/", relative), ignore, files);
+            }
+        } else {
+            files.push(relative);
+        }
+    }
+}
+
+// The simple subset of the root .gitignore: globs with `*` and `?`, a trailing `/` for
+// folders only and a `/` elsewhere to anchor to the root. Negations are skipped.
+struct Ignore {
+    patterns: Vec<(String, bool, bool)>,
+}
+
+impl Ignore {
+    fn load(root: &Path) -> Self {
+        let content = fs::read_to_string(root.join(".gitignore")).unwrap_or_default();
+        let patterns = content
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#') && !l.starts_with('!'))
+            .map(|line| {
+                let dir_only = line.ends_with('/');
+                let line = line.trim_end_matches('/');
+                let anchored = line.contains('/');
+                (line.trim_start_matches('/').to_string(), dir_only, anchored)
+            })
+            .collect();
+        Self { patterns }
+    }
+
+    fn is_ignored(&self, relative: &str, name: &str, is_dir: bool) -> bool {
+        self.patterns.iter().any(|(pattern, dir_only, anchored)| {
+            (!dir_only || is_dir) && glob_match(pattern, if *anchored { relative } else { name })
+        })
+    }
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    // Classic wildcard matching with backtracking to the last `*`
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
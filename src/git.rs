@@ -0,0 +1,157 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+// Locate the git directory for a file by walking up its parent directories.
+// Returns (work tree root, git dir); `.git` may also be a file pointing elsewhere (worktrees).
+pub fn find_repo(file: &Path) -> Option<(PathBuf, PathBuf)> {
+    let start = fs::canonicalize(file).ok()?;
+    for dir in start.ancestors().skip(1) {
+        let dot_git = dir.join(".git");
+        if dot_git.is_dir() {
+            return Some((dir.to_path_buf(), dot_git));
+        }
+        if dot_git.is_file() {
+            let content = fs::read_to_string(&dot_git).ok()?;
+            let target = content.strip_prefix("gitdir:")?.trim();
+            return Some((dir.to_path_buf(), dir.join(target)));
+        }
+    }
+    None
+}
+
+// Current branch name, or the abbreviated commit id for a detached HEAD
+pub fn current_branch(git_dir: &Path) -> Option<String> {
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    match head.strip_prefix("ref:") {
+        Some(reference) => {
+            let reference = reference.trim();
+            Some(reference.strip_prefix("refs/heads/").unwrap_or(reference).to_string())
+        }
+        None => Some(head.chars().take(7).collect()),
+    }
+}
+
+// Branch of the repository containing `file`, if it is in one
+pub fn branch_for(file: &Path) -> Option<String> {
+    let (_, git_dir) = find_repo(file)?;
+    current_branch(&git_dir)
+}
+
+// How a buffer line differs from the committed version of the file
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LineStatus {
+    Unchanged,
+    Added,
+    Modified,
+    // Lines were removed just after this one
+    DeletedBelow,
+}
+
+// Cells allowed in the LCS table before the changed region is just marked as modified
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+// Per-line change status of `lines` against the file's contents at HEAD.
+// Empty when the file isn't tracked, git isn't available, or nothing could be compared.
+pub fn line_changes(file: &Path, lines: &[String]) -> Vec<LineStatus> {
+    let Some((root, _)) = find_repo(file) else {
+        return Vec::new();
+    };
+    let Ok(full_path) = fs::canonicalize(file) else {
+        return Vec::new();
+    };
+    let Ok(relative) = full_path.strip_prefix(&root) else {
+        return Vec::new();
+    };
+    let spec = format!("HEAD:{}", relative.to_string_lossy().replace('\\', "/"));
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(&root)
+        .args(["show", &spec])
+        .stderr(Stdio::null())
+        .output();
+    match output {
+        Ok(output) if output.status.success() => {
+            let committed = String::from_utf8_lossy(&output.stdout);
+            let committed: Vec<&str> = committed.lines().collect();
+            let current: Vec<&str> = lines.iter().map(|l| l.as_str()).collect();
+            diff_lines(&committed, &current)
+        }
+        _ => Vec::new(),
+    }
+}
+
+// Classify each line of `new` relative to `old` using a longest-common-subsequence diff
+pub fn diff_lines(old: &[&str], new: &[&str]) -> Vec<LineStatus> {
+    let mut status = vec![LineStatus::Unchanged; new.len()];
+
+    // Only the region between the common prefix and suffix needs diffing
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mark_deleted = |status: &mut Vec<LineStatus>, row: usize| {
+        // Deletions before the first line are shown on the first line
+        let row = row.saturating_sub(1).min(status.len().saturating_sub(1));
+        if let Some(s) = status.get_mut(row)
+            && *s == LineStatus::Unchanged
+        {
+            *s = LineStatus::DeletedBelow;
+        }
+    };
+
+    if old_mid.len() * new_mid.len() > MAX_DIFF_CELLS {
+        for s in &mut status[prefix..prefix + new_mid.len()] {
+            *s = LineStatus::Modified;
+        }
+        return status;
+    }
+
+    // lcs[i][j] = LCS length of old_mid[i..] and new_mid[j..]
+    let (n, m) = (old_mid.len(), new_mid.len());
+    let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old_mid[i] == new_mid[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    // Walk the table, grouping runs of removed and inserted lines into hunks
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old_mid[i] == new_mid[j] {
+            i += 1;
+            j += 1;
+            continue;
+        }
+        let (mut removed, mut inserted) = (0, 0);
+        while (i < n || j < m) && !(i < n && j < m && old_mid[i] == new_mid[j]) {
+            if j < m && (i == n || lcs[i][j + 1] >= lcs[i + 1][j]) {
+                inserted += 1;
+                j += 1;
+            } else {
+                removed += 1;
+                i += 1;
+            }
+        }
+        let hunk_start = prefix + j - inserted;
+        for (k, s) in status[hunk_start..prefix + j].iter_mut().enumerate() {
+            *s = if k < removed { LineStatus::Modified } else { LineStatus::Added };
+        }
+        if removed > inserted || inserted == 0 {
+            mark_deleted(&mut status, hunk_start + inserted.min(removed));
+        }
+    }
+    status
+}
@@ -0,0 +1,191 @@
+use crate::buffer::char_index;
+use crate::finder;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// Searching stops after this many matches
+const MAX_MATCHES: usize = 5_000;
+// Files whose first bytes contain a NUL are treated as binary and skipped
+const BINARY_SNIFF_LEN: usize = 8_000;
+
+#[derive(Clone)]
+pub struct GrepMatch {
+    pub path: String,
+    pub line: usize,
+    // Character column of the match
+    pub col: usize,
+    pub text: String,
+}
+
+// A project-wide search running in the background; matches appear as they are found
+pub struct GrepSearch {
+    pub pattern: String,
+    pub selected: usize,
+    matches: Arc<Mutex<Vec<GrepMatch>>>,
+    done: Arc<AtomicBool>,
+    cancel: Arc<AtomicBool>,
+    // Files searched so far out of the total, for the progress display
+    scanned: Arc<AtomicUsize>,
+    total: Arc<AtomicUsize>,
+    seen: (usize, usize),
+}
+
+impl GrepSearch {
+    // Lowercase patterns match case-insensitively, any capital makes the search exact
+    pub fn start(root: &Path, pattern: &str) -> Self {
+        let matches = Arc::new(Mutex::new(Vec::new()));
+        let done = Arc::new(AtomicBool::new(false));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let scanned = Arc::new(AtomicUsize::new(0));
+        let total = Arc::new(AtomicUsize::new(0));
+        {
+            let (root, pattern) = (root.to_path_buf(), pattern.to_string());
+            let (matches, done, cancel) = (Arc::clone(&matches), Arc::clone(&done), Arc::clone(&cancel));
+            let progress = (Arc::clone(&scanned), Arc::clone(&total));
+            thread::spawn(move || {
+                search_tree(&root, &pattern, &matches, &cancel, &progress);
+                done.store(true, Ordering::Relaxed);
+            });
+        }
+        Self {
+            pattern: pattern.to_string(),
+            selected: 0,
+            matches,
+            done,
+            cancel,
+            scanned,
+            total,
+            seen: (0, 0),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.done.load(Ordering::Relaxed)
+    }
+
+    // Stop the search early, keeping the matches found so far
+    pub fn cancel(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    // True if the search was stopped before it got through every file
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed) && self.len() < MAX_MATCHES
+    }
+
+    // Returns true if new matches arrived, more files were searched or the search
+    // finished since the last poll
+    pub fn poll(&mut self) -> bool {
+        let progress = (self.len(), self.scanned.load(Ordering::Relaxed));
+        let finished = !self.is_running() && self.seen != (usize::MAX, usize::MAX);
+        if progress != self.seen || finished {
+            self.seen = if finished { (usize::MAX, usize::MAX) } else { progress };
+            return true;
+        }
+        false
+    }
+
+    pub fn len(&self) -> usize {
+        self.matches.lock().map(|m| m.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn status_text(&self) -> String {
+        let len = self.len();
+        if self.is_cancelled() {
+            format!("{} matches, cancelled", len)
+        } else if self.is_running() {
+            let scanned = self.scanned.load(Ordering::Relaxed);
+            match self.total.load(Ordering::Relaxed) {
+                0 => format!("{} matches, searching...", len),
+                total => format!("{} matches, searched {}/{} files...", len, scanned, total),
+            }
+        } else if len >= MAX_MATCHES {
+            format!("first {} matches", len)
+        } else {
+            format!("{} matches", len)
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let Ok(matches) = self.matches.lock() else {
+            return Vec::new();
+        };
+        matches
+            .iter()
+            .map(|m| format!("{}:{}: {}", m.path, m.line + 1, m.text.trim()))
+            .collect()
+    }
+
+    pub fn get(&self, idx: usize) -> Option<GrepMatch> {
+        self.matches.lock().ok()?.get(idx).cloned()
+    }
+
+    pub fn move_selection(&mut self, delta: isize) {
+        let last = self.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+}
+
+impl Drop for GrepSearch {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+fn search_tree(
+    root: &Path,
+    pattern: &str,
+    matches: &Mutex<Vec<GrepMatch>>,
+    cancel: &AtomicBool,
+    (scanned, total): &(Arc<AtomicUsize>, Arc<AtomicUsize>),
+) {
+    let ignore_case = !pattern.chars().any(|c| c.is_uppercase());
+
+    let files = finder::list_files(root);
+    total.store(files.len(), Ordering::Relaxed);
+    for relative in files {
+        if cancel.load(Ordering::Relaxed) {
+            return;
+        }
+        scanned.fetch_add(1, Ordering::Relaxed);
+        let Ok(bytes) = fs::read(root.join(&relative)) else {
+            continue;
+        };
+        if bytes[..bytes.len().min(BINARY_SNIFF_LEN)].contains(&0) {
+            continue;
+        }
+        let content = String::from_utf8_lossy(&bytes);
+        let mut found = Vec::new();
+        for (line_idx, line) in content.lines().enumerate() {
+            // ASCII lowercasing keeps byte offsets valid in the original line
+            let haystack = if ignore_case { line.to_ascii_lowercase() } else { line.to_string() };
+            if let Some(col) = haystack.find(pattern) {
+                found.push(GrepMatch {
+                    path: relative.clone(),
+                    line: line_idx,
+                    col: char_index(line, col),
+                    text: line.to_string(),
+                });
+            }
+        }
+        if found.is_empty() {
+            continue;
+        }
+        let Ok(mut matches) = matches.lock() else {
+            return;
+        };
+        let room = MAX_MATCHES - matches.len();
+        let full = found.len() >= room;
+        matches.extend(found.into_iter().take(room));
+        if full {
+            return;
+        }
+    }
+}
@@ -0,0 +1,570 @@
+use crate::config::HighlightRules;
+use crossterm::style::Color;
+use regex::Regex;
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TokenType {
+    Keyword,
+    String,
+    Comment,
+    Number,
+    Function,
+    Type,
+    Normal,
+    // Lines of a patch: added, removed, `@@` hunk headers and file headers
+    Inserted,
+    Deleted,
+    Hunk,
+    Header,
+}
+
+impl TokenType {
+    pub fn color(&self) -> Color {
+        match self {
+            TokenType::Keyword => Color::Magenta,
+            TokenType::String => Color::Green,
+            TokenType::Comment => Color::DarkGrey,
+            TokenType::Number => Color::Cyan,
+            TokenType::Function => Color::Yellow,
+            TokenType::Type => Color::Blue,
+            TokenType::Normal | TokenType::Header => Color::White,
+            TokenType::Inserted => Color::Green,
+            TokenType::Deleted => Color::Red,
+            TokenType::Hunk => Color::Cyan,
+        }
+    }
+
+    pub fn is_bold(&self) -> bool {
+        *self == TokenType::Header
+    }
+
+    // Token type named in a config highlighting rule, e.g. "keyword"
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "keyword" => Some(TokenType::Keyword),
+            "string" => Some(TokenType::String),
+            "comment" => Some(TokenType::Comment),
+            "number" => Some(TokenType::Number),
+            "function" => Some(TokenType::Function),
+            "type" => Some(TokenType::Type),
+            "normal" => Some(TokenType::Normal),
+            "inserted" => Some(TokenType::Inserted),
+            "deleted" => Some(TokenType::Deleted),
+            "hunk" => Some(TokenType::Hunk),
+            "header" => Some(TokenType::Header),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SyntaxHighlighter {
+    pub language: Language,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Language {
+    Rust,
+    Python,
+    JavaScript,
+    Java,
+    C,
+    Bash,
+    Markdown,
+    Html,
+    Diff,
+    Plain,
+}
+
+impl Language {
+    pub fn from_extension(ext: &str) -> Self {
+        match ext.to_lowercase().as_str() {
+            "rs" => Language::Rust,
+            "py" => Language::Python,
+            "js" | "jsx" | "ts" | "tsx" => Language::JavaScript,
+            "java" => Language::Java,
+            "c" | "h" | "cpp" | "hpp" | "cc" => Language::C,
+            "sh" | "bash" => Language::Bash,
+            "md" | "markdown" => Language::Markdown,
+            "html" | "htm" | "xhtml" | "xml" | "svg" => Language::Html,
+            "diff" | "patch" => Language::Diff,
+            _ => Language::Plain,
+        }
+    }
+
+    // Language of a file from its extension, or None when it has none
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension().and_then(|ext| ext.to_str()).map(Self::from_extension)
+    }
+
+    // Language recognisable from the first lines of text whose file name doesn't tell:
+    // a patch starting with `diff --git` or with `---`/`+++` file headers
+    pub fn from_content(lines: &[String]) -> Option<Self> {
+        let first = lines.first()?;
+        let second = lines.get(1).map_or("", String::as_str);
+        let patch = first.starts_with("diff --git ") || (first.starts_with("--- ") && second.starts_with("+++ "));
+        patch.then_some(Language::Diff)
+    }
+
+    // Bracket and quote pairs closed automatically when the opener is typed. Rust
+    // leaves `'` alone for lifetimes; markup pairs `<` with `>`.
+    pub fn auto_close_pairs(&self) -> &'static [(char, char)] {
+        const BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+        match self {
+            Language::Rust => &[BRACKETS[0], BRACKETS[1], BRACKETS[2], ('"', '"')],
+            Language::Python | Language::Java | Language::C => {
+                &[BRACKETS[0], BRACKETS[1], BRACKETS[2], ('"', '"'), ('\'', '\'')]
+            }
+            Language::Html => &[BRACKETS[0], BRACKETS[1], BRACKETS[2], ('"', '"'), ('\'', '\''), ('<', '>')],
+            Language::JavaScript | Language::Bash | Language::Markdown | Language::Diff | Language::Plain => {
+                &[BRACKETS[0], BRACKETS[1], BRACKETS[2], ('"', '"'), ('\'', '\''), ('`', '`')]
+            }
+        }
+    }
+
+    fn keywords(&self) -> &[&str] {
+        match self {
+            Language::Rust => &[
+                "fn", "let", "mut", "const", "static", "if", "else", "match", "for", "while",
+                "loop", "break", "continue", "return", "struct", "enum", "trait", "impl", "pub",
+                "use", "mod", "crate", "self", "super", "as", "move", "ref", "unsafe", "async",
+                "await", "dyn", "where", "type", "in",
+            ],
+            Language::Python => &[
+                "def", "class", "if", "elif", "else", "for", "while", "break", "continue",
+                "return", "try", "except", "finally", "with", "as", "import", "from", "pass",
+                "raise", "assert", "lambda", "yield", "async", "await", "global", "nonlocal",
+                "True", "False", "None", "and", "or", "not", "in", "is",
+            ],
+            Language::JavaScript => &[
+                "function", "const", "let", "var", "if", "else", "for", "while", "break",
+                "continue", "return", "class", "extends", "super", "this", "new", "try", "catch",
+                "finally", "throw", "async", "await", "import", "export", "from", "default",
+                "switch", "case", "typeof", "instanceof", "delete", "void", "yield",
+            ],
+            Language::Java => &[
+                "abstract", "assert", "boolean", "break", "byte", "case", "catch", "char", "class",
+                "const", "continue", "default", "do", "double", "else", "enum", "extends", "final",
+                "finally", "float", "for", "goto", "if", "implements", "import", "instanceof",
+                "int", "interface", "long", "native", "new", "package", "private", "protected",
+                "public", "return", "short", "static", "strictfp", "super", "switch", "synchronized",
+                "this", "throw", "throws", "transient", "try", "void", "volatile", "while",
+                "true", "false", "null",
+            ],
+            Language::C => &[
+                "int", "char", "float", "double", "void", "struct", "union", "enum", "if",
+                "else", "for", "while", "do", "break", "continue", "return", "switch", "case",
+                "default", "sizeof", "typedef", "static", "const", "extern", "auto", "register",
+                "volatile", "unsigned", "signed", "long", "short",
+            ],
+            Language::Bash => &[
+                "if", "then", "else", "elif", "fi", "for", "while", "do", "done", "case",
+                "esac", "function", "return", "exit", "break", "continue", "local", "export",
+                "source", "alias", "echo", "read", "test",
+            ],
+            Language::Markdown | Language::Html | Language::Diff | Language::Plain => &[],
+        }
+    }
+
+    fn types(&self) -> &[&str] {
+        match self {
+            Language::Rust => &[
+                "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128",
+                "usize", "f32", "f64", "bool", "char", "str", "String", "Vec", "Option", "Result",
+                "Box", "Rc", "Arc", "Cell", "RefCell",
+            ],
+            Language::Java => &[
+                "byte", "short", "int", "long", "float", "double", "boolean", "char", "String",
+                "Integer", "Double", "List", "ArrayList", "Map", "HashMap", "Set", "HashSet",
+            ],
+            Language::C => &["int", "char", "float", "double", "void", "size_t", "uint8_t", "uint16_t", "uint32_t"],
+            _ => &[],
+        }
+    }
+
+    // Name used for the language in the config file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::Rust => "rust",
+            Language::Python => "python",
+            Language::JavaScript => "javascript",
+            Language::Java => "java",
+            Language::C => "c",
+            Language::Bash => "bash",
+            Language::Markdown => "markdown",
+            Language::Html => "html",
+            Language::Diff => "diff",
+            Language::Plain => "plain",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            Language::Rust,
+            Language::Python,
+            Language::JavaScript,
+            Language::Java,
+            Language::C,
+            Language::Bash,
+            Language::Markdown,
+            Language::Html,
+            Language::Diff,
+            Language::Plain,
+        ]
+        .into_iter()
+        .find(|lang| lang.name().eq_ignore_ascii_case(name))
+    }
+
+    // Prose is spell checked everywhere, code only inside comments and strings
+    pub fn is_prose(&self) -> bool {
+        matches!(self, Language::Markdown | Language::Plain)
+    }
+}
+
+impl SyntaxHighlighter {
+    pub fn new(language: Language) -> Self {
+        Self { language }
+    }
+
+    fn is_comment_start(&self, ch: char, next: Option<char>) -> bool {
+        match self.language {
+            Language::Rust | Language::C | Language::JavaScript | Language::Java => {
+                ch == '/' && (next == Some('/') || next == Some('*'))
+            }
+            Language::Python | Language::Bash => ch == '#',
+            Language::Html => ch == '<' && next == Some('!'),
+            Language::Markdown | Language::Diff | Language::Plain => false,
+        }
+    }
+
+    fn push_token(&self, tokens: &mut Vec<(Range<usize>, TokenType)>, line: &str, span: Range<usize>) {
+        let token = &line[span.clone()];
+        let token_type = if self.language.keywords().contains(&token) {
+            TokenType::Keyword
+        } else if self.language.types().contains(&token) {
+            TokenType::Type
+        } else if token.chars().all(|c| c.is_numeric() || c == '.') {
+            TokenType::Number
+        } else {
+            TokenType::Normal
+        };
+
+        tokens.push((span, token_type));
+    }
+}
+
+// Splits lines into typed spans for drawing. `SyntaxHighlighter` covers the built-in
+// languages; other highlighters can be registered by file extension in a
+// `HighlighterRegistry`.
+pub trait Highlighter {
+    // Split a line into typed spans. The byte ranges cover the line in order, without
+    // gaps, so drawing can slice the line instead of copying each token.
+    fn highlight_line(&self, line: &str) -> Vec<(Range<usize>, TokenType)>;
+
+    // The built-in language whose editing rules (indentation, comments, auto-close
+    // pairs, snippets, config section) apply to the text
+    fn language(&self) -> Language;
+
+    // Name the highlighter is known by, which tells apart ones sharing a language
+    fn name(&self) -> &str {
+        self.language().name()
+    }
+
+    // Brackets without a partner, as (row, byte column): closers with nothing to close,
+    // and openers left unclosed inside an outer pair or at the end. Brackets in strings
+    // and comments don't count, and neither does a lone `)` in Bash, which ends a
+    // `case` pattern.
+    fn unbalanced_brackets(&self, lines: &[String]) -> Vec<(usize, usize)> {
+        // Hunks show only parts of the code, so their brackets needn't pair up
+        if self.language().is_prose() || self.language() == Language::Diff {
+            return Vec::new();
+        }
+        let mut open: Vec<(char, usize, usize)> = Vec::new();
+        let mut unbalanced = Vec::new();
+        for (row, line) in lines.iter().enumerate() {
+            for (span, token_type) in self.highlight_line(line) {
+                if matches!(token_type, TokenType::String | TokenType::Comment) {
+                    continue;
+                }
+                for (offset, ch) in line[span.clone()].char_indices() {
+                    let col = span.start + offset;
+                    let opener = match ch {
+                        '(' | '[' | '{' => {
+                            open.push((ch, row, col));
+                            continue;
+                        }
+                        ')' => '(',
+                        ']' => '[',
+                        '}' => '{',
+                        _ => continue,
+                    };
+                    // A closer for a bracket further out closes the ones left open
+                    // inside it; a closer with no opener at all is the stray one
+                    match open.iter().rposition(|&(c, _, _)| c == opener) {
+                        Some(idx) => {
+                            unbalanced.extend(open.drain(idx..).skip(1).map(|(_, row, col)| (row, col)));
+                        }
+                        None if ch == ')' && self.language() == Language::Bash => {}
+                        None => unbalanced.push((row, col)),
+                    }
+                }
+            }
+        }
+        unbalanced.extend(open.into_iter().map(|(_, row, col)| (row, col)));
+        unbalanced.sort_unstable();
+        unbalanced
+    }
+}
+
+impl Highlighter for SyntaxHighlighter {
+    fn highlight_line(&self, line: &str) -> Vec<(Range<usize>, TokenType)> {
+        if self.language.is_prose() {
+            return vec![(0..line.len(), TokenType::Normal)];
+        }
+        if self.language == Language::Diff {
+            return vec![(0..line.len(), diff_line_type(line))];
+        }
+
+        let mut tokens = Vec::new();
+        // The token being built is always `line[start..idx]` for the next char at `idx`
+        let mut start = 0;
+        let mut chars = line.char_indices().peekable();
+        let mut in_string = false;
+        let mut string_char = ' ';
+        let mut in_comment = false;
+
+        while let Some((idx, ch)) = chars.next() {
+            let current = &line[start..idx];
+            let end = idx + ch.len_utf8();
+
+            // Handle comments
+            if !in_string && !in_comment && self.is_comment_start(ch, chars.peek().map(|&(_, c)| c)) {
+                if !current.is_empty() {
+                    self.push_token(&mut tokens, line, start..idx);
+                }
+                in_comment = true;
+                start = idx;
+                if let Some(&(_, next)) = chars.peek()
+                    && (next == '/' || next == '*')
+                {
+                    chars.next();
+                }
+                continue;
+            }
+
+            if in_comment {
+                continue;
+            }
+
+            // Handle strings. In Rust a quote that doesn't close a char literal is a lifetime.
+            // Markup text is full of apostrophes, so only double-quoted attributes count there.
+            let opens_string = match ch {
+                '"' => true,
+                '`' => self.language != Language::Html,
+                '\'' => match self.language {
+                    Language::Rust => is_char_literal(&line[end..]),
+                    Language::Html => false,
+                    _ => true,
+                },
+                _ => false,
+            };
+            if opens_string && !in_string {
+                if !current.is_empty() {
+                    self.push_token(&mut tokens, line, start..idx);
+                }
+                in_string = true;
+                string_char = ch;
+                start = idx;
+                continue;
+            }
+
+            if in_string {
+                // A quote after an odd number of backslashes is escaped
+                let backslashes = current.chars().rev().take_while(|&c| c == '\\').count();
+                if ch == string_char && backslashes % 2 == 0 {
+                    tokens.push((start..end, TokenType::String));
+                    start = end;
+                    in_string = false;
+                }
+                continue;
+            }
+
+            // Handle numbers
+            if ch.is_numeric() && current.chars().all(|c| c.is_numeric() || c == '.') {
+                continue;
+            }
+
+            // Handle identifiers and keywords
+            if ch.is_alphanumeric() || ch == '_' {
+                continue;
+            }
+
+            // We hit a separator
+            if !current.is_empty() {
+                self.push_token(&mut tokens, line, start..idx);
+                // An identifier directly followed by `(` is a call or definition
+                if ch == '('
+                    && let Some(last) = tokens.last_mut()
+                    && last.1 == TokenType::Normal
+                    && !current.starts_with(|c: char| c.is_numeric())
+                {
+                    last.1 = TokenType::Function;
+                }
+            }
+
+            // Add the separator as is
+            tokens.push((idx..end, TokenType::Normal));
+            start = end;
+        }
+
+        // Handle remaining content
+        let rest = start..line.len();
+        if in_comment {
+            tokens.push((rest, TokenType::Comment));
+        } else if in_string {
+            tokens.push((rest, TokenType::String));
+        } else if !rest.is_empty() {
+            self.push_token(&mut tokens, line, rest);
+        }
+
+        tokens
+    }
+
+    fn language(&self) -> Language {
+        self.language
+    }
+}
+
+// Highlighters for file extensions, consulted before the built-in languages
+#[derive(Clone, Default)]
+pub struct HighlighterRegistry {
+    by_extension: Vec<(String, Rc<dyn Highlighter>)>,
+}
+
+impl HighlighterRegistry {
+    // Use `highlighter` for files ending in `.extension`, replacing any registered before
+    pub fn register(&mut self, extension: &str, highlighter: Rc<dyn Highlighter>) {
+        let extension = extension.to_lowercase();
+        self.by_extension.retain(|(ext, _)| *ext != extension);
+        self.by_extension.push((extension, highlighter));
+    }
+
+    // Compile a `[highlight.NAME]` config section and use it for its extensions. An
+    // error names the first rule that couldn't be used.
+    pub fn register_rules(&mut self, rules: &HighlightRules) -> Result<(), String> {
+        let highlighter: Rc<dyn Highlighter> = Rc::new(ConfigHighlighter::new(rules)?);
+        for extension in &rules.extensions {
+            self.register(extension, highlighter.clone());
+        }
+        Ok(())
+    }
+
+    // The highlighter for a file: one registered for its extension, else the built-in
+    // language's. None when the file has no extension.
+    pub fn for_path(&self, path: &Path) -> Option<Rc<dyn Highlighter>> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match self.by_extension.iter().find(|(ext, _)| *ext == extension) {
+            Some((_, highlighter)) => Some(highlighter.clone()),
+            None => Some(Rc::new(SyntaxHighlighter::new(Language::from_extension(&extension)))),
+        }
+    }
+}
+
+// Highlights with the regex rules of a `[highlight.NAME]` config section, compiled once
+pub struct ConfigHighlighter {
+    name: String,
+    language: Language,
+    rules: Vec<(Regex, TokenType)>,
+}
+
+impl ConfigHighlighter {
+    pub fn new(section: &HighlightRules) -> Result<Self, String> {
+        let language = match &section.language {
+            Some(name) => Language::from_name(name).ok_or_else(|| format!("unknown language: {}", name))?,
+            None => Language::Plain,
+        };
+        let rules = section
+            .rules
+            .iter()
+            .map(|(token, pattern)| {
+                let token_type = TokenType::from_name(token).ok_or_else(|| format!("unknown token type: {}", token))?;
+                let regex = Regex::new(pattern).map_err(|_| format!("invalid pattern for {}: {}", token, pattern))?;
+                Ok((regex, token_type))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self { name: section.name.clone(), language, rules })
+    }
+}
+
+impl Highlighter for ConfigHighlighter {
+    fn highlight_line(&self, line: &str) -> Vec<(Range<usize>, TokenType)> {
+        // Each rule only claims text no earlier rule matched; a match overlapping one
+        // already claimed is dropped whole
+        let mut claimed: Vec<(Range<usize>, TokenType)> = Vec::new();
+        for (regex, token_type) in &self.rules {
+            for found in regex.find_iter(line).filter(|m| !m.is_empty()) {
+                let span = found.range();
+                if !claimed.iter().any(|(c, _)| c.start < span.end && span.start < c.end) {
+                    claimed.push((span, *token_type));
+                }
+            }
+        }
+        claimed.sort_by_key(|(span, _)| span.start);
+
+        // Text no rule matched is normal, so the spans cover the line without gaps
+        let mut tokens = Vec::new();
+        let mut end = 0;
+        for (span, token_type) in claimed {
+            if span.start > end {
+                tokens.push((end..span.start, TokenType::Normal));
+            }
+            end = span.end;
+            tokens.push((span, token_type));
+        }
+        if end < line.len() || tokens.is_empty() {
+            tokens.push((end..line.len(), TokenType::Normal));
+        }
+        tokens
+    }
+
+    fn language(&self) -> Language {
+        self.language
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+// Whether the text after a `'` is the rest of a char literal like `'a'` or `'\n'`
+// How a patch line is coloured, from how it starts. File headers are checked before
+// added and removed lines, which `+++` and `---` would otherwise be taken for.
+fn diff_line_type(line: &str) -> TokenType {
+    const HEADERS: &[&str] = &[
+        "diff ", "index ", "--- ", "+++ ", "new file mode", "deleted file mode", "old mode", "new mode",
+        "similarity index", "rename from", "rename to", "Binary files",
+    ];
+    if HEADERS.iter().any(|header| line.starts_with(header)) {
+        TokenType::Header
+    } else if line.starts_with("@@") {
+        TokenType::Hunk
+    } else if line.starts_with('+') {
+        TokenType::Inserted
+    } else if line.starts_with('-') {
+        TokenType::Deleted
+    } else {
+        TokenType::Normal
+    }
+}
+
+fn is_char_literal(rest: &str) -> bool {
+    let mut chars = rest.chars();
+    match chars.next() {
+        Some('\\') => rest[1..].contains('\''),
+        Some(_) => chars.next() == Some('\''),
+        None => false,
+    }
+}
@@ -0,0 +1,88 @@
+use crate::config;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::PathBuf;
+
+const MAX_ENTRIES: usize = 100;
+
+// Previously entered prompt values, newest first, saved to the config dir
+pub struct History {
+    entries: VecDeque<String>,
+    path: Option<PathBuf>,
+    // Position while cycling with Up/Down, and the text typed before starting
+    position: Option<usize>,
+    draft: String,
+}
+
+impl History {
+    // `name` is the file in the config dir, e.g. "search_history"
+    pub fn load(name: &str) -> Self {
+        let path = config::config_dir().map(|dir| dir.join(name));
+        let entries = path
+            .as_ref()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .map(|content| {
+                content
+                    .lines()
+                    .filter(|l| !l.is_empty())
+                    .take(MAX_ENTRIES)
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { entries, path, position: None, draft: String::new() }
+    }
+
+    // Record a committed value, moving a repeat to the front, and save the list
+    pub fn push(&mut self, entry: &str) {
+        self.reset();
+        if entry.is_empty() || entry.contains('\n') {
+            return;
+        }
+        self.entries.retain(|e| e != entry);
+        self.entries.push_front(entry.to_string());
+        self.entries.truncate(MAX_ENTRIES);
+        if let Some(path) = &self.path {
+            // History is a convenience; failing to save it isn't worth reporting
+            let _ = fs::create_dir_all(path.parent().unwrap_or(path));
+            let content: Vec<&str> = self.entries.iter().map(String::as_str).collect();
+            let _ = fs::write(path, content.join("\n") + "\n");
+        }
+    }
+
+    // Step to an older entry. `current` is the prompt text, kept to come back to.
+    pub fn older(&mut self, current: &str) -> Option<&str> {
+        let next = match self.position {
+            None => {
+                self.draft = current.to_string();
+                0
+            }
+            Some(pos) => pos + 1,
+        };
+        if next >= self.entries.len() {
+            return None;
+        }
+        self.position = Some(next);
+        self.entries.get(next).map(String::as_str)
+    }
+
+    // Step to a newer entry, ending with the text that was typed before cycling
+    pub fn newer(&mut self) -> Option<&str> {
+        match self.position? {
+            0 => {
+                self.position = None;
+                Some(&self.draft)
+            }
+            pos => {
+                self.position = Some(pos - 1);
+                self.entries.get(pos - 1).map(String::as_str)
+            }
+        }
+    }
+
+    // Forget the cycling position, e.g. when a prompt opens
+    pub fn reset(&mut self) {
+        self.position = None;
+        self.draft.clear();
+    }
+}
@@ -0,0 +1,300 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+// What a key does in normal mode. Editing and cursor motion are applied to a pane by
+// `Pane::apply`; the rest need the editor (files, prompts, splits, the clipboard).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Action {
+    // Editing
+    InsertChar(char),
+    InsertTab,
+    Newline,
+    Backspace,
+    DeleteToLineStart,
+    DeleteLine,
+    Undo,
+    Redo,
+    SelectAll,
+    // Cursor motion
+    Left,
+    Right,
+    Up,
+    Down,
+    ParagraphUp,
+    ParagraphDown,
+    // Jumps to the last line of the current block
+    BlockEnd,
+    // Collapses the indented block the cursor line starts into that line, or opens it
+    ToggleFold,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    // Shift-motions, extending the selection from where the cursor was
+    SelectLeft,
+    SelectRight,
+    SelectUp,
+    SelectDown,
+    SelectHome,
+    SelectEnd,
+    // Editor commands
+    Cancel,
+    Quit,
+    Save,
+    OpenPrompt,
+    // Prompts for a file whose text is inserted at the cursor
+    InsertFilePrompt,
+    // Prompts for a file to write the selected lines, or a typed line range, to
+    WriteLinesPrompt,
+    // Stops or allows replacing the active pane's buffer with another file
+    ToggleLock,
+    // Reports the code point, byte offset and width of the character under the cursor
+    DescribeChar,
+    Search,
+    SearchBackward,
+    // Asks for a search term and its replacement, then whether to replace each match
+    ReplacePrompt,
+    GotoLinePrompt,
+    // Repeats the last search in the direction it went
+    FindNext,
+    // Repeats the last search in the opposite direction
+    FindPrev,
+    ToggleLineNumbers,
+    ToggleFocusMode,
+    ToggleZenMode,
+    ToggleBracketCheck,
+    NextPane,
+    SwapPanes,
+    Split { vertical: bool, same_buffer: bool },
+    CloseSplit,
+    GrowSplit,
+    ShrinkSplit,
+    Copy,
+    // Removes the selection, or the current line, into the clipboard
+    Cut,
+    Paste,
+    // Jump to the next or previous line marked as changed in the gutter
+    NextChange,
+    PrevChange,
+    // Jump to the next or previous `@@` hunk of a patch
+    NextHunk,
+    PrevHunk,
+    ConvertIndentation { to_spaces: bool },
+    // Converts indentation to the configured style
+    NormalizeIndentation,
+    Reindent,
+    ShellFilter,
+    RunFile,
+    OpenAlternate,
+    ToggleTrailingNewline,
+    ToggleBom,
+    NextQueuedFile,
+    FuzzyFind,
+    GrepPrompt,
+    ShowMessages,
+    ToggleSpellCheck,
+    NextMisspelling,
+    AddWordToDictionary,
+    InsertLiteral,
+    ExpandSnippet,
+}
+
+impl Action {
+    // Actions that work on the selection keep it; anything else drops it
+    pub fn keeps_selection(&self) -> bool {
+        matches!(
+            self,
+            Action::SelectAll
+                | Action::SelectLeft
+                | Action::SelectRight
+                | Action::SelectUp
+                | Action::SelectDown
+                | Action::SelectHome
+                | Action::SelectEnd
+                | Action::Copy
+                | Action::Cut
+                | Action::ConvertIndentation { .. }
+                | Action::Reindent
+                | Action::ShellFilter
+                | Action::WriteLinesPrompt
+        )
+    }
+
+    // Vertical motions keep the goal column so the cursor returns to it past short lines
+    pub fn is_vertical(&self) -> bool {
+        matches!(
+            self,
+            Action::Up
+                | Action::Down
+                | Action::PageUp
+                | Action::PageDown
+                | Action::ParagraphUp
+                | Action::ParagraphDown
+                | Action::SelectUp
+                | Action::SelectDown
+        )
+    }
+
+    // Typing over a selection replaces it, and Backspace deletes it
+    pub fn replaces_selection(&self) -> bool {
+        matches!(
+            self,
+            Action::InsertChar(_) | Action::InsertTab | Action::Newline | Action::Backspace | Action::Paste
+        )
+    }
+
+    // The cursor motion a Shift-motion makes
+    pub fn selection_motion(&self) -> Option<Action> {
+        match self {
+            Action::SelectLeft => Some(Action::Left),
+            Action::SelectRight => Some(Action::Right),
+            Action::SelectUp => Some(Action::Up),
+            Action::SelectDown => Some(Action::Down),
+            Action::SelectHome => Some(Action::Home),
+            Action::SelectEnd => Some(Action::End),
+            _ => None,
+        }
+    }
+
+    // Changes the text or how it's saved, so it's refused in a read-only pane before it runs
+    pub fn edits_buffer(&self) -> bool {
+        matches!(
+            self,
+            Action::InsertChar(_)
+                | Action::InsertTab
+                | Action::Newline
+                | Action::Backspace
+                | Action::DeleteToLineStart
+                | Action::DeleteLine
+                | Action::Undo
+                | Action::Redo
+                | Action::InsertFilePrompt
+                | Action::ReplacePrompt
+                | Action::Cut
+                | Action::Paste
+                | Action::ConvertIndentation { .. }
+                | Action::NormalizeIndentation
+                | Action::Reindent
+                | Action::ShellFilter
+                | Action::ToggleTrailingNewline
+                | Action::ToggleBom
+                | Action::InsertLiteral
+                | Action::ExpandSnippet
+        )
+    }
+
+    // Typing clears the message line
+    pub fn is_typing(&self) -> bool {
+        matches!(
+            self,
+            Action::InsertChar(_)
+                | Action::InsertTab
+                | Action::Newline
+                | Action::Backspace
+                | Action::DeleteToLineStart
+                | Action::DeleteLine
+        )
+    }
+}
+
+// The normal-mode binding for a key, if it has one
+pub fn normal_mode_action(key: KeyEvent) -> Option<Action> {
+    let ctrl = key.modifiers == KeyModifiers::CONTROL;
+    let alt = key.modifiers == KeyModifiers::ALT;
+    let shift = key.modifiers == KeyModifiers::SHIFT;
+    let ctrl_shift = key.modifiers == KeyModifiers::CONTROL | KeyModifiers::SHIFT;
+    let action = match key.code {
+        KeyCode::Char(c) if ctrl => match c {
+            'q' => Action::Quit,
+            's' => Action::Save,
+            'o' => Action::OpenPrompt,
+            'f' => Action::Search,
+            'g' => Action::GotoLinePrompt,
+            'r' => Action::SearchBackward,
+            'n' => Action::FindNext,
+            'b' => Action::FindPrev,
+            'l' => Action::ToggleLineNumbers,
+            'w' => Action::NextPane,
+            'h' => Action::Split { vertical: false, same_buffer: false },
+            'k' => Action::Split { vertical: true, same_buffer: false },
+            'x' => Action::CloseSplit,
+            'y' => Action::Redo,
+            'z' => Action::Undo,
+            'c' => Action::Copy,
+            'v' => Action::Paste,
+            'a' => Action::SelectAll,
+            'u' => Action::DeleteToLineStart,
+            'p' => Action::FuzzyFind,
+            't' => Action::Cut,
+            'd' => Action::DeleteLine,
+            _ => return None,
+        },
+        // Terminals differ on whether Alt-Shift-t arrives with SHIFT set
+        KeyCode::Char('T') if key.modifiers.contains(KeyModifiers::ALT) => {
+            Action::ConvertIndentation { to_spaces: false }
+        }
+        // Shift is needed to type `?` and `%`, and some terminals report it
+        KeyCode::Char('?') if key.modifiers.contains(KeyModifiers::ALT) => Action::DescribeChar,
+        KeyCode::Char('%') if key.modifiers.contains(KeyModifiers::ALT) => Action::ReplacePrompt,
+        KeyCode::Char(c) if alt => match c {
+            'v' => Action::InsertLiteral,
+            'p' => Action::InsertFilePrompt,
+            'c' => Action::WriteLinesPrompt,
+            'q' => Action::ToggleLock,
+            '.' => Action::NextHunk,
+            ',' => Action::PrevHunk,
+            'x' => Action::ExpandSnippet,
+            'w' => Action::SwapPanes,
+            't' => Action::ConvertIndentation { to_spaces: true },
+            'i' => Action::Reindent,
+            'r' => Action::ShellFilter,
+            'o' => Action::OpenAlternate,
+            'e' => Action::ToggleTrailingNewline,
+            'b' => Action::ToggleBom,
+            'f' => Action::NextQueuedFile,
+            'g' => Action::GrepPrompt,
+            'm' => Action::ShowMessages,
+            'd' => Action::ToggleFocusMode,
+            'z' => Action::ToggleZenMode,
+            'u' => Action::ToggleBracketCheck,
+            'j' => Action::BlockEnd,
+            '-' => Action::ToggleFold,
+            'l' => Action::NormalizeIndentation,
+            'h' => Action::Split { vertical: false, same_buffer: true },
+            'k' => Action::Split { vertical: true, same_buffer: true },
+            's' => Action::ToggleSpellCheck,
+            'n' => Action::NextMisspelling,
+            'a' => Action::AddWordToDictionary,
+            _ => return None,
+        },
+        KeyCode::Char(c) if key.modifiers == KeyModifiers::NONE || key.modifiers == KeyModifiers::SHIFT => {
+            Action::InsertChar(c)
+        }
+        KeyCode::Up | KeyCode::Left if ctrl_shift => Action::ShrinkSplit,
+        KeyCode::Down | KeyCode::Right if ctrl_shift => Action::GrowSplit,
+        KeyCode::Up if alt => Action::PrevChange,
+        KeyCode::Down if alt => Action::NextChange,
+        KeyCode::Left if shift => Action::SelectLeft,
+        KeyCode::Right if shift => Action::SelectRight,
+        KeyCode::Up if shift => Action::SelectUp,
+        KeyCode::Down if shift => Action::SelectDown,
+        KeyCode::Home if shift => Action::SelectHome,
+        KeyCode::End if shift => Action::SelectEnd,
+        KeyCode::Up if ctrl => Action::ParagraphUp,
+        KeyCode::Down if ctrl => Action::ParagraphDown,
+        KeyCode::Left => Action::Left,
+        KeyCode::Right => Action::Right,
+        KeyCode::Up => Action::Up,
+        KeyCode::Down => Action::Down,
+        KeyCode::Home => Action::Home,
+        KeyCode::End => Action::End,
+        KeyCode::PageUp => Action::PageUp,
+        KeyCode::PageDown => Action::PageDown,
+        KeyCode::Tab => Action::InsertTab,
+        KeyCode::Enter => Action::Newline,
+        KeyCode::Backspace => Action::Backspace,
+        KeyCode::Esc => Action::Cancel,
+        KeyCode::F(5) => Action::RunFile,
+        _ => return None,
+    };
+    Some(action)
+}
@@ -0,0 +1,19 @@
+// Buffers, editing commands, highlighting and the other pieces of the editor that
+// don't touch the terminal. The binary in main.rs drives them from its event loop.
+pub mod browser;
+pub mod buffer;
+pub mod cli;
+pub mod config;
+pub mod display;
+pub mod error;
+pub mod finder;
+pub mod git;
+pub mod grep;
+pub mod highlight;
+pub mod history;
+pub mod keymap;
+pub mod pane;
+pub mod runner;
+pub mod script;
+pub mod session;
+pub mod spell;
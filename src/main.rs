@@ -1,605 +1,738 @@
 use arboard::Clipboard;
+use axis::browser::FileBrowser;
+use axis::buffer::{byte_index, char_index, EditCommand, SearchKind, SearchOptions, SearchPattern, TextBuffer};
+use axis::cli;
+use axis::config::{self, Clock, Config, GutterAlign, TrailingNewline};
+use axis::display::{
+    clip_line, control_notation, describe_char, display_width, expand_tabs, expanded_index, format_age, format_clock,
+    local_time, printable, shorten_path, skip_columns,
+};
+use axis::error::EditorError;
+use axis::finder::FuzzyFinder;
+use axis::git::{self, LineStatus};
+use axis::grep::GrepSearch;
+use axis::highlight::{Highlighter, HighlighterRegistry, Language, SyntaxHighlighter, TokenType};
+use axis::history::History;
+use axis::keymap::{self, Action};
+use axis::pane::{
+    check_group_refs, detect_indent, enclosing_block, mixed_indent_line, line_at_percent, reindent_lines, retab_indent,
+    split_line_range, Cursor, Pane, Panes, BOM,
+};
+use axis::runner::{self, RunningCommand};
+use axis::script::{self, Command};
+use axis::session::{Session, SessionFile};
+use axis::spell::{self, SpellChecker};
 use crossterm::{
     cursor,
     event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
     execute, queue,
-    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
+    style::{
+        Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor,
+        SetUnderlineColor,
+    },
     terminal::{self, ClearType},
 };
+use regex::Regex;
+use std::collections::VecDeque;
 use std::fs;
-use std::io::{self, Write};
-use std::path::PathBuf;
+use std::io::{self, IsTerminal, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
-use unicode_width::UnicodeWidthStr;
-
-#[derive(Clone, Copy, PartialEq)]
-enum TokenType {
-    Keyword,
-    String,
-    Comment,
-    Number,
-    Function,
-    Type,
+
+enum EditorMode {
     Normal,
+    Search,
+    // Asks for the text to replace, then what to replace it with
+    ReplacePrompt,
+    SavePrompt,
+    OpenPrompt,
+    InsertFilePrompt,
+    WriteLinesPrompt,
+    GotoLinePrompt,
+    Messages,
+    RunOutput,
+    Confirm,
+    ShellFilter,
+    FileBrowser,
+    FuzzyFind,
+    GrepPrompt,
+    GrepResults,
 }
 
-impl TokenType {
-    fn color(&self) -> Color {
-        match self {
-            TokenType::Keyword => Color::Magenta,
-            TokenType::String => Color::Green,
-            TokenType::Comment => Color::DarkGrey,
-            TokenType::Number => Color::Cyan,
-            TokenType::Function => Color::Yellow,
-            TokenType::Type => Color::Blue,
-            TokenType::Normal => Color::White,
-        }
-    }
+// Actions waiting on an answer in `EditorMode::Confirm`
+enum ConfirmAction {
+    SaveAndRun,
+    // Save (s), discard (d) or cancel (c) unsaved changes before replacing the buffer
+    ReplaceBuffer(ReplaceWith),
+    // Open a file over the size limit anyway (y/n)
+    OpenLargeFile(ReplaceWith),
+    // Replace the match under the cursor (y), skip it (n), replace it and the rest (a)
+    // or stop (q)
+    ReplaceMatch(ReplaceState),
 }
 
-#[derive(Clone)]
-struct SyntaxHighlighter {
-    language: Language,
+// An interactive replace in progress
+struct ReplaceState {
+    from: String,
+    to: String,
+    // The search kind and whole-word setting when the replace started
+    options: SearchOptions,
+    // Where the match being asked about starts
+    at: (usize, usize),
+    count: usize,
 }
 
-#[derive(Clone, Copy, PartialEq)]
-enum Language {
-    Rust,
-    Python,
-    JavaScript,
-    Java,
-    C,
-    Bash,
-    Plain,
+// What to load into the active pane once it is safe to replace its buffer
+#[derive(Clone)]
+enum ReplaceWith {
+    // `line` is 1-based, `col` a character column on that line
+    File { name: String, line: Option<usize>, col: usize },
+    QueuedFile,
 }
 
-impl Language {
-    fn from_extension(ext: &str) -> Self {
-        match ext.to_lowercase().as_str() {
-            "rs" => Language::Rust,
-            "py" => Language::Python,
-            "js" | "jsx" | "ts" | "tsx" => Language::JavaScript,
-            "java" => Language::Java,
-            "c" | "h" | "cpp" | "hpp" | "cc" => Language::C,
-            "sh" | "bash" => Language::Bash,
-            _ => Language::Plain,
-        }
-    }
-
-    fn keywords(&self) -> &[&str] {
-        match self {
-            Language::Rust => &[
-                "fn", "let", "mut", "const", "static", "if", "else", "match", "for", "while",
-                "loop", "break", "continue", "return", "struct", "enum", "trait", "impl", "pub",
-                "use", "mod", "crate", "self", "super", "as", "move", "ref", "unsafe", "async",
-                "await", "dyn", "where", "type", "in",
-            ],
-            Language::Python => &[
-                "def", "class", "if", "elif", "else", "for", "while", "break", "continue",
-                "return", "try", "except", "finally", "with", "as", "import", "from", "pass",
-                "raise", "assert", "lambda", "yield", "async", "await", "global", "nonlocal",
-                "True", "False", "None", "and", "or", "not", "in", "is",
-            ],
-            Language::JavaScript => &[
-                "function", "const", "let", "var", "if", "else", "for", "while", "break",
-                "continue", "return", "class", "extends", "super", "this", "new", "try", "catch",
-                "finally", "throw", "async", "await", "import", "export", "from", "default",
-                "switch", "case", "typeof", "instanceof", "delete", "void", "yield",
-            ],
-            Language::Java => &[
-                "abstract", "assert", "boolean", "break", "byte", "case", "catch", "char", "class",
-                "const", "continue", "default", "do", "double", "else", "enum", "extends", "final",
-                "finally", "float", "for", "goto", "if", "implements", "import", "instanceof",
-                "int", "interface", "long", "native", "new", "package", "private", "protected",
-                "public", "return", "short", "static", "strictfp", "super", "switch", "synchronized",
-                "this", "throw", "throws", "transient", "try", "void", "volatile", "while",
-                "true", "false", "null",
-            ],
-            Language::C => &[
-                "int", "char", "float", "double", "void", "struct", "union", "enum", "if",
-                "else", "for", "while", "do", "break", "continue", "return", "switch", "case",
-                "default", "sizeof", "typedef", "static", "const", "extern", "auto", "register",
-                "volatile", "unsigned", "signed", "long", "short",
-            ],
-            Language::Bash => &[
-                "if", "then", "else", "elif", "fi", "for", "while", "do", "done", "case",
-                "esac", "function", "return", "exit", "break", "continue", "local", "export",
-                "source", "alias", "echo", "read", "test",
-            ],
-            Language::Plain => &[],
-        }
-    }
-
-    fn types(&self) -> &[&str] {
-        match self {
-            Language::Rust => &[
-                "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128",
-                "usize", "f32", "f64", "bool", "char", "str", "String", "Vec", "Option", "Result",
-                "Box", "Rc", "Arc", "Cell", "RefCell",
-            ],
-            Language::Java => &[
-                "byte", "short", "int", "long", "float", "double", "boolean", "char", "String",
-                "Integer", "Double", "List", "ArrayList", "Map", "HashMap", "Set", "HashSet",
-            ],
-            Language::C => &["int", "char", "float", "double", "void", "size_t", "uint8_t", "uint16_t", "uint32_t"],
-            _ => &[],
-        }
-    }
+// Text last copied or cut in the editor, kept even when there is no system clipboard.
+// A `linewise` copy of a whole line is pasted above the cursor's line.
+#[derive(Default)]
+struct Register {
+    text: String,
+    linewise: bool,
 }
 
-impl SyntaxHighlighter {
-    fn new(language: Language) -> Self {
-        Self { language }
-    }
-
-    fn highlight_line(&self, line: &str) -> Vec<(String, TokenType)> {
-        if self.language == Language::Plain {
-            return vec![(line.to_string(), TokenType::Normal)];
-        }
-
-        let mut tokens = Vec::new();
-        let mut current = String::new();
-        let mut chars = line.chars().peekable();
-        let mut in_string = false;
-        let mut string_char = ' ';
-        let mut in_comment = false;
-
-        while let Some(ch) = chars.next() {
-            // Handle comments
-            if !in_string && self.is_comment_start(ch, chars.peek().copied()) {
-                if !current.is_empty() {
-                    self.push_token(&mut tokens, current.clone());
-                    current.clear();
-                }
-                in_comment = true;
-                current.push(ch);
-                if let Some(next) = chars.peek() {
-                    if *next == '/' || *next == '*' {
-                        current.push(chars.next().unwrap());
-                    }
-                }
-                continue;
-            }
-
-            if in_comment {
-                current.push(ch);
-                continue;
-            }
-
-            // Handle strings
-            if (ch == '"' || ch == '\'' || ch == '`') && !in_string {
-                if !current.is_empty() {
-                    self.push_token(&mut tokens, current.clone());
-                    current.clear();
-                }
-                in_string = true;
-                string_char = ch;
-                current.push(ch);
-                continue;
-            }
-
-            if in_string {
-                current.push(ch);
-                if ch == string_char && current.chars().rev().nth(1) != Some('\\') {
-                    tokens.push((current.clone(), TokenType::String));
-                    current.clear();
-                    in_string = false;
-                }
-                continue;
-            }
-
-            // Handle numbers
-            if ch.is_numeric() && (current.is_empty() || current.chars().all(|c| c.is_numeric() || c == '.')) {
-                current.push(ch);
-                continue;
-            }
-
-            // Handle identifiers and keywords
-            if ch.is_alphanumeric() || ch == '_' {
-                current.push(ch);
-                continue;
-            }
+enum SplitMode {
+    None,
+    Horizontal,
+    Vertical,
+}
 
-            // We hit a separator
-            if !current.is_empty() {
-                self.push_token(&mut tokens, current.clone());
-                current.clear();
-            }
+// How long the event loop sleeps between checks for timed work
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+// Status bar name for text piped in on standard input
+const STDIN_NAME: &str = "[stdin]";
+const MESSAGE_TIMEOUT: Duration = Duration::from_secs(4);
+const ERROR_MESSAGE_TIMEOUT: Duration = Duration::from_secs(10);
+const MESSAGE_LOG_SIZE: usize = 100;
+// Shown in the last column of lines too long for the pane
+const TRUNCATION_MARKER: char = '>';
+const RULER_CHAR: char = '│';
+const RULER_COLOR: Color = Color::AnsiValue(238);
+// Control characters are drawn spelled out in this color instead of being sent raw
+const CONTROL_CHAR_COLOR: Color = Color::Magenta;
+// Smallest size either side of a split may shrink to
+const MIN_SPLIT_ROWS: u16 = 3;
+const MIN_SPLIT_COLS: u16 = 10;
+const SPLIT_RATIO_STEP: f32 = 0.05;
 
-            // Add the separator as is
-            tokens.push((ch.to_string(), TokenType::Normal));
-        }
+struct Editor {
+    panes: Panes,
+    should_quit: bool,
+    mode: EditorMode,
+    message: Option<String>,
+    // The message currently on screen, when it appeared, and whether it is an error
+    message_shown: Option<(String, Instant, bool)>,
+    message_log: VecDeque<(Instant, String)>,
+    overlay_scroll: usize,
+    input_buffer: String,
+    input_cursor: usize,
+    quit_warning_shown: bool,
+    needs_full_redraw: bool,
+    // Active pane and its scroll offsets (rows, then columns) at the last full redraw.
+    // Redrawing just the cursor line is only valid while these are unchanged.
+    drawn_view: Option<(usize, usize, usize)>,
+    // Terminal (width, height), refreshed on every draw and resize
+    screen_size: (u16, u16),
+    split_mode: SplitMode,
+    split_ratio: f32,
+    config: Config,
+    show_line_numbers: bool,
+    // Focus mode dims the active pane outside the block around the cursor; the block
+    // as last drawn, to notice when it changes
+    focus_mode: bool,
+    focus_block: Option<Range<usize>>,
+    // The clock as last drawn in the status bar, to redraw it when the minute changes
+    drawn_clock: Option<String>,
+    // --batch: write the buffer and exit instead of starting the interface
+    batch: bool,
+    // Zen mode hides line numbers and centers a column of `zen_width` text
+    zen_mode: bool,
+    // Plain-text panes with a file get its language on redraw, unless --language was given
+    detect_languages: bool,
+    // Highlighters picked by file extension ahead of the built-in languages
+    highlighters: HighlighterRegistry,
+    // Brackets without a partner are drawn in red; (row, col) of each, per pane
+    bracket_check: bool,
+    unbalanced_brackets: Vec<Vec<(usize, usize)>>,
+    clipboard: Option<Clipboard>,
+    register: Register,
+    spell_check: bool,
+    spell_checker: Option<SpellChecker>,
+    spell_loader: Option<JoinHandle<SpellChecker>>,
+    running: Option<RunningCommand>,
+    pending_confirm: Option<ConfirmAction>,
+    // Files from the command line that didn't fit into the panes, opened with Alt-F
+    queued_files: VecDeque<(String, Option<usize>)>,
+    file_browser: Option<FileBrowser>,
+    fuzzy_finder: Option<FuzzyFinder>,
+    // The last project-wide search, kept so its results can be shown again
+    grep: Option<GrepSearch>,
+    search_whole_word: bool,
+    search_kind: SearchKind,
+    // The text to replace once it's been entered, while asking for the replacement
+    replace_query: Option<String>,
+    // The search prompt is for a backward search
+    search_backward: bool,
+    // Alt-V was pressed: the next key is inserted verbatim
+    literal_next: bool,
+    search_history: History,
+    open_history: History,
+    save_history: History,
+}
 
-        // Handle remaining content
-        if in_comment {
-            tokens.push((current, TokenType::Comment));
-        } else if in_string {
-            tokens.push((current, TokenType::String));
-        } else if !current.is_empty() {
-            self.push_token(&mut tokens, current);
+impl Editor {
+    fn new() -> Self {
+        let config = Config::load();
+        // Files matching a section with a broken rule stay plain text
+        let mut highlighters = HighlighterRegistry::default();
+        let rule_errors: Vec<String> = config
+            .highlight_rules
+            .iter()
+            .filter_map(|rules| {
+                let error = highlighters.register_rules(rules).err()?;
+                Some(format!("[highlight.{}] {}", rules.name, error))
+            })
+            .collect();
+        let mut editor = Self {
+            panes: Panes::new(Pane::with_config(&config)),
+            should_quit: false,
+            mode: EditorMode::Normal,
+            message: None,
+            message_shown: None,
+            message_log: VecDeque::new(),
+            overlay_scroll: 0,
+            input_buffer: String::new(),
+            input_cursor: 0,
+            quit_warning_shown: false,
+            needs_full_redraw: true,
+            drawn_view: None,
+            screen_size: (80, 24),
+            split_mode: SplitMode::None,
+            split_ratio: config.split_ratio.clamp(0.1, 0.9),
+            show_line_numbers: config.show_line_numbers,
+            bracket_check: config.bracket_check,
+            config,
+            focus_mode: false,
+            drawn_clock: None,
+            batch: false,
+            zen_mode: false,
+            detect_languages: true,
+            highlighters,
+            focus_block: None,
+            unbalanced_brackets: Vec::new(),
+            clipboard: Clipboard::new().ok(),
+            register: Register::default(),
+            spell_check: false,
+            spell_checker: None,
+            spell_loader: None,
+            running: None,
+            pending_confirm: None,
+            queued_files: VecDeque::new(),
+            file_browser: None,
+            fuzzy_finder: None,
+            grep: None,
+            search_whole_word: false,
+            search_kind: SearchKind::Literal,
+            replace_query: None,
+            search_backward: false,
+            literal_next: false,
+            search_history: History::load("search_history"),
+            open_history: History::load("open_history"),
+            save_history: History::load("save_history"),
+        };
+        for error in rule_errors {
+            editor.set_error(error);
         }
+        editor
+    }
 
-        tokens
+    // Errors stay on screen longer than ordinary messages
+    fn set_error(&mut self, msg: String) {
+        self.log_message(msg.clone());
+        self.message_shown = Some((msg.clone(), Instant::now(), true));
+        self.message = Some(msg);
     }
 
-    fn is_comment_start(&self, ch: char, next: Option<char>) -> bool {
-        match self.language {
-            Language::Rust | Language::C | Language::JavaScript | Language::Java => {
-                ch == '/' && (next == Some('/') || next == Some('*'))
-            }
-            Language::Python | Language::Bash => ch == '#',
-            Language::Plain => false,
+    fn log_message(&mut self, msg: String) {
+        if self.message_log.len() == MESSAGE_LOG_SIZE {
+            self.message_log.pop_front();
         }
+        self.message_log.push_back((Instant::now(), msg));
     }
 
-    fn push_token(&self, tokens: &mut Vec<(String, TokenType)>, token: String) {
-        let token_type = if self.language.keywords().contains(&token.as_str()) {
-            TokenType::Keyword
-        } else if self.language.types().contains(&token.as_str()) {
-            TokenType::Type
-        } else if token.chars().all(|c| c.is_numeric() || c == '.') {
-            TokenType::Number
-        } else {
-            TokenType::Normal
+    // Note a newly displayed message so it can expire and show up in the log
+    fn track_message(&mut self) {
+        let Some(msg) = &self.message else {
+            self.message_shown = None;
+            return;
         };
-
-        tokens.push((token, token_type));
-    }
-}
-
-// Command pattern for undo/redo
-#[derive(Clone, Debug)]
-enum EditCommand {
-    InsertChar { row: usize, col: usize, ch: char },
-    DeleteChar { row: usize, col: usize, ch: char },
-    InsertNewline { row: usize, col: usize },
-    DeleteNewline { row: usize, deleted_line: String },
-    ClearAll { old_content: Vec<String> }, // Add this
-}
-
-impl EditCommand {
-    fn undo(&self, buffer: &mut TextBuffer) {
-    match self {
-        EditCommand::InsertChar { row, col, .. } => {
-            if *row < buffer.lines.len() && *col < buffer.lines[*row].len() {
-                buffer.lines[*row].remove(*col);
-            }
+        if self.message_shown.as_ref().is_some_and(|(shown, _, _)| shown == msg) {
+            return;
         }
-        EditCommand::DeleteChar { row, col, ch } => {
-            if *row < buffer.lines.len() {
-                buffer.lines[*row].insert(*col, *ch);
-            }
-        }
-        EditCommand::InsertNewline { row, col: _ } => {
-            if *row + 1 < buffer.lines.len() {
-                let line = buffer.lines.remove(*row + 1);
-                buffer.lines[*row].push_str(&line);
-            }
-        }
-        EditCommand::DeleteNewline { row, deleted_line } => {
-            if *row < buffer.lines.len() {
-                let current_len = buffer.lines[*row].len();
-                let deleted_len = deleted_line.len();
-                buffer.lines[*row].truncate(current_len - deleted_len);
-                buffer.lines.insert(*row + 1, deleted_line.clone());
-            }
-        }
-        EditCommand::ClearAll { old_content } => {
-            buffer.lines = old_content.clone();
+        let msg = msg.clone();
+        self.message_shown = Some((msg.clone(), Instant::now(), false));
+        // Prompt modes use the message as their label, which is not worth logging
+        if matches!(self.mode, EditorMode::Normal) {
+            self.log_message(msg);
         }
     }
-}
 
-fn redo(&self, buffer: &mut TextBuffer) {
-    match self {
-        EditCommand::InsertChar { row, col, ch } => {
-            if *row < buffer.lines.len() {
-                buffer.lines[*row].insert(*col, *ch);
-            }
+    // Clear the message once it has been shown long enough. Returns true if it was cleared.
+    fn expire_message(&mut self) -> bool {
+        self.track_message();
+        if !matches!(self.mode, EditorMode::Normal) {
+            return false;
         }
-        EditCommand::DeleteChar { row, col, .. } => {
-            if *row < buffer.lines.len() && *col < buffer.lines[*row].len() {
-                buffer.lines[*row].remove(*col);
-            }
-        }
-        EditCommand::InsertNewline { row, col } => {
-            if *row < buffer.lines.len() {
-                let current_line = &buffer.lines[*row];
-                let new_line = current_line[*col..].to_string();
-                buffer.lines[*row].truncate(*col);
-                buffer.lines.insert(*row + 1, new_line);
-            }
-        }
-        EditCommand::DeleteNewline { row, .. } => {
-            if *row > 0 && *row < buffer.lines.len() {
-                let line = buffer.lines.remove(*row);
-                buffer.lines[*row - 1].push_str(&line);
+        if let Some((_, shown_at, is_error)) = &self.message_shown {
+            let timeout = if *is_error { ERROR_MESSAGE_TIMEOUT } else { MESSAGE_TIMEOUT };
+            if shown_at.elapsed() >= timeout {
+                self.message = None;
+                self.message_shown = None;
+                return true;
             }
         }
-        EditCommand::ClearAll { .. } => {
-            buffer.lines = vec![String::new()];
-        }
+        false
     }
-}
-}
 
-#[derive(Clone)]
-struct TextBuffer {
-    lines: Vec<String>,
-}
-
-impl TextBuffer {
-    fn new() -> Self {
-        Self {
-            lines: vec![String::new()],
-        }
+    fn active_pane(&self) -> &Pane {
+        self.panes.active()
     }
 
-    fn from_string(content: String) -> Self {
-        let lines: Vec<String> = content.lines().map(|s| s.to_string()).collect();
-        Self {
-            lines: if lines.is_empty() {
-                vec![String::new()]
-            } else {
-                lines
-            },
-        }
+    fn active_pane_mut(&mut self) -> &mut Pane {
+        self.panes.active_mut()
     }
 
-    fn to_string(&self) -> String {
-        self.lines.join("\n")
+    fn split_vertical(&mut self) {
+        self.split(SplitMode::Vertical, false);
     }
 
-    fn insert_char(&mut self, row: usize, col: usize, ch: char) {
-        if row < self.lines.len() {
-            self.lines[row].insert(col, ch);
+    // Open a second pane, either empty or as another view of the active pane's buffer
+    // that shares its text and undo history but has its own cursor and scroll position
+    fn split(&mut self, mode: SplitMode, same_buffer: bool) {
+        if self.panes.count() >= 2 {
+            return;
         }
+        let pane = if same_buffer {
+            let mut view = self.active_pane().clone();
+            view.selection_start = None;
+            view.locked = false;
+            view
+        } else {
+            Pane::with_config(&self.config)
+        };
+        self.panes.push(pane);
+        self.split_mode = mode;
+        self.fit_panes();
+        self.needs_full_redraw = true;
     }
 
-    fn delete_char(&mut self, row: usize, col: usize) -> Option<char> {
-        if row < self.lines.len() && col > 0 && col <= self.lines[row].len() {
-            Some(self.lines[row].remove(col - 1))
-        } else {
-            None
+    fn close_split(&mut self) {
+        if self.panes.close_active() {
+            self.split_mode = SplitMode::None;
+            self.fit_panes();
+            self.needs_full_redraw = true;
         }
     }
 
-    fn insert_newline(&mut self, row: usize, col: usize) {
-        if row < self.lines.len() {
-            let current_line = &self.lines[row];
-            let new_line = current_line[col..].to_string();
-            self.lines[row].truncate(col);
-            self.lines.insert(row + 1, new_line);
-        }
+    // Keep every cursor on screen after the pane sizes change, without resetting the
+    // scroll position of panes whose cursor is still visible
+    fn fit_panes(&mut self) {
+        let heights: Vec<usize> = self.pane_areas().iter().map(|&(_, _, _, h)| h as usize).collect();
+        self.panes.fit_to_heights(&heights);
     }
 
-    fn delete_newline(&mut self, row: usize) -> Option<String> {
-        if row > 0 && row < self.lines.len() {
-            let line = self.lines.remove(row);
-            self.lines[row - 1].push_str(&line);
-            Some(line)
-        } else {
-            None
+    fn next_pane(&mut self) {
+        if self.panes.count() > 1 {
+            self.panes.focus_next();
+            self.needs_full_redraw = true;
         }
     }
 
-    fn line_count(&self) -> usize {
-        self.lines.len()
+    // Size of the first pane along the split axis, given the space left after the divider
+    fn split_size(&self, available: u16, min: u16) -> u16 {
+        let min = min.min(available / 2);
+        ((available as f32 * self.split_ratio).round() as u16).clamp(min, available - min)
     }
 
-    fn get_line(&self, row: usize) -> Option<&String> {
-        self.lines.get(row)
+    // Move the divider; a positive delta grows the first (top/left) pane
+    fn resize_split(&mut self, delta: f32) {
+        if matches!(self.split_mode, SplitMode::None) {
+            return;
+        }
+        self.split_ratio = (self.split_ratio + delta).clamp(0.1, 0.9);
+        self.config.split_ratio = self.split_ratio;
+        if let Err(e) = config::persist("split_ratio", &format!("{:.2}", self.split_ratio)) {
+            self.set_error(format!("Error saving config: {}", e));
+        }
+        self.fit_panes();
+        self.needs_full_redraw = true;
     }
 
-    fn search(&self, query: &str, start_row: usize, start_col: usize) -> Option<(usize, usize)> {
-        if query.is_empty() {
-            return None;
+    // Swap the active pane with its neighbour, keeping focus on the same buffer
+    fn swap_panes(&mut self) {
+        if self.panes.count() > 1 {
+            self.panes.swap_with_next();
+            self.fit_panes();
+            self.needs_full_redraw = true;
         }
+    }
 
-        // Search from current position to end
-        for row in start_row..self.lines.len() {
-            let search_col = if row == start_row { start_col } else { 0 };
-            if let Some(col) = self.lines[row][search_col..].find(query) {
-                return Some((row, search_col + col));
-            }
+    // Rewrite leading indentation as spaces or tabs across the selection or whole buffer
+    fn convert_indentation(&mut self, to_spaces: bool) {
+        let pane = self.active_pane_mut();
+        let (first, last) = pane
+            .selection_bounds()
+            .map(|(start, end)| (start.0, end.0))
+            .unwrap_or((0, pane.line_count() - 1));
+        let old = pane.buffer().lines[first..=last].to_vec();
+        let new: Vec<String> = old
+            .iter()
+            .map(|line| retab_indent(line, pane.tab_width, to_spaces))
+            .collect();
+        let changed = old.iter().zip(&new).filter(|(a, b)| a != b).count();
+
+        pane.use_spaces = to_spaces;
+        if changed > 0 {
+            pane.execute_command(EditCommand::ReplaceLines { start: first, old, new });
+            pane.cursor.x = pane.floor_col(pane.cursor.y, pane.cursor.x);
+            self.needs_full_redraw = true;
         }
+        let style = if to_spaces { "spaces" } else { "tabs" };
+        self.message = Some(format!("Converted {} line(s) to {}", changed, style));
+    }
 
-        // Wrap around: search from beginning to start position
-        for row in 0..=start_row {
-            let end_col = if row == start_row { start_col } else { self.lines[row].len() };
-            if let Some(col) = self.lines[row][..end_col].find(query) {
-                return Some((row, col));
-            }
+    // Recompute indentation from nesting for the selected lines or the whole buffer
+    fn reindent(&mut self) {
+        let pane = self.active_pane_mut();
+        let language = pane.highlighter.language();
+        if language.is_prose() || language == Language::Diff {
+            self.message = Some(format!("Nothing to reindent in {}", language.name()));
+            return;
         }
-
-        None
+        let (first, last) = pane
+            .selection_bounds()
+            .map(|(start, end)| (start.0, end.0))
+            .unwrap_or((0, pane.line_count() - 1));
+        let old = pane.buffer().lines[first..=last].to_vec();
+        let new = reindent_lines(&old, language, &pane.indent_unit(), pane.tab_width);
+        let changed = old.iter().zip(&new).filter(|(a, b)| a != b).count();
+
+        if changed > 0 {
+            pane.execute_command(EditCommand::ReplaceLines { start: first, old, new });
+            pane.cursor.x = pane.floor_col(pane.cursor.y, pane.cursor.x);
+            self.needs_full_redraw = true;
+        }
+        self.message = Some(format!("Reindented {} line(s)", changed));
     }
-}
-
-#[derive(Clone)]
-struct Cursor {
-    x: usize,
-    y: usize,
-}
 
-enum EditorMode {
-    Normal,
-    Search,
-    SavePrompt,
-    OpenPrompt,
-    GotoLinePrompt,
-}
+    fn toggle_trailing_newline(&mut self) {
+        let pane = self.active_pane_mut();
+        pane.trailing_newline = !pane.trailing_newline;
+        pane.set_modified(true);
+        let state = if pane.trailing_newline { "added" } else { "removed" };
+        self.message = Some(format!("Final newline will be {} on save", state));
+    }
 
-#[derive(Clone)]
-struct Pane {
-    buffer: TextBuffer,
-    cursor: Cursor,
-    offset_y: usize,
-    undo_stack: Vec<EditCommand>,
-    redo_stack: Vec<EditCommand>,
-    current_file: Option<PathBuf>,
-    modified: bool,
-    search_query: String,
-    last_search_pos: Option<(usize, usize)>,
-    highlighter: SyntaxHighlighter,
-    selection_start: Option<(usize, usize)>,
-}
+    fn toggle_bom(&mut self) {
+        let pane = self.active_pane_mut();
+        pane.bom = !pane.bom;
+        pane.set_modified(true);
+        let state = if pane.bom { "added" } else { "removed" };
+        self.message = Some(format!("Byte order mark will be {} on save", state));
+    }
 
-impl Pane {
-    fn new() -> Self {
-    Self {
-        buffer: TextBuffer::new(),
-        cursor: Cursor { x: 0, y: 0 },
-        offset_y: 0,
-        undo_stack: Vec::new(),
-        redo_stack: Vec::new(),
-        current_file: None,
-        modified: false,
-        search_query: String::new(),
-        last_search_pos: None,
-        highlighter: SyntaxHighlighter {
-            language: Language::Plain,
-        },
-        selection_start: None,
+    fn toggle_line_numbers(&mut self) {
+        self.show_line_numbers = !self.show_line_numbers;
+        self.needs_full_redraw = true;
     }
+
+    fn toggle_bracket_check(&mut self) {
+        self.bracket_check = !self.bracket_check;
+        self.message = Some(format!("Unbalanced bracket check {}", if self.bracket_check { "on" } else { "off" }));
+        self.needs_full_redraw = true;
     }
 
+    fn toggle_zen_mode(&mut self) {
+        self.zen_mode = !self.zen_mode;
+        self.message = Some(format!("Zen mode {}", if self.zen_mode { "on" } else { "off" }));
+        self.needs_full_redraw = true;
+    }
 
-    fn execute_command(&mut self, command: EditCommand) {
-        command.redo(&mut self.buffer);
-        self.undo_stack.push(command);
-        self.redo_stack.clear();
-        self.modified = true;
+    fn toggle_focus_mode(&mut self) {
+        self.focus_mode = !self.focus_mode;
+        self.focus_block = None;
+        self.message = Some(format!("Focus mode {}", if self.focus_mode { "on" } else { "off" }));
+        self.needs_full_redraw = true;
     }
 
-    fn undo(&mut self) {
-        if let Some(command) = self.undo_stack.pop() {
-            command.undo(&mut self.buffer);
-            self.redo_stack.push(command);
-            self.modified = !self.undo_stack.is_empty();
+    fn toggle_spell_check(&mut self) {
+        self.spell_check = !self.spell_check;
+        if self.spell_check && self.spell_checker.is_none() && self.spell_loader.is_none() {
+            // Load the dictionary in the background so the UI stays responsive
+            self.spell_loader = Some(thread::spawn(SpellChecker::load));
+            self.message = Some("Loading dictionary...".to_string());
+        } else if self.spell_check {
+            self.message = Some("Spell check on".to_string());
+        } else {
+            self.message = Some("Spell check off".to_string());
         }
+        self.needs_full_redraw = true;
     }
 
-    fn redo(&mut self) {
-        if let Some(command) = self.redo_stack.pop() {
-            command.redo(&mut self.buffer);
-            self.undo_stack.push(command);
-            self.modified = true;
+    fn poll_spell_loader(&mut self) {
+        if !self.spell_loader.as_ref().is_some_and(|h| h.is_finished()) {
+            return;
+        }
+        if let Some(handle) = self.spell_loader.take()
+            && let Ok(checker) = handle.join()
+        {
+            if checker.is_empty() {
+                self.message = Some("No dictionary found; add words.txt to the config dir".to_string());
+            } else if self.spell_check {
+                self.message = Some("Spell check on".to_string());
+            }
+            self.spell_checker = Some(checker);
+            self.needs_full_redraw = true;
         }
     }
 
-    fn adjust_scroll(&mut self, visible_lines: usize) {
-        if self.cursor.y < self.offset_y {
-            self.offset_y = self.cursor.y;
-        } else if self.cursor.y >= self.offset_y + visible_lines {
-            self.offset_y = self.cursor.y - visible_lines + 1;
+    fn active_spell_checker(&self) -> Option<&SpellChecker> {
+        if self.spell_check {
+            self.spell_checker.as_ref()
+        } else {
+            None
         }
     }
-}
 
-enum SplitMode {
-    None,
-    Horizontal,
-    Vertical,
-}
+    // Byte ranges of misspelled words in a highlighted line
+    fn misspelled_ranges(&self, line: &str, tokens: &[(Range<usize>, TokenType)], language: Language) -> Vec<Range<usize>> {
+        let Some(checker) = self.active_spell_checker() else {
+            return Vec::new();
+        };
+        let mut ranges = Vec::new();
+        for (span, token_type) in tokens {
+            if language.is_prose() || matches!(token_type, TokenType::Comment | TokenType::String) {
+                ranges.extend(
+                    checker
+                        .misspelled(&line[span.clone()])
+                        .into_iter()
+                        .map(|r| r.start + span.start..r.end + span.start),
+                );
+            }
+        }
+        ranges
+    }
 
-struct Editor {
-    panes: Vec<Pane>,
-    active_pane: usize,
-    should_quit: bool,
-    mode: EditorMode,
-    message: Option<String>,
-    input_buffer: String,
-    quit_warning_shown: bool,
-    needs_full_redraw: bool,
-    split_mode: SplitMode,
-    show_line_numbers: bool,
-    clipboard: Option<Clipboard>,
-}
+    fn next_misspelling(&mut self) {
+        if self.active_spell_checker().is_none() {
+            self.message = Some("Spell check is off (Alt-S)".to_string());
+            return;
+        }
+        let visible_lines = self.visible_lines();
+
+        let found = {
+            let pane = self.active_pane();
+            let line_count = pane.line_count();
+            let start_row = pane.cursor.y;
+            let start_col = byte_index(&pane.buffer().lines[start_row], pane.cursor.x);
+            // Visit every line once, then the start of the cursor line again to wrap around
+            (0..=line_count).find_map(|i| {
+                let row = (start_row + i) % line_count;
+                let line = &pane.buffer().lines[row];
+                let tokens = pane.highlighter.highlight_line(line);
+                self.misspelled_ranges(line, &tokens, pane.highlighter.language())
+                    .into_iter()
+                    .find(|r| match i {
+                        0 => r.start > start_col,
+                        _ if i == line_count => r.start <= start_col,
+                        _ => true,
+                    })
+                    .map(|r| (row, char_index(line, r.start), line[r].to_string()))
+            })
+        };
 
-impl Editor {
-    fn new() -> Self {
-        Self {
-            panes: vec![Pane::new()],
-            active_pane: 0,
-            should_quit: false,
-            mode: EditorMode::Normal,
-            message: None,
-            input_buffer: String::new(),
-            quit_warning_shown: false,
-            needs_full_redraw: true,
-            split_mode: SplitMode::None,
-            show_line_numbers: true,
-            clipboard: Clipboard::new().ok(),
+        if let Some((row, col, word)) = found {
+            let pane = self.active_pane_mut();
+            pane.cursor.y = row;
+            pane.cursor.x = col;
+            pane.adjust_scroll(visible_lines);
+            self.message = Some(format!("Misspelled: {}", word));
+            self.needs_full_redraw = true;
+        } else {
+            self.message = Some("No misspellings".to_string());
         }
     }
 
-    fn active_pane(&self) -> &Pane {
-        &self.panes[self.active_pane]
+    // Move to the next or previous changed line, for reviewing edits before saving
+    fn jump_to_change(&mut self, forward: bool) {
+        let visible_lines = self.visible_lines();
+        let pane = self.active_pane_mut();
+        if pane.line_status.is_empty() {
+            self.message = Some("No change markers for this file".to_string());
+            return;
+        }
+        match pane.next_change(forward) {
+            Some(row) => {
+                pane.move_to_row(row, visible_lines);
+                self.needs_full_redraw = true;
+            }
+            None => self.message = Some("No more changes".to_string()),
+        }
     }
 
-    fn active_pane_mut(&mut self) -> &mut Pane {
-        &mut self.panes[self.active_pane]
+    // Keep copied or cut text, on the system clipboard too when there is one
+    fn set_register(&mut self, text: String, linewise: bool) {
+        if let Some(clipboard) = &mut self.clipboard {
+            let _ = clipboard.set_text(text.clone());
+        }
+        self.register = Register { text, linewise };
     }
 
-    fn split_horizontal(&mut self) {
-        if self.panes.len() < 2 {
-            // Create a new empty pane instead of cloning
-            self.panes.push(Pane::new());
-            self.split_mode = SplitMode::Horizontal;
-            self.needs_full_redraw = true;
+    fn jump_to_hunk(&mut self, forward: bool) {
+        let visible_lines = self.visible_lines();
+        let pane = self.active_pane_mut();
+        match pane.next_hunk(forward) {
+            Some(row) => {
+                pane.move_to_row(row, visible_lines);
+                pane.cursor.x = 0;
+                self.needs_full_redraw = true;
+            }
+            None => self.message = Some("No more hunks".to_string()),
         }
     }
 
-    fn split_vertical(&mut self) {
-        if self.panes.len() < 2 {
-            // Create a new empty pane instead of cloning
-            self.panes.push(Pane::new());
-            self.split_mode = SplitMode::Vertical;
+    fn add_word_to_dictionary(&mut self) {
+        let word = {
+            let pane = self.active_pane();
+            pane.buffer()
+                .get_line(pane.cursor.y)
+                .and_then(|line| spell::word_at(line, byte_index(line, pane.cursor.x)).map(|r| line[r].to_string()))
+        };
+        let Some(word) = word else {
+            self.message = Some("No word under cursor".to_string());
+            return;
+        };
+        if let Some(checker) = self.spell_checker.as_mut().filter(|_| self.spell_check) {
+            match checker.add_word(&word) {
+                Ok(()) => self.message = Some(format!("Added '{}' to personal dictionary", word)),
+                Err(e) => self.set_error(format!("Error saving dictionary: {}", e)),
+            }
             self.needs_full_redraw = true;
+        } else {
+            self.message = Some("Spell check is off (Alt-S)".to_string());
         }
     }
 
-    fn close_split(&mut self) {
-        if self.panes.len() > 1 {
-            self.panes.remove(self.active_pane);
-            if self.active_pane >= self.panes.len() {
-                self.active_pane = self.panes.len() - 1;
-            }
-            self.split_mode = SplitMode::None;
-            self.needs_full_redraw = true;
+    fn get_line_number_width(&self) -> usize {
+        self.gutter_width(self.active_pane())
+    }
+
+    // Line numbers plus a column for git change markers when the pane has any
+    fn gutter_width(&self, pane: &Pane) -> usize {
+        let signs = Self::sign_width(pane);
+        if !self.line_numbers_shown() {
+            return signs;
         }
+        self.config.gutter_padding
+            + Self::number_width(pane)
+            + display_width(&self.config.gutter_separator)
+            + signs
     }
 
-    fn next_pane(&mut self) {
-        if self.panes.len() > 1 {
-            self.active_pane = (self.active_pane + 1) % self.panes.len();
-            self.needs_full_redraw = true;
+    // Zen mode hides line numbers without changing the setting it returns to
+    fn line_numbers_shown(&self) -> bool {
+        self.show_line_numbers && !self.zen_mode
+    }
+
+    fn number_width(pane: &Pane) -> usize {
+        format!("{}", pane.line_count()).len()
+    }
+
+    // The number (or `~` past the content) with the configured padding, alignment and
+    // separator. Draws nothing when line numbers are hidden.
+    fn draw_line_number(
+        &self,
+        stdout: &mut io::Stdout,
+        pane: &Pane,
+        label: Option<usize>,
+        active: bool,
+    ) -> io::Result<()> {
+        if !self.line_numbers_shown() {
+            return Ok(());
         }
+        let config = &self.config;
+        let color = if active && label.is_some() { config.gutter_color } else { config.gutter_inactive_color };
+        let label = label.map_or("~".to_string(), |n| n.to_string());
+        let width = Self::number_width(pane);
+        let number = match config.gutter_align {
+            GutterAlign::Left => format!("{:<width$}", label),
+            GutterAlign::Right => format!("{:>width$}", label),
+        };
+        queue!(
+            stdout,
+            Print(" ".repeat(config.gutter_padding)),
+            SetForegroundColor(color),
+            Print(number),
+            Print(&config.gutter_separator),
+            ResetColor
+        )
     }
 
-    fn toggle_line_numbers(&mut self) {
-        self.show_line_numbers = !self.show_line_numbers;
-        self.needs_full_redraw = true;
+    fn sign_width(pane: &Pane) -> usize {
+        if pane.line_status.is_empty() { 0 } else { 1 }
     }
 
-    fn get_line_number_width(&self) -> usize {
-        if !self.show_line_numbers {
-            return 0;
+    fn draw_git_sign(&self, stdout: &mut io::Stdout, pane: &Pane, file_row: usize) -> io::Result<()> {
+        if Self::sign_width(pane) == 0 {
+            return Ok(());
         }
-        let max_line = self.active_pane().buffer.line_count();
-        format!("{}", max_line).len() + 1
+        let (sign, color) = match pane.line_status.get(file_row) {
+            Some(LineStatus::Added) => ('+', Color::Green),
+            Some(LineStatus::Modified) => ('~', Color::Yellow),
+            Some(LineStatus::DeletedBelow) => ('\u{2581}', Color::Red),
+            _ => (' ', Color::Reset),
+        };
+        queue!(stdout, SetForegroundColor(color), Print(sign), ResetColor)
+    }
+
+    // Refresh the branch name and change markers after the file was read or written
+    fn refresh_git_status(pane: &mut Pane) {
+        let Some(path) = pane.current_file.clone() else {
+            return;
+        };
+        pane.git_branch = git::branch_for(&path);
+        pane.line_status = if pane.git_branch.is_some() {
+            git::line_changes(&path, &pane.buffer().lines)
+        } else {
+            Vec::new()
+        };
     }
 
-    fn save_file(&mut self) -> io::Result<()> {
+    fn save_file(&mut self) -> Result<(), EditorError> {
+        if self.active_pane().read_only {
+            self.message = Some("Buffer is read-only".to_string());
+            return Ok(());
+        }
         let pane = self.active_pane_mut();
         if let Some(path) = &pane.current_file.clone() {
-            fs::write(path, pane.buffer.to_string())?;
-            pane.modified = false;
-            self.message = Some(format!("Saved to {}", path.display()));
+            pane.save_to(path)?;
+            pane.set_modified(false);
+            Self::refresh_git_status(pane);
+            self.report_saved(path);
             Ok(())
         } else {
             self.mode = EditorMode::SavePrompt;
+            self.save_history.reset();
             self.input_buffer.clear();
             self.message = Some("Enter filename: ".to_string());
             self.needs_full_redraw = true;
@@ -607,125 +740,857 @@ impl Editor {
         }
     }
 
-    fn save_file_as(&mut self, filename: String) -> io::Result<()> {
+    fn save_file_as(&mut self, filename: String) -> Result<(), EditorError> {
         let path = PathBuf::from(filename);
+        check_regular_file(&path)?;
         let pane = self.active_pane_mut();
-        fs::write(&path, pane.buffer.to_string())?;
+        pane.save_to(&path)?;
+        pane.is_symlink = is_symlink(&path);
         pane.current_file = Some(path.clone());
-        pane.modified = false;
-        self.message = Some(format!("Saved to {}", path.display()));
+        pane.set_modified(false);
+        Self::refresh_git_status(pane);
+        self.report_saved(&path);
         Ok(())
     }
 
-    fn open_file(&mut self, filename: String) -> io::Result<()> {
-    let path = PathBuf::from(filename);
-    let content = fs::read_to_string(&path)?;
-    let pane = self.active_pane_mut();
-    pane.buffer = TextBuffer::from_string(content);
-    pane.current_file = Some(path.clone());
-    pane.modified = false;
-    pane.cursor = Cursor { x: 0, y: 0 };
-    pane.offset_y = 0;
-    pane.undo_stack.clear();
-    pane.redo_stack.clear();
-    
-    // Detect language from file extension
-    if let Some(ext) = path.extension() {
-        if let Some(ext_str) = ext.to_str() {
-            pane.highlighter = SyntaxHighlighter::new(Language::from_extension(ext_str));
+    // "Saved to" message, with the mixed indentation warning if that's checked on save
+    fn report_saved(&mut self, path: &Path) {
+        let warning = self.config.indent_check_on_save.then(|| self.mixed_indent_warning()).flatten();
+        match warning {
+            Some(warning) => self.set_error(format!("Saved to {}. {}", path.display(), warning)),
+            None => self.message = Some(format!("Saved to {}", path.display())),
         }
     }
-    
-    self.message = Some(format!("Opened {}", path.display()));
+
+    // A warning naming the first line where the active buffer's indentation switches
+    // between tabs and spaces
+    fn mixed_indent_warning(&self) -> Option<String> {
+        let row = mixed_indent_line(&self.active_pane().buffer().lines)?;
+        Some(format!("Indentation mixes tabs and spaces (line {}); Alt-L converts it", row + 1))
+    }
+
+    fn open_file(&mut self, filename: String) -> Result<(), EditorError> {
+        let path = PathBuf::from(filename);
+        if path.is_dir() {
+            return self.open_file_browser(&path);
+        }
+        let label = path.display().to_string();
+        check_regular_file(&path)?;
+        let content = String::from_utf8(fs::read(&path)?).map_err(|_| EditorError::Decode(label.clone()))?;
+        let symlink = is_symlink(&path);
+        self.load_content(content, Some(path), &label);
+        self.active_pane_mut().is_symlink = symlink;
+        Ok(())
+    }
+
+    // Insert a file's text at the cursor as one undoable edit, without its byte order
+    // mark or carriage returns
+    fn insert_file(&mut self, name: &str) -> Result<(), EditorError> {
+        let path = Path::new(name);
+        check_regular_file(path)?;
+        let content = String::from_utf8(fs::read(path)?).map_err(|_| EditorError::Decode(name.to_string()))?;
+        let text = content.strip_prefix(BOM).unwrap_or(&content).replace('\r', "");
+        let visible_lines = self.visible_lines();
+        let pane = self.active_pane_mut();
+        pane.insert_str_at_cursor(&text);
+        pane.adjust_scroll(visible_lines);
+        self.message = Some(format!("Inserted {} ({} lines)", name, text.lines().count()));
+        Ok(())
+    }
+
+    // Write some lines of the active buffer to another file: those given as `START,END FILE`,
+    // else the selected ones. The buffer stays attached to its own file.
+    fn write_lines(&mut self, input: &str) -> Result<(), EditorError> {
+        let pane = self.active_pane();
+        let line_count = pane.line_count();
+        let (rows, name) = match split_line_range(input) {
+            Some((start, end, name)) => {
+                if start == 0 || start > end || end > line_count {
+                    return Err(EditorError::Parse(format!("Lines must be within 1-{}", line_count)));
+                }
+                (start - 1..=end - 1, name)
+            }
+            None => match pane.selected_rows() {
+                Some(rows) => (rows, input.trim()),
+                None => return Err(EditorError::Parse("Select lines or give a range like 10,20 FILE".to_string())),
+            },
+        };
+        if name.is_empty() {
+            return Err(EditorError::Parse("No file name given".to_string()));
+        }
+        let path = Path::new(name);
+        check_regular_file(path)?;
+        pane.write_lines(rows.clone(), path)?;
+        self.message = Some(format!("Wrote {} line(s) to {}", rows.count(), name));
+        Ok(())
+    }
+
+    fn open_file_browser(&mut self, dir: &Path) -> Result<(), EditorError> {
+        self.file_browser = Some(FileBrowser::open(dir)?);
+        self.mode = EditorMode::FileBrowser;
+        self.overlay_scroll = 0;
+        self.needs_full_redraw = true;
+        Ok(())
+    }
+
+    // List the files under the working directory and filter them as the user types
+    fn open_fuzzy_finder(&mut self) {
+        let root = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        self.fuzzy_finder = Some(FuzzyFinder::new(&root));
+        self.mode = EditorMode::FuzzyFind;
+        self.input_buffer.clear();
+        self.message = Some("Find file: ".to_string());
+        self.overlay_scroll = 0;
+        self.needs_full_redraw = true;
+    }
+
+    // Show text read from standard input; it has no file until saved under a name
+    fn open_stdin(&mut self, content: String) {
+        self.load_content(content, None, STDIN_NAME);
+    }
+
+    // Replace the active pane's buffer. `label` names the source in the status message,
+    // and in the status bar too when there is no path.
+    fn load_content(&mut self, content: String, path: Option<PathBuf>, label: &str) {
+    let size = content.len();
+    // A byte order mark isn't part of the text; note it so saving can write it back
+    let (content, had_bom) = match content.strip_prefix(BOM) {
+        Some(rest) => (rest.to_string(), true),
+        None => (content, false),
+    };
+    let keep_bom = had_bom && self.config.keep_bom;
+    let trailing_newline = match self.config.trailing_newline {
+        TrailingNewline::Preserve => content.ends_with('\n'),
+        TrailingNewline::Always => true,
+        TrailingNewline::Never => false,
+    };
+    let highlighter = match path.as_deref().and_then(|path| self.highlighters.for_path(path)) {
+        Some(highlighter) => highlighter,
+        None => self.active_pane().highlighter.clone(),
+    };
+    let language = highlighter.language();
+    // A `[lang.NAME]` section in the config replaces the global defaults
+    let settings = self.config.language_settings(language.name());
+    self.show_line_numbers = settings.show_line_numbers.unwrap_or(self.config.show_line_numbers);
+    let default_spaces = settings.use_spaces.unwrap_or(self.config.use_spaces);
+    let default_width = settings.tab_width.unwrap_or(self.config.tab_width);
+    let pane = self.active_pane_mut();
+    pane.highlighter = highlighter;
+    pane.auto_close_override = settings.auto_close_pairs;
+    pane.set_buffer(TextBuffer::from_string(content));
+    pane.trailing_newline = trailing_newline;
+    pane.bom = keep_bom;
+    pane.is_symlink = false;
+    pane.current_file = path.clone();
+    pane.buffer_name = if path.is_none() { Some(label.to_string()) } else { None };
+    pane.line_status.clear();
+    Self::refresh_git_status(pane);
+    pane.set_modified(false);
+    pane.cursor = Cursor { x: 0, y: 0 };
+    pane.offset_y = 0;
+    pane.offset_x = 0;
+
+    // Match the file's own indentation, falling back to the configured style
+    pane.use_spaces = default_spaces;
+    pane.tab_width = default_width;
+    let detected = detect_indent(&pane.buffer().lines);
+    let indent_note = match detected {
+        Some((false, _)) => {
+            pane.use_spaces = false;
+            " (detected: tabs)".to_string()
+        }
+        Some((true, width)) => {
+            pane.use_spaces = true;
+            pane.tab_width = width;
+            format!(" (detected: {} spaces)", width)
+        }
+        None => String::new(),
+    };
+    
+    let long_lines = pane.buffer().has_long_lines();
+    let mut warnings: Vec<String> = self.mixed_indent_warning().into_iter().collect();
+    if let Some(mb) = self.large_file_mb(size as u64) {
+        warnings.push(format!("File is {} MB; editing may be slow", mb));
+    }
+    if long_lines {
+        warnings.push("File contains very long lines; editing may be slow".to_string());
+    }
+    if warnings.is_empty() {
+        self.message = Some(format!("Opened {}{}", label, indent_note));
+    } else {
+        self.set_error(format!("Opened {}{}. {}", label, indent_note, warnings.join(". ")));
+    }
     self.needs_full_redraw = true;
-    Ok(())
 }
 
-    fn start_search(&mut self) {
+    // The files in the panes and where their cursors are, for the next plain launch.
+    // Panes without a file on disk are left out.
+    fn session(&self) -> Session {
+        let mut session = Session::default();
+        for idx in 0..self.panes.count() {
+            let Some(pane) = self.panes.get(idx) else { continue };
+            let Some(path) = &pane.current_file else { continue };
+            if idx == self.panes.active_index() {
+                session.active = session.files.len();
+            }
+            session.files.push(SessionFile {
+                path: std::path::absolute(path).unwrap_or_else(|_| path.clone()),
+                line: pane.cursor.y + 1,
+                col: pane.cursor.x,
+            });
+        }
+        if session.files.len() > 1 {
+            session.vertical = Some(!matches!(self.split_mode, SplitMode::Horizontal));
+        }
+        session
+    }
+
+    // Reopen the files of the last session in their panes, skipping any that are gone
+    fn restore_session(&mut self, session: Session) {
+        let mut skipped = Vec::new();
+        // Indices into `session.files` of the files now in panes 0 and 1
+        let mut opened: Vec<usize> = Vec::new();
+        for (idx, file) in session.files.iter().enumerate() {
+            if opened.len() == 2 {
+                break;
+            }
+            let name = file.path.display().to_string();
+            if !file.path.is_file() {
+                skipped.push(format!("{} (no longer exists)", name));
+                continue;
+            }
+            let mut result = Ok(());
+            if let Some(&first) = opened.first() {
+                let mode = if session.vertical == Some(false) { SplitMode::Horizontal } else { SplitMode::Vertical };
+                let same_buffer = session.files[first].path == file.path;
+                self.split(mode, same_buffer);
+                self.panes.focus(1);
+                if !same_buffer {
+                    result = self.open_file(name.clone());
+                }
+            } else {
+                result = self.open_file(name.clone());
+            }
+            if let Err(e) = result {
+                skipped.push(format!("{} ({})", name, e));
+                if self.panes.count() > 1 {
+                    self.close_split();
+                }
+                continue;
+            }
+            self.jump_to_line(file.line);
+            let visible_lines = self.visible_lines();
+            let pane = self.active_pane_mut();
+            pane.cursor.x = pane.floor_col(pane.cursor.y, file.col);
+            pane.adjust_scroll(visible_lines);
+            opened.push(idx);
+        }
+        self.panes.focus(opened.iter().position(|&idx| idx == session.active).unwrap_or(0));
+
+        if !skipped.is_empty() {
+            self.set_error(format!("Not restored: {}", skipped.join(", ")));
+        }
+    }
+
+    // Open the files named on the command line: the first in the main pane, the second
+    // in a vertical split and the rest queued. `line` is the 1-based target of a `+N` argument.
+    fn open_initial_files(&mut self, files: Vec<(String, Option<usize>)>) {
+        let mut failed = Vec::new();
+        // Text piped on stdin already occupies the main pane
+        let mut opened = if self.active_pane().buffer_name.is_some() { 1 } else { 0 };
+        let mut files: VecDeque<_> = files.into();
+        while opened < 2 {
+            let Some((name, line)) = files.pop_front() else {
+                break;
+            };
+            if opened == 1 {
+                self.split_vertical();
+                self.panes.focus(1);
+            }
+            match self.open_file(name.clone()) {
+                Ok(()) => {
+                    opened += 1;
+                    if let Some(line) = line {
+                        self.jump_to_line(line);
+                    }
+                }
+                Err(e) => failed.push(format!("{} ({})", name, e)),
+            }
+            if opened < 2 && self.panes.count() > 1 {
+                self.close_split();
+            }
+        }
+        self.panes.focus(0);
+        self.queued_files = files;
+
+        if !failed.is_empty() {
+            self.set_error(format!("Could not open: {}", failed.join(", ")));
+        } else if !self.queued_files.is_empty() {
+            self.message = Some(format!(
+                "{} more file(s) queued (Alt-F opens the next)",
+                self.queued_files.len()
+            ));
+        }
+    }
+
+    // Whether the active pane's buffer can be replaced by `next` right away. With unsaved
+    // changes this asks what to do with them instead, and the answer replaces the buffer
+    // later. Anything that loads over the buffer goes through here.
+    fn confirm_discard_if_modified(&mut self, next: &ReplaceWith) -> bool {
+        // A folder only opens the file browser, leaving the buffer alone
+        let opens_browser = matches!(next, ReplaceWith::File { name, .. } if Path::new(name).is_dir());
+        let pane = self.active_pane();
+        if opens_browser || !pane.is_modified() {
+            return true;
+        }
+        let name = pane
+            .current_file
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .or_else(|| pane.buffer_name.clone())
+            .unwrap_or_else(|| "[No Name]".to_string());
+        self.pending_confirm = Some(ConfirmAction::ReplaceBuffer(next.clone()));
+        self.mode = EditorMode::Confirm;
+        self.message = Some(format!("Unsaved changes in {}: (s)ave, (d)iscard or (c)ancel? ", name));
+        self.needs_full_redraw = true;
+        false
+    }
+
+    // Load something else into the active pane, first asking what to do with unsaved
+    // changes unless `force` is set
+    fn replace_buffer(&mut self, next: ReplaceWith, force: bool) {
+        // A locked pane keeps its buffer; open in a new split instead if there's room
+        if self.active_pane().locked {
+            if self.panes.count() >= 2 {
+                self.message = Some("Pane is locked".to_string());
+                return;
+            }
+            self.split_vertical();
+            self.panes.focus(1);
+        }
+        if !force && !self.confirm_discard_if_modified(&next) {
+            return;
+        }
+        // Reading a huge file by accident can take all the memory, so check its size first
+        if let ReplaceWith::File { name, .. } = &next
+            && let Some(mb) = fs::metadata(name).ok().and_then(|meta| self.large_file_mb(meta.len()))
+        {
+            let question = format!("{} is {} MB; open anyway?", name, mb);
+            self.ask_confirm(ConfirmAction::OpenLargeFile(next), question);
+            return;
+        }
+        self.load_replacement(next);
+    }
+
+    // The size of a file in megabytes if it's over the configured limit
+    fn large_file_mb(&self, bytes: u64) -> Option<u64> {
+        let limit = self.config.large_file_mb;
+        (limit > 0 && bytes > limit * 1024 * 1024).then_some(bytes / (1024 * 1024))
+    }
+
+    fn load_replacement(&mut self, next: ReplaceWith) {
+        match next {
+            ReplaceWith::File { name, line, col } => match self.open_file(name) {
+                Ok(()) => {
+                    if let Some(line) = line {
+                        self.jump_to_line(line);
+                        let pane = self.active_pane_mut();
+                        pane.cursor.x = pane.floor_col(pane.cursor.y, col);
+                    }
+                }
+                Err(e) => self.set_error(format!("Error opening: {}", e)),
+            },
+            ReplaceWith::QueuedFile => self.open_queued_file(),
+        }
+        self.needs_full_redraw = true;
+    }
+
+    // Replace the active pane's file with the next one queued from the command line
+    fn open_queued_file(&mut self) {
+        let Some((name, line)) = self.queued_files.pop_front() else {
+            self.message = Some("No more files queued".to_string());
+            return;
+        };
+        match self.open_file(name.clone()) {
+            Ok(()) => {
+                if let Some(line) = line {
+                    self.jump_to_line(line);
+                }
+                if !self.queued_files.is_empty() {
+                    self.message = Some(format!("Opened {} ({} more queued)", name, self.queued_files.len()));
+                }
+            }
+            Err(e) => self.set_error(format!("Could not open {}: {}", name, e)),
+        }
+        self.needs_full_redraw = true;
+    }
+
+    // Run the commands from a `--commands` file in order, stopping at the first that fails
+    fn run_script(&mut self, commands: &[(usize, Command)]) -> Result<(), String> {
+        for (line, command) in commands {
+            self.run_command(command)
+                .map_err(|e| format!("Command on line {} failed: {}", line, e))?;
+        }
+        Ok(())
+    }
+
+    fn run_command(&mut self, command: &Command) -> Result<(), String> {
+        let editable = !self.active_pane().read_only;
+        match command {
+            Command::Goto(line) => self.jump_to_line(*line),
+            Command::Search(text) => {
+                self.active_pane_mut().search_backward = false;
+                self.input_buffer = text.clone();
+                self.perform_search(false).map_err(|e| e.to_string())?;
+            }
+            Command::Replace { from, to } if editable => {
+                let count = self.active_pane_mut().replace_all(from, to);
+                self.message = Some(format!("Replaced {} occurrence(s) of {}", count, from));
+            }
+            Command::ReplaceRegex { pattern, to } if editable => {
+                let regex = Regex::new(pattern).map_err(|_| format!("invalid pattern: {}", pattern))?;
+                let count = self.active_pane_mut().replace_all_regex(&regex, to)?;
+                self.message = Some(format!("Replaced {} match(es) of {}", count, pattern));
+            }
+            Command::Save if editable => {
+                if self.active_pane().current_file.is_none() {
+                    return Err("the buffer has no file name".to_string());
+                }
+                self.save_file().map_err(|e| e.to_string())?;
+            }
+            Command::Replace { .. } | Command::ReplaceRegex { .. } | Command::Save => {
+                return Err("the buffer is read-only".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    // Move to a 1-based line, clamped to the buffer, and scroll it into view
+    fn jump_to_line(&mut self, line: usize) {
+        let visible_lines = self.visible_lines();
+        let pane = self.active_pane_mut();
+        pane.cursor.y = line.saturating_sub(1).min(pane.line_count().saturating_sub(1));
+        pane.cursor.x = 0;
+        pane.adjust_scroll(visible_lines);
+    }
+
+    // Open the counterpart of the current file (header/source, impl/test)
+    fn open_alternate_file(&mut self) {
+        let Some(path) = self.active_pane().current_file.clone() else {
+            self.message = Some("No file to find a counterpart for".to_string());
+            return;
+        };
+        let candidates = alternate_files(&path, &self.config);
+        match candidates.into_iter().find(|p| p.is_file()) {
+            Some(partner) => {
+                let name = partner.to_string_lossy().into_owned();
+                self.replace_buffer(ReplaceWith::File { name, line: None, col: 0 }, false);
+            }
+            None => self.message = Some(format!("No counterpart found for {}", path.display())),
+        }
+    }
+
+    fn ask_confirm(&mut self, action: ConfirmAction, question: String) {
+        self.pending_confirm = Some(action);
+        self.mode = EditorMode::Confirm;
+        self.message = Some(format!("{} (y/n) ", question));
+        self.needs_full_redraw = true;
+    }
+
+    // Run the current file with the runner configured for its language,
+    // showing the output in an overlay as it arrives
+    fn run_current_file(&mut self) -> Result<(), EditorError> {
+        let pane = self.active_pane();
+        let Some(path) = pane.current_file.clone() else {
+            self.message = Some("Save the file before running it".to_string());
+            return Ok(());
+        };
+        let language = pane.highlighter.language();
+        let Some(runner) = self.config.runner(language.name()) else {
+            self.message = Some(format!("No runner configured for {}", language.name()));
+            return Ok(());
+        };
+        if pane.is_modified() {
+            let question = format!("{} has unsaved changes. Save and run?", path.display());
+            self.ask_confirm(ConfirmAction::SaveAndRun, question);
+            return Ok(());
+        }
+
+        let quoted = runner::shell_quote(&path.display().to_string(), self.config.shell.as_deref());
+        let command_line = runner.replace("{file}", &quoted);
+        let cwd = path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        match RunningCommand::spawn(&command_line, self.config.shell.as_deref(), cwd) {
+            Ok(running) => {
+                self.running = Some(running);
+                self.mode = EditorMode::RunOutput;
+                // Start at the bottom so the newest output stays in view
+                self.overlay_scroll = usize::MAX;
+                self.message = None;
+            }
+            Err(e) => self.set_error(format!("Error running {}: {}", command_line, e)),
+        }
+        self.needs_full_redraw = true;
+        Ok(())
+    }
+
+    // Prompt for a shell command whose output is inserted at the cursor,
+    // or which the selection is piped through and replaced by
+    fn start_shell_filter(&mut self) {
+        self.mode = EditorMode::ShellFilter;
+        self.input_buffer.clear();
+        self.message = Some(if self.active_pane().selection_start.is_some() {
+            "Filter selection through: ".to_string()
+        } else {
+            "Insert output of: ".to_string()
+        });
+        self.needs_full_redraw = true;
+    }
+
+    fn run_shell_filter(&mut self, command_line: &str) {
+        let visible_lines = self.visible_lines();
+        let pane = self.active_pane();
+        let selection = pane.selection_bounds();
+        let input = selection.map(|(start, end)| pane.buffer().text_range(start, end));
+
+        match runner::run_filter(command_line, self.config.shell.as_deref(), input) {
+            Ok(output) => {
+                let output = output.replace("\r\n", "\n");
+                let output = output.strip_suffix('\n').unwrap_or(&output);
+                let pane = self.active_pane_mut();
+                let cursor = (pane.cursor.y, pane.cursor.x);
+                let (start, end) = selection.unwrap_or((cursor, cursor));
+                pane.replace_text(start, end, output);
+                pane.adjust_scroll(visible_lines);
+                self.message = Some(if selection.is_some() {
+                    format!("Filtered selection through `{}`", command_line)
+                } else {
+                    format!("Inserted {} line(s) from `{}`", output.split('\n').count(), command_line)
+                });
+            }
+            Err(e) => self.set_error(format!("Command failed: {}", e)),
+        }
+    }
+
+    fn start_search(&mut self, backward: bool) {
         self.mode = EditorMode::Search;
+        self.search_backward = backward;
+        self.search_history.reset();
         self.input_buffer.clear();
-        self.message = Some("Search: ".to_string());
+        self.message = Some(self.search_prompt());
         self.needs_full_redraw = true;
     }
 
-    fn perform_search(&mut self) {
+    fn search_prompt(&self) -> String {
+        let direction = if self.search_backward { " backward" } else { "" };
+        format!(
+            "Search{}{} (Alt-W: whole word, Alt-R: regex, Alt-V Enter: line break): ",
+            direction,
+            self.search_flags()
+        )
+    }
+
+    // Note appended to search results describing the active options
+    fn search_flags(&self) -> String {
+        let mut flags = String::new();
+        if self.search_whole_word {
+            flags.push_str(" [whole word]");
+        }
+        if self.search_kind == SearchKind::Regex {
+            flags.push_str(" [regex]");
+        }
+        flags
+    }
+
+    // Search for the input buffer from the cursor, up the buffer when `backward` is set
+    fn perform_search(&mut self, backward: bool) -> Result<(), EditorError> {
     if self.input_buffer.is_empty() {
         self.message = Some("Search cancelled".to_string());
-        return;
+        return Ok(());
+    }
+    if self.search_kind == SearchKind::Regex
+        && let Err(e) = Regex::new(&self.input_buffer)
+    {
+        return Err(EditorError::Search(bad_regex(&e)));
     }
 
     // Clone the search query to avoid borrow issues
     let search_query = self.input_buffer.clone();
-    let (_, height) = terminal::size().unwrap_or((80, 24));
-    let visible_lines = self.calculate_visible_lines(height);
+    // Line breaks in the query are shown as ^J in messages
+    let shown_query = printable(&search_query);
+    let visible_lines = self.visible_lines();
+    let options = SearchOptions {
+        wrap: self.config.search_wrap,
+        whole_word: self.search_whole_word,
+        kind: self.search_kind,
+    };
+    let wrap = options.wrap;
     
-    // Scope the mutable borrow
     let search_result = {
         let pane = self.active_pane_mut();
+        // Find-next moves past the match under the cursor; anything else (a new query,
+        // or the cursor having moved) searches from the cursor. Searching backward always
+        // starts before the cursor, so it moves past the match either way.
+        let current = pane
+            .last_search_pos
+            .filter(|&pos| pos == (pane.cursor.y, pane.cursor.x) && pane.search_query == search_query);
         pane.search_query = search_query.clone();
+        pane.search_kind = options.kind;
+        pane.search_whole_word = options.whole_word;
+        pane.last_search_pos = current;
+        let buffer = pane.buffer();
+        match current {
+            _ if backward => buffer.search_backward(&search_query, pane.cursor.y, pane.cursor.x, options),
+            Some((row, col)) => buffer.search_after(&search_query, row, col, options),
+            None => buffer.search(&search_query, pane.cursor.y, pane.cursor.x, options),
+        }
+        .map(|(row, col, wrapped)| (row, col, wrapped, current == Some((row, col))))
+    };
 
-        let start_pos = if let Some((row, col)) = pane.last_search_pos {
-            if col + 1 < pane.buffer.get_line(row).map(|l| l.len()).unwrap_or(0) {
-                (row, col + 1)
-            } else if row + 1 < pane.buffer.line_count() {
-                (row + 1, 0)
+    let pane = self.active_pane_mut();
+    match search_result {
+        Some((row, col, wrapped, same)) => {
+            pane.cursor.y = row;
+            pane.cursor.x = col;
+            pane.last_search_pos = Some((row, col));
+            pane.adjust_scroll(visible_lines);
+            let position = format!("line {}, col {}{}", row + 1, col + 1, self.search_flags());
+            self.message = Some(if same {
+                format!("Only match, at {}", position)
+            } else if wrapped {
+                let end = if backward { "bottom" } else { "top" };
+                format!("Search wrapped to {}, found at {}", end, position)
             } else {
-                (0, 0)
-            }
+                format!("Found at {}", position)
+            });
+            self.needs_full_redraw = true;
+            Ok(())
+        }
+        // Without wrapping, stay on the last match so the next find-next doesn't start
+        // over from it
+        None if !wrap && pane.last_search_pos.is_some() => Err(EditorError::Search(format!(
+            "No more matches: {}{}",
+            shown_query,
+            self.search_flags()
+        ))),
+        None => Err(EditorError::Search(if wrap {
+            format!("Not found: {}{}", shown_query, self.search_flags())
         } else {
-            (pane.cursor.y, pane.cursor.x)
-        };
+            let end = if backward { "start" } else { "end" };
+            format!("Not found before {} of file: {}{}", end, shown_query, self.search_flags())
+        })),
+    }
+}
 
-        pane.buffer.search(&pane.search_query, start_pos.0, start_pos.1)
-    }; // Mutable borrow ends here
+    // Replace `from` with `to` from the cursor to the end of the buffer, asking at each match
+    fn start_replace(&mut self, from: String, to: String) {
+        let options = SearchOptions { wrap: false, whole_word: self.search_whole_word, kind: self.search_kind };
+        // A bad pattern, or a group `to` refers to that the pattern lacks, fails before any match is asked about
+        let checked = match SearchPattern::new(&from, options.kind) {
+            Ok(SearchPattern::Regex(regex)) => check_group_refs(&regex, &to),
+            Ok(SearchPattern::Literal(_)) => Ok(()),
+            Err(e) => Err(bad_regex(&e)),
+        };
+        if let Err(e) = checked {
+            self.set_error(e);
+            return;
+        }
+        let pane = self.active_pane_mut();
+        let found = pane.buffer().search(&from, pane.cursor.y, pane.cursor.x, options);
+        match found {
+            Some((row, col, _)) => {
+                pane.search_query = from.clone();
+                pane.search_kind = options.kind;
+                pane.search_whole_word = options.whole_word;
+                self.ask_replace(ReplaceState { from, to, options, at: (row, col), count: 0 });
+            }
+            None => self.set_error(format!("Not found before end of file: {}", printable(&from))),
+        }
+    }
 
-    // Now we can safely borrow again
-    if let Some((row, col)) = search_result {
+    // Show the match a replace is at and ask what to do with it
+    fn ask_replace(&mut self, state: ReplaceState) {
+        let visible_lines = self.visible_lines();
         let pane = self.active_pane_mut();
-        pane.cursor.y = row;
-        pane.cursor.x = col;
-        pane.last_search_pos = Some((row, col));
+        (pane.cursor.y, pane.cursor.x) = state.at;
+        pane.last_search_pos = Some(state.at);
         pane.adjust_scroll(visible_lines);
-        self.message = Some(format!("Found at line {}, col {}", row + 1, col + 1));
+        self.message = Some(format!("Replace with {}? (y)es, (n)o, (a)ll, (q)uit ", printable(&state.to)));
+        self.pending_confirm = Some(ConfirmAction::ReplaceMatch(state));
+        self.mode = EditorMode::Confirm;
         self.needs_full_redraw = true;
-    } else {
+    }
+
+    fn answer_replace(&mut self, mut state: ReplaceState, answer: char) {
+        let options = state.options;
         let pane = self.active_pane_mut();
-        pane.last_search_pos = None;
-        self.message = Some(format!("Not found: {}", search_query));
+        let (row, col) = state.at;
+        let next = match answer {
+            'y' => match SearchPattern::new(&state.from, options.kind) {
+                Ok(pattern) => {
+                    // After an empty match the next one is looked for a character further on
+                    let empty = pane.match_end(row, col, &pattern) == (row, col);
+                    let (row, col) = pane.replace_match(row, col, &pattern, &state.to);
+                    state.count += 1;
+                    if empty {
+                        pane.buffer().search_after(&state.from, row, col, options)
+                    } else {
+                        pane.buffer().search(&state.from, row, col, options)
+                    }
+                }
+                Err(_) => None,
+            },
+            'n' => pane.buffer().search_after(&state.from, row, col, options),
+            'a' => {
+                state.count += pane.replace_from(&state.from, &state.to, state.at, options).unwrap_or(0);
+                None
+            }
+            _ => None,
+        };
+        match next {
+            Some((row, col, _)) => {
+                state.at = (row, col);
+                self.ask_replace(state);
+            }
+            None => self.message = Some(format!("Replaced {} occurrence(s)", state.count)),
+        }
     }
-}
 
-    fn find_next(&mut self) {
-        let search_query = self.active_pane().search_query.clone();
-        if !search_query.is_empty() {
-            self.input_buffer = search_query;
-            self.perform_search();
+    // Repeat the last search in its own direction, or with `reverse` the opposite one.
+    // Like Vim's n and N, the direction of the original search is kept either way.
+    fn find_next(&mut self, reverse: bool) -> Result<(), EditorError> {
+        let pane = self.active_pane();
+        if pane.search_query.is_empty() {
+            return Ok(());
         }
+        let backward = pane.search_backward != reverse;
+        self.input_buffer = pane.search_query.clone();
+        self.perform_search(backward)
+    }
+
+    // Text rows of the active pane at the current terminal size
+    fn visible_lines(&self) -> usize {
+        let area = self.pane_areas().get(self.panes.active_index()).copied();
+        area.map_or(1, |(_, _, _, height)| height.max(1) as usize)
     }
 
-    fn calculate_visible_lines(&self, height: u16) -> usize {
+    // Screen region (x, y, width, height) of each pane, between the dividers
+    fn pane_regions(&self) -> Vec<(u16, u16, u16, u16)> {
+        let (width, height) = self.screen_size;
+        let rows = height.saturating_sub(2);
         match self.split_mode {
-            SplitMode::None => (height - 2) as usize,
-            SplitMode::Horizontal => ((height - 3) / 2) as usize,
-            SplitMode::Vertical => (height - 2) as usize,
+            SplitMode::None => vec![(0, 0, width, rows)],
+            SplitMode::Horizontal => {
+                let top = self.split_size(rows.saturating_sub(1), MIN_SPLIT_ROWS);
+                vec![(0, 0, width, top), (0, top + 1, width, rows.saturating_sub(top + 1))]
+            }
+            SplitMode::Vertical => {
+                let left = self.split_size(width.saturating_sub(1), MIN_SPLIT_COLS);
+                vec![(0, 0, left, rows), (left + 1, 0, width.saturating_sub(left + 1), rows)]
+            }
         }
     }
 
-    fn run(&mut self) -> io::Result<()> {
+    // Where each pane's text goes: its region, less the header row in a split
+    fn pane_areas(&self) -> Vec<(u16, u16, u16, u16)> {
+        let header = u16::from(!matches!(self.split_mode, SplitMode::None));
+        self.pane_regions()
+            .into_iter()
+            .map(|(x, y, width, height)| (x, y + header, width, height.saturating_sub(header)))
+            .collect()
+    }
+
+    // Write out the edited buffer without touching the terminal. Text read from standard
+    // input goes to standard output, so batch mode also works as a filter.
+    fn finish_batch(&mut self) -> Result<(), EditorError> {
+        let pane = self.active_pane();
+        if pane.current_file.is_some() {
+            if pane.is_modified() && !pane.read_only {
+                self.save_file()?;
+            }
+            return Ok(());
+        }
+        if pane.buffer_name.is_none() {
+            // Nothing was opened, most likely because the file couldn't be
+            let reason = self.message.clone().unwrap_or_else(|| "no file or input to edit".to_string());
+            return Err(EditorError::Io(io::Error::new(io::ErrorKind::NotFound, reason)));
+        }
+        let mut stdout = io::stdout().lock();
+        pane.write_contents(&mut stdout)?;
+        stdout.flush()?;
+        Ok(())
+    }
+
+    // Where each pane's text is drawn: its area, narrowed in zen mode to a centered
+    // column of `zen_width` text plus the gutter, with blank margins either side
+    fn text_areas(&self) -> Vec<(u16, u16, u16, u16)> {
+        let areas = self.pane_areas();
+        if !self.zen_mode {
+            return areas;
+        }
+        areas
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (x, y, width, height))| {
+                let gutter = self.panes.get(idx).map_or(0, |pane| self.gutter_width(pane));
+                let column = (self.config.zen_width + gutter).min(width as usize) as u16;
+                (x + (width - column) / 2, y, column, height)
+            })
+            .collect()
+    }
+
+    // Blank the parts of each pane area outside its text area
+    fn draw_margins(&self, stdout: &mut io::Stdout) -> io::Result<()> {
+        for (&(x, y, width, height), &(text_x, _, text_width, _)) in self.pane_areas().iter().zip(&self.text_areas()) {
+            let left = text_x - x;
+            let right = width.saturating_sub(left + text_width);
+            for row in y..y + height {
+                queue!(stdout, cursor::MoveTo(x, row), Print(" ".repeat(left as usize)))?;
+                queue!(stdout, cursor::MoveTo(text_x + text_width, row), Print(" ".repeat(right as usize)))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<(), EditorError> {
+        if self.batch {
+            return self.finish_batch();
+        }
         let mut stdout = io::stdout();
 
+        // A panic mid-edit would otherwise leave the shell in raw mode on the alternate
+        // screen. Restore the terminal first so the panic message lands on the normal one.
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let _ = execute!(
+                io::stdout(),
+                cursor::Show,
+                cursor::SetCursorStyle::DefaultUserShape,
+                terminal::LeaveAlternateScreen
+            );
+            let _ = terminal::disable_raw_mode();
+            default_hook(info);
+        }));
+
         terminal::enable_raw_mode()?;
         execute!(stdout, terminal::EnterAlternateScreen)?;
+        self.screen_size = terminal::size()?;
 
         let result = self.main_loop(&mut stdout);
+        if self.config.restore_last_session {
+            // Losing the session only costs the convenience of reopening the files
+            let _ = self.session().save();
+        }
 
-        execute!(stdout, terminal::LeaveAlternateScreen)?;
+        execute!(stdout, cursor::SetCursorStyle::DefaultUserShape, terminal::LeaveAlternateScreen)?;
         terminal::disable_raw_mode()?;
 
         result
     }
 
-    fn main_loop(&mut self, stdout: &mut io::Stdout) -> io::Result<()> {
+    fn main_loop(&mut self, stdout: &mut io::Stdout) -> Result<(), EditorError> {
         loop {
             self.refresh_screen(stdout)?;
 
@@ -733,233 +1598,461 @@ impl Editor {
                 break;
             }
 
-            self.process_keypress()?;
+            // Wake up periodically for timed work while waiting for input
+            loop {
+                if event::poll(POLL_INTERVAL)? {
+                    self.process_keypress()?;
+                    break;
+                }
+                self.poll_spell_loader();
+                if let Some(running) = &mut self.running
+                    && running.poll()
+                    && matches!(self.mode, EditorMode::RunOutput)
+                {
+                    self.needs_full_redraw = true;
+                }
+                if let Some(grep) = &mut self.grep
+                    && grep.poll()
+                {
+                    match self.mode {
+                        EditorMode::GrepResults => self.needs_full_redraw = true,
+                        // A search left running shows its progress on the message line
+                        EditorMode::Normal if self.message.is_none() => self.redraw_message_line(stdout)?,
+                        _ => {}
+                    }
+                }
+                if self.needs_full_redraw {
+                    break;
+                }
+                if self.expire_message() {
+                    self.redraw_message_line(stdout)?;
+                }
+                if self.clock_text() != self.drawn_clock {
+                    self.redraw_status_bar(stdout)?;
+                }
+            }
         }
         Ok(())
     }
 
-    fn refresh_screen(&mut self, stdout: &mut io::Stdout) -> io::Result<()> {
-        let (width, height) = terminal::size()?;
-
+    fn redraw_status_bar(&mut self, stdout: &mut io::Stdout) -> io::Result<()> {
+        let (width, height) = self.screen_size;
         queue!(stdout, cursor::Hide)?;
+        self.draw_status_bar(stdout, width, height)?;
+        self.position_cursor(stdout, height)?;
+        stdout.flush()
+    }
 
-        if self.needs_full_redraw {
-            // queue!(stdout, terminal::Clear(ClearType::All))?;  <-- REMOVED
+    // The time for the status bar, or None when the clock is off
+    fn clock_text(&self) -> Option<String> {
+        let twelve_hour = match self.config.clock {
+            Clock::Off => return None,
+            Clock::TwentyFourHour => false,
+            Clock::TwelveHour => true,
+        };
+        let (hour, minute) = local_time();
+        Some(format_clock(hour, minute, twelve_hour))
+    }
 
-            match self.split_mode {
-                SplitMode::None => {
-                    self.draw_pane(stdout, 0, 0, width, height - 2, 0)?;
-                }
-                SplitMode::Horizontal => {
-                    let split_height = (height - 3) / 2;
-                    self.draw_pane(stdout, 0, 0, width, split_height, 0)?;
+    fn redraw_message_line(&self, stdout: &mut io::Stdout) -> io::Result<()> {
+        let (_, height) = self.screen_size;
+        queue!(stdout, cursor::Hide)?;
+        self.draw_message_line(stdout, height)?;
+        self.position_cursor(stdout, height)?;
+        stdout.flush()
+    }
 
-                    queue!(stdout, cursor::MoveTo(0, split_height))?;
-                    for _ in 0..width {
-                        queue!(stdout, Print("─"))?;
-                    }
+    fn refresh_screen(&mut self, stdout: &mut io::Stdout) -> io::Result<()> {
+        self.track_message();
+        self.screen_size = terminal::size()?;
+        let (width, height) = self.screen_size;
 
-                    self.draw_pane(stdout, 0, split_height + 1, width, split_height, 1)?;
-                }
-                SplitMode::Vertical => {
-                    let split_width = width / 2;
-                    self.draw_pane(stdout, 0, 0, split_width, height - 2, 0)?;
+        queue!(stdout, cursor::Hide)?;
 
-                    for row in 0..(height - 2) {
-                        queue!(stdout, cursor::MoveTo(split_width, row), Print("│"))?;
-                    }
+        // A search or jump that lands inside a collapsed block opens it
+        let visible_lines = self.visible_lines();
+        let pane = self.active_pane_mut();
+        if pane.reveal_cursor() {
+            pane.adjust_scroll(visible_lines);
+            self.needs_full_redraw = true;
+        }
+        // Scroll long lines sideways to the cursor before deciding what to redraw
+        if let Some(&(_, _, width, _)) = self.text_areas().get(self.panes.active_index()) {
+            let text_width = (width as usize).saturating_sub(self.get_line_number_width());
+            let cursor_col = self.cursor_col(self.active_pane());
+            self.active_pane_mut().adjust_scroll_x(cursor_col, text_width);
+        }
+        // The cursor-line redraw doesn't know about the selection on other lines, or matches
+        // of a query spanning lines that an edit here can change, and after scrolling every
+        // line has moved, so those need it all. Matches within the line are redrawn with it.
+        let pane = self.active_pane();
+        let view = (self.panes.active_index(), pane.offset_y, pane.offset_x);
+        let multiline_matches = pane.search_kind == SearchKind::Literal && pane.search_query.contains('\n');
+        if self.drawn_view != Some(view) || pane.selection_start.is_some() || multiline_matches {
+            self.needs_full_redraw = true;
+        }
+        if self.detect_languages {
+            for pane in self.panes.iter_mut() {
+                self.needs_full_redraw |= pane.detect_language(&self.highlighters);
+            }
+        }
+        // Typing one bracket can change the balance of others on any line
+        let unbalanced: Vec<Vec<(usize, usize)>> = match self.bracket_check {
+            true => (0..self.panes.count())
+                .filter_map(|idx| self.panes.get(idx))
+                .map(|pane| pane.highlighter.unbalanced_brackets(&pane.buffer().lines))
+                .collect(),
+            false => Vec::new(),
+        };
+        if unbalanced != self.unbalanced_brackets {
+            self.unbalanced_brackets = unbalanced;
+            self.needs_full_redraw = true;
+        }
+        if self.focus_mode {
+            let pane = self.active_pane();
+            let block = enclosing_block(&pane.buffer().lines, pane.highlighter.language(), pane.cursor.y);
+            if block != self.focus_block {
+                self.focus_block = block;
+                self.needs_full_redraw = true;
+            }
+        }
 
-                    self.draw_pane(stdout, split_width + 1, 0, split_width - 1, height - 2, 1)?;
-                }
+        if let Some((title, lines)) = self.overlay_content() {
+            self.draw_overlay(stdout, width, height - 2, &title, &lines)?;
+        } else if self.needs_full_redraw {
+            if self.zen_mode {
+                self.draw_margins(stdout)?;
+            }
+            for (idx, &(x, y, width, height)) in self.text_areas().iter().enumerate() {
+                self.draw_pane(stdout, x, y, width, height, idx)?;
             }
+            self.draw_divider(stdout, width)?;
 
             self.needs_full_redraw = false;
-        } else {
-            match self.split_mode {
-                SplitMode::None => {
-                    self.draw_current_line(stdout, 0, 0, width)?;
-                }
-                SplitMode::Horizontal => {
-                    let split_height = (height - 3) / 2;
-                    if self.active_pane == 0 {
-                        self.draw_current_line(stdout, 0, 0, width)?;
-                    } else {
-                        self.draw_current_line(stdout, 0, split_height + 1, width)?;
-                    }
-                }
-                SplitMode::Vertical => {
-                    let split_width = width / 2;
-                    if self.active_pane == 0 {
-                        self.draw_current_line(stdout, 0, 0, split_width)?;
-                    } else {
-                        self.draw_current_line(stdout, split_width + 1, 0, split_width - 1)?;
-                    }
-                }
+            self.drawn_view = Some(view);
+        } else if let Some(&(x, y, width, _)) = self.text_areas().get(self.panes.active_index()) {
+            self.draw_current_line(stdout, x, y, width)?;
+        }
+
+        // Headers are cheap and show a pane's modified state as soon as it changes
+        if self.overlay_content().is_none() && !matches!(self.split_mode, SplitMode::None) {
+            for (idx, &(x, y, width, _)) in self.pane_regions().iter().enumerate() {
+                self.draw_pane_header(stdout, x, y, width, idx)?;
             }
         }
 
-        self.draw_status_bar(stdout, height)?;
+        self.draw_status_bar(stdout, width, height)?;
         self.draw_message_line(stdout, height)?;
-        self.position_cursor(stdout, width, height)?;
+        self.position_cursor(stdout, height)?;
 
         stdout.flush()?;
         Ok(())
     }
 
-    fn draw_pane(
-    &self,
-    stdout: &mut io::Stdout,
-    start_x: u16,
-    start_y: u16,
-    width: u16,
-    height: u16,
-    pane_idx: usize,
-) -> io::Result<()> {
-    if pane_idx >= self.panes.len() {
-        return Ok(());
+    // Title and lines of the full-screen overlay for the current mode, if any
+    fn overlay_content(&self) -> Option<(String, Vec<String>)> {
+        match self.mode {
+            EditorMode::Messages => {
+                let lines = self
+                    .message_log
+                    .iter()
+                    .rev()
+                    .map(|(at, msg)| format!("{:>6}  {}", format_age(at.elapsed()), msg))
+                    .collect();
+                Some(("Messages (newest first)".to_string(), lines))
+            }
+            EditorMode::RunOutput => {
+                let running = self.running.as_ref()?;
+                let title = format!("Run: {} [{}]", running.title, running.status_text());
+                Some((title, running.lines()))
+            }
+            EditorMode::FileBrowser => {
+                let browser = self.file_browser.as_ref()?;
+                let hidden = if browser.show_hidden { " (showing hidden)" } else { "" };
+                Some((format!("{}{}", browser.dir.display(), hidden), browser.lines()))
+            }
+            EditorMode::GrepResults => {
+                let grep = self.grep.as_ref()?;
+                let title = format!("Search for '{}' ({})", grep.pattern, grep.status_text());
+                Some((title, grep.lines()))
+            }
+            EditorMode::FuzzyFind => {
+                let finder = self.fuzzy_finder.as_ref()?;
+                let lines = finder.lines();
+                let title = format!("Files ({} of {})", lines.len(), finder.file_count());
+                Some((title, lines))
+            }
+            _ => None,
+        }
     }
 
-    let pane = &self.panes[pane_idx];
-    let is_active = pane_idx == self.active_pane;
-    let line_num_width = self.get_line_number_width();
-    let text_width = width.saturating_sub(line_num_width as u16);
-
-    // Find last line with content
-    let mut last_content_line = 0;
-    for (idx, line) in pane.buffer.lines.iter().enumerate() {
-        if !line.trim().is_empty() {
-            last_content_line = idx;
+    // Highlighted row of overlays that pick an item
+    fn overlay_selection(&self) -> Option<usize> {
+        match self.mode {
+            EditorMode::FileBrowser => self.file_browser.as_ref().map(|b| b.selected),
+            EditorMode::FuzzyFind => self.fuzzy_finder.as_ref().map(|f| f.selected),
+            EditorMode::GrepResults => self.grep.as_ref().map(|g| g.selected),
+            _ => None,
         }
     }
-    // Ensure at least line 1 is shown
-    last_content_line = last_content_line.max(0);
-
-    for screen_row in 0..height as usize {
-        let file_row = screen_row + pane.offset_y;
-        let screen_y = start_y + screen_row as u16;
 
-        queue!(stdout, cursor::MoveTo(start_x, screen_y))?;
+    // Draw a scrollable list covering the text area
+    fn draw_overlay(
+        &self,
+        stdout: &mut io::Stdout,
+        width: u16,
+        height: u16,
+        title: &str,
+        lines: &[String],
+    ) -> io::Result<()> {
+        let width = width as usize;
+        let title: String = format!(" {}", title).chars().take(width).collect();
+        queue!(
+            stdout,
+            cursor::MoveTo(0, 0),
+            SetBackgroundColor(Color::DarkGrey),
+            SetForegroundColor(Color::White),
+            Print(format!("{:<width$}", title, width = width)),
+            ResetColor
+        )?;
 
-        if self.show_line_numbers {
-            // Only show line numbers up to last content line or current line, whichever is greater
-            if file_row < pane.buffer.line_count() && file_row <= last_content_line.max(pane.cursor.y) {
-                queue!(
-                    stdout,
-                    SetForegroundColor(if is_active { Color::Yellow } else { Color::DarkGrey }),
-                    Print(format!("{:>width$} ", file_row + 1, width = line_num_width - 1)),
-                    ResetColor
-                )?;
-            } else if file_row < pane.buffer.line_count() {
-                // Lines after last content but within buffer - show tilde
-                queue!(
-                    stdout,
-                    SetForegroundColor(Color::DarkGrey),
-                    Print(format!("{:>width$} ", "~", width = line_num_width - 1)),
-                    ResetColor
-                )?;
-            } else {
-                // Beyond buffer - show tilde
-                queue!(
-                    stdout,
-                    SetForegroundColor(Color::DarkGrey),
-                    Print(format!("{:>width$} ", "~", width = line_num_width - 1)),
-                    ResetColor
-                )?;
+        let rows = height.saturating_sub(1) as usize;
+        let scroll = self.overlay_scroll.min(lines.len().saturating_sub(rows));
+        let selected = self.overlay_selection();
+        for screen_row in 0..rows {
+            let text: String = lines
+                .get(scroll + screen_row)
+                .map(|l| printable(l).chars().take(width).collect())
+                .unwrap_or_default();
+            queue!(stdout, cursor::MoveTo(0, screen_row as u16 + 1))?;
+            if selected == Some(scroll + screen_row) {
+                queue!(stdout, SetAttribute(Attribute::Reverse))?;
             }
+            queue!(
+                stdout,
+                Print(format!("{:<width$}", text, width = width)),
+                SetAttribute(Attribute::Reset)
+            )?;
+        }
+        Ok(())
+    }
+
+    fn draw_pane(
+    &self,
+    stdout: &mut io::Stdout,
+    start_x: u16,
+    start_y: u16,
+    width: u16,
+    height: u16,
+    pane_idx: usize,
+) -> io::Result<()> {
+    let Some(pane) = self.panes.get(pane_idx) else {
+        return Ok(());
+    };
+    let buffer = pane.buffer();
+    let search_pattern = pane.search_pattern();
+    let search_options = pane.search_options();
+    let is_active = pane_idx == self.panes.active_index();
+    let dimmed = !is_active && self.config.dim_inactive_pane;
+    let focus = self.focus_block.as_ref().filter(|_| is_active && self.focus_mode);
+    let line_num_width = self.gutter_width(pane);
+    let text_width = width.saturating_sub(line_num_width as u16);
+
+    // Find last line with content
+    let mut last_content_line = 0;
+    for (idx, line) in buffer.lines.iter().enumerate() {
+        if !line.trim().is_empty() {
+            last_content_line = idx;
         }
+    }
+    // Collapsed blocks show only their first line
+    let rows: Vec<usize> = pane.shown_rows(pane.offset_y).take(height as usize).collect();
+    let folds = pane.fold_ranges();
+    let last_row = rows.last().map_or(pane.offset_y, |&row| row + 1);
+    let padding = self.tab_padding(pane, pane.offset_y..last_row);
+    for screen_row in 0..height as usize {
+        let file_row = rows.get(screen_row).copied().unwrap_or(last_row + screen_row);
+        let screen_y = start_y + screen_row as u16;
 
-        if file_row < pane.buffer.line_count() {
-            if let Some(line) = pane.buffer.get_line(file_row) {
-                let display_line = if line.len() > text_width as usize {
-                    &line[..text_width as usize]
-                } else {
-                    line
-                };
+        queue!(stdout, cursor::MoveTo(start_x, screen_y))?;
+        self.draw_git_sign(stdout, pane, file_row)?;
 
-                let selection_range = if let Some(start_pos) = pane.selection_start {
-                    let end_pos = (pane.cursor.y, pane.cursor.x);
-                    let (start, end) = if start_pos < end_pos { (start_pos, end_pos) } else { (end_pos, start_pos) };
-                    
-                    if file_row > start.0 && file_row < end.0 {
-                         Some((0, line.len()))
-                    } else if file_row == start.0 && file_row == end.0 {
-                         Some((start.1, end.1))
-                    } else if file_row == start.0 {
-                         Some((start.1, line.len()))
-                    } else if file_row == end.0 {
-                         Some((0, end.1))
-                    } else {
-                         None
-                    }
-                } else {
-                    None
-                };
+        // Only show line numbers up to last content line or current line, whichever is greater
+        let numbered = file_row < pane.line_count() && file_row <= last_content_line.max(pane.cursor.y);
+        self.draw_line_number(stdout, pane, numbered.then_some(file_row + 1), is_active)?;
 
-                if !pane.search_query.is_empty() && line.contains(&pane.search_query) {
-                    self.draw_line_with_highlight(stdout, display_line, &pane.search_query)?;
+        let mut used_width = line_num_width;
+        let expanded;
+        let mut ruler_line = None;
+        let dimmed = dimmed || focus.is_some_and(|block| !block.contains(&file_row));
+        if dimmed {
+            queue!(stdout, SetAttribute(Attribute::Dim))?;
+        }
+        if file_row < pane.line_count() {
+            if let Some(line) = buffer.get_line(file_row) {
+                // Elastic tabs are drawn as spaces, which moves the byte positions after them
+                let pad = padding.get(file_row - pane.offset_y).map_or(&[][..], Vec::as_slice);
+                let at = |idx| expanded_index(line, pad, idx);
+                expanded = expand_tabs(line, pad);
+                // The columns scrolled off to the left are still highlighted, so tokens
+                // that start there keep their color
+                let skip = skip_columns(&expanded, pane.offset_x);
+                let (visible, clipped) = clip_line(&expanded[skip..], text_width as usize);
+                let display_line = &expanded[..skip + visible.len()];
+
+                let selection_range = pane.selection_on_row(file_row).map(|r| at(r.start)..at(r.end));
+                // The selection shows over search matches so it's never hidden
+                let matches: Vec<Range<usize>> = search_pattern
+                    .iter()
+                    .flat_map(|pattern| buffer.match_spans(file_row, pattern, search_options))
+                    .map(|r| at(r.start)..at(r.end))
+                    .collect();
+                if selection_range.is_none() && !matches.is_empty() {
+                    self.draw_line_with_highlight(stdout, display_line, skip, &matches)?;
                 } else {
-                    // Use syntax highlighting
-                    self.draw_line_with_syntax(stdout, display_line, &pane.highlighter, selection_range)?;
+                    let bad_brackets: Vec<usize> = self.bad_brackets(pane_idx, file_row).into_iter().map(at).collect();
+                    let highlighter = &*pane.highlighter;
+                    self.draw_line_with_syntax(stdout, display_line, skip, highlighter, selection_range, &bad_brackets)?;
+                }
+                used_width += display_width(visible);
+                if clipped {
+                    self.draw_truncation_marker(stdout)?;
+                    used_width += 1;
+                }
+                if let Some(block) = folds.iter().find(|block| block.start == file_row) {
+                    used_width += self.draw_fold_marker(stdout, block, (width as usize).saturating_sub(used_width))?;
                 }
+                ruler_line = Some(display_line);
             }
-        } else if !self.show_line_numbers {
+        } else if !self.line_numbers_shown() {
             queue!(stdout, SetForegroundColor(Color::DarkGrey))?;
             queue!(stdout, Print("~"))?;
             queue!(stdout, ResetColor)?;
+            used_width += 1;
         }
 
-        let mut used_width = line_num_width;
-        if file_row < pane.buffer.line_count() {
-             if let Some(line) = pane.buffer.get_line(file_row) {
-                 if line.len() > text_width as usize {
-                     used_width += text_width as usize;
-                 } else {
-                     used_width += line.len();
-                 }
-             }
-        } else if !self.show_line_numbers {
-             used_width += 1;
-        }
-
-        let remaining = width as usize - used_width;
+        let remaining = (width as usize).saturating_sub(used_width);
         if remaining > 0 {
              queue!(stdout, Print(" ".repeat(remaining)))?;
         }
+        if dimmed {
+            queue!(stdout, SetAttribute(Attribute::NormalIntensity))?;
+        }
+        if file_row < pane.line_count() {
+            self.draw_rulers(
+                stdout,
+                start_x + line_num_width as u16,
+                screen_y,
+                text_width,
+                ruler_line.unwrap_or(""),
+                pane,
+            )?;
+        }
     }
 
     Ok(())
 }
 
+    // How many lines a collapsed block hides, after its first line if there's room.
+    // Returns the columns used.
+    fn draw_fold_marker(&self, stdout: &mut io::Stdout, block: &Range<usize>, room: usize) -> io::Result<usize> {
+        let marker = format!(" [+{} lines]", block.len() - 1);
+        if marker.len() > room {
+            return Ok(0);
+        }
+        queue!(stdout, SetForegroundColor(Color::DarkGrey), Print(&marker), ResetColor)?;
+        Ok(marker.len())
+    }
+
+    // Elastic tabstop padding for rows of a pane; none when elastic tabstops are off
+    fn tab_padding(&self, pane: &Pane, rows: Range<usize>) -> Vec<Vec<usize>> {
+        if self.config.elastic_tabstops { pane.elastic_padding(rows) } else { Vec::new() }
+    }
+
+    // Columns of the unbalanced brackets on a row of a pane
+    fn bad_brackets(&self, pane_idx: usize, row: usize) -> Vec<usize> {
+        let Some(brackets) = self.unbalanced_brackets.get(pane_idx) else {
+            return Vec::new();
+        };
+        brackets.iter().filter(|&&(r, _)| r == row).map(|&(_, col)| col).collect()
+    }
+
+    // Draw a line from byte `skip`, which is where horizontal scrolling starts it
     fn draw_line_with_syntax(
         &self,
         stdout: &mut io::Stdout,
         line: &str,
-        highlighter: &SyntaxHighlighter,
-        selection_range: Option<(usize, usize)>,
+        skip: usize,
+        highlighter: &dyn Highlighter,
+        selection_range: Option<Range<usize>>,
+        bad_brackets: &[usize],
     ) -> io::Result<()> {
         let tokens = highlighter.highlight_line(line);
-        
-        if let Some((sel_start, sel_end)) = selection_range {
+        let misspelled = self.misspelled_ranges(line, &tokens, highlighter.language());
+        let overflow = self
+            .config
+            .line_width_limit()
+            .filter(|&limit| line.chars().count() > limit);
+
+        if selection_range.is_some() || !misspelled.is_empty() || overflow.is_some() || !bad_brackets.is_empty() {
+             let selection_range = selection_range.unwrap_or(0..0);
              let mut current_col = 0;
-             for (text, token_type) in tokens {
+             for (span, token_type) in tokens {
                 let color = token_type.color();
-                for ch in text.chars() {
-                    let is_selected = current_col >= sel_start && current_col < sel_end;
+                for (offset, ch) in line[span.clone()].char_indices() {
+                    let byte_idx = span.start + offset;
+                    if byte_idx < skip {
+                        current_col += 1;
+                        continue;
+                    }
+                    let is_selected = selection_range.contains(&byte_idx);
                     if is_selected {
                         queue!(stdout, SetBackgroundColor(Color::DarkGrey))?;
+                    } else if overflow.is_some_and(|limit| current_col >= limit) {
+                        queue!(stdout, SetBackgroundColor(Color::DarkRed))?;
+                    }
+                    if misspelled.iter().any(|r| r.contains(&byte_idx)) {
+                        queue!(
+                            stdout,
+                            SetAttribute(Attribute::Undercurled),
+                            SetUnderlineColor(Color::Red),
+                            SetForegroundColor(Color::Red),
+                            Print(ch),
+                            SetAttribute(Attribute::NoUnderline)
+                        )?;
+                    } else if let Some(notation) = control_notation(ch) {
+                        queue!(stdout, SetForegroundColor(CONTROL_CHAR_COLOR), Print(notation))?;
+                    } else if bad_brackets.contains(&byte_idx) {
+                        queue!(stdout, SetForegroundColor(Color::Red), Print(ch))?;
+                    } else if token_type.is_bold() {
+                        queue!(
+                            stdout,
+                            SetAttribute(Attribute::Bold),
+                            SetForegroundColor(color),
+                            Print(ch),
+                            SetAttribute(Attribute::NormalIntensity)
+                        )?;
+                    } else {
+                        queue!(stdout, SetForegroundColor(color), Print(ch))?;
                     }
-                    queue!(stdout, SetForegroundColor(color), Print(ch), ResetColor)?;
+                    queue!(stdout, ResetColor)?;
                     current_col += 1;
                 }
              }
         } else {
-            for (text, token_type) in tokens {
-                queue!(
-                    stdout,
-                    SetForegroundColor(token_type.color()),
-                    Print(text),
-                    ResetColor
-                )?;
+            for (span, token_type) in tokens {
+                let span = span.start.max(skip)..span.end;
+                if span.is_empty() {
+                    continue;
+                }
+                if token_type.is_bold() {
+                    queue!(stdout, SetAttribute(Attribute::Bold))?;
+                    print_text(stdout, &line[span], token_type.color())?;
+                    queue!(stdout, SetAttribute(Attribute::NormalIntensity))?;
+                } else {
+                    print_text(stdout, &line[span], token_type.color())?;
+                }
+                queue!(stdout, ResetColor)?;
             }
         }
         
@@ -974,118 +2067,290 @@ impl Editor {
     width: u16,
 ) -> io::Result<()> {
     let pane = self.active_pane();
+    let buffer = pane.buffer();
     let line_num_width = self.get_line_number_width();
     let text_width = width.saturating_sub(line_num_width as u16);
 
-    let screen_y = pane.cursor.y.saturating_sub(pane.offset_y);
+    let screen_y = pane.cursor_screen_row();
     let actual_y = start_y + screen_y as u16;
 
     queue!(stdout, cursor::MoveTo(start_x, actual_y))?;
-
-    if self.show_line_numbers {
-        queue!(
-            stdout,
-            SetForegroundColor(Color::Yellow),
-            Print(format!("{:>width$} ", pane.cursor.y + 1, width = line_num_width - 1)),
-            ResetColor
-        )?;
-    }
-
-    if let Some(line) = pane.buffer.get_line(pane.cursor.y) {
-        let display_line = if line.len() > text_width as usize {
-            &line[..text_width as usize]
-        } else {
-            line
-        };
-
-        if !pane.search_query.is_empty() && line.contains(&pane.search_query) {
-            self.draw_line_with_highlight(stdout, display_line, &pane.search_query)?;
+    self.draw_git_sign(stdout, pane, pane.cursor.y)?;
+
+    self.draw_line_number(stdout, pane, Some(pane.cursor.y + 1), true)?;
+
+    let mut current_x = line_num_width;
+    let expanded;
+    let mut ruler_line = "";
+    if let Some(line) = buffer.get_line(pane.cursor.y) {
+        let padding = self.tab_padding(pane, pane.cursor.y..pane.cursor.y + 1);
+        let pad = padding.first().map_or(&[][..], Vec::as_slice);
+        let at = |idx| expanded_index(line, pad, idx);
+        expanded = expand_tabs(line, pad);
+        let skip = skip_columns(&expanded, pane.offset_x);
+        let (visible, clipped) = clip_line(&expanded[skip..], text_width as usize);
+        let display_line = &expanded[..skip + visible.len()];
+
+        let matches: Vec<Range<usize>> = pane
+            .search_pattern()
+            .iter()
+            .flat_map(|pattern| buffer.match_spans(pane.cursor.y, pattern, pane.search_options()))
+            .map(|r| at(r.start)..at(r.end))
+            .collect();
+        if !matches.is_empty() {
+            self.draw_line_with_highlight(stdout, display_line, skip, &matches)?;
         } else {
-            self.draw_line_with_syntax(stdout, display_line, &pane.highlighter, None)?;
+            let bad_brackets: Vec<usize> =
+                self.bad_brackets(self.panes.active_index(), pane.cursor.y).into_iter().map(at).collect();
+            self.draw_line_with_syntax(stdout, display_line, skip, &*pane.highlighter, None, &bad_brackets)?;
+        }
+        current_x += display_width(visible);
+        if clipped {
+            self.draw_truncation_marker(stdout)?;
+            current_x += 1;
         }
+        if let Some(block) = pane.fold_ranges().iter().find(|block| block.start == pane.cursor.y) {
+            current_x += self.draw_fold_marker(stdout, block, (width as usize).saturating_sub(current_x))?;
+        }
+        ruler_line = display_line;
     }
     
     // Fill remaining space with spaces instead of clearing to end of line
     // ensuring we don't wipe out the right pane in vertical split mode
-    let current_x = line_num_width + if let Some(line) = pane.buffer.get_line(pane.cursor.y) {
-        if line.len() > text_width as usize {
-            text_width as usize
-        } else {
-            line.len()
-        }
-    } else {
-        0
-    };
     
     let remaining = width.saturating_sub(current_x as u16);
     if remaining > 0 {
         queue!(stdout, Print(" ".repeat(remaining as usize)))?;
     }
+    self.draw_rulers(
+        stdout,
+        start_x + line_num_width as u16,
+        actual_y,
+        text_width,
+        ruler_line,
+        pane,
+    )?;
 
     Ok(())
 }
 
+    // Mark the configured ruler columns: shade the character under a ruler, or draw a
+    // guide line where the text is shorter. `text_x` is the screen column after the gutter,
+    // which shows the pane's first column scrolled into view.
+    fn draw_rulers(
+        &self,
+        stdout: &mut io::Stdout,
+        text_x: u16,
+        screen_y: u16,
+        text_width: u16,
+        line: &str,
+        pane: &Pane,
+    ) -> io::Result<()> {
+        if self.config.rulers.is_empty() {
+            return Ok(());
+        }
+        let offset_x = pane.offset_x;
+        let tokens = pane.highlighter.highlight_line(line);
+        for &ruler in &self.config.rulers {
+            let col = ruler - 1;
+            if col < offset_x || col - offset_x >= text_width as usize {
+                continue;
+            }
+            queue!(stdout, cursor::MoveTo(text_x + (col - offset_x) as u16, screen_y))?;
+            let under = tokens
+                .iter()
+                .flat_map(|(span, token_type)| line[span.clone()].chars().map(move |ch| (ch, token_type.color())))
+                .flat_map(|(ch, color)| match control_notation(ch) {
+                    Some(notation) => notation.chars().map(|c| (c, CONTROL_CHAR_COLOR)).collect(),
+                    None => vec![(ch, color)],
+                })
+                .nth(col);
+            match under {
+                Some((ch, color)) => queue!(
+                    stdout,
+                    SetBackgroundColor(RULER_COLOR),
+                    SetForegroundColor(color),
+                    Print(ch),
+                    ResetColor
+                )?,
+                None => queue!(
+                    stdout,
+                    SetForegroundColor(RULER_COLOR),
+                    Print(RULER_CHAR),
+                    ResetColor
+                )?,
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_truncation_marker(&self, stdout: &mut io::Stdout) -> io::Result<()> {
+        queue!(
+            stdout,
+            SetForegroundColor(Color::DarkGrey),
+            Print(TRUNCATION_MARKER),
+            ResetColor
+        )
+    }
+
+    // Draw a line with the search matches in `matches` (byte ranges, in order) marked.
+    // The line may be clipped short of the later ones, and is drawn from byte `skip`.
     fn draw_line_with_highlight(
         &self,
         stdout: &mut io::Stdout,
         line: &str,
-        query: &str,
+        skip: usize,
+        matches: &[Range<usize>],
     ) -> io::Result<()> {
-        let mut last_end = 0;
-        for (idx, _) in line.match_indices(query) {
-            if idx > last_end {
-                queue!(stdout, Print(&line[last_end..idx]))?;
+        let mut last_end = skip;
+        for span in matches {
+            let start = span.start.max(last_end);
+            let end = span.end.min(line.len());
+            if start >= end {
+                continue;
+            }
+            if start > last_end {
+                print_text(stdout, &line[last_end..start], Color::Reset)?;
+            }
+            queue!(stdout, SetBackgroundColor(Color::Yellow))?;
+            print_text(stdout, &line[start..end], Color::Black)?;
+            queue!(stdout, ResetColor)?;
+            last_end = end;
+        }
+        if last_end < line.len() {
+            print_text(stdout, &line[last_end..], Color::Reset)?;
+        }
+        queue!(stdout, ResetColor)
+    }
+
+    fn modified_indicator(pane: &Pane) -> String {
+        let state = match (pane.read_only, pane.is_modified()) {
+            (true, _) => " [RO]",
+            (false, true) => " [+]",
+            (false, false) => "",
+        };
+        let lock = if pane.locked { " [Locked]" } else { "" };
+        format!("{}{}", state, lock)
+    }
+
+    // The pane's file path shortened to fit `max_width`, or its buffer name
+    fn pane_name(pane: &Pane, max_width: usize) -> String {
+        match &pane.current_file {
+            Some(path) => shorten_path(path, max_width),
+            None => pane.buffer_name.as_deref().unwrap_or("[No Name]").to_string(),
+        }
+    }
+
+    // The line between split panes, drawn as a thin bar on the side of the focused pane
+    // in the accent colour
+    fn draw_divider(&self, stdout: &mut io::Stdout, width: u16) -> io::Result<()> {
+        let first_active = self.panes.active_index() == 0;
+        queue!(stdout, SetForegroundColor(self.config.divider_color))?;
+        match (&self.split_mode, self.pane_regions().first()) {
+            (SplitMode::Horizontal, Some(&(_, _, _, top))) => {
+                let bar = if first_active { "▔" } else { "▁" };
+                queue!(stdout, cursor::MoveTo(0, top), Print(bar.repeat(width as usize)))?;
+            }
+            (SplitMode::Vertical, Some(&(_, _, left, rows))) => {
+                let bar = if first_active { "▏" } else { "▕" };
+                for row in 0..rows {
+                    queue!(stdout, cursor::MoveTo(left, row), Print(bar))?;
+                }
             }
+            _ => {}
+        }
+        queue!(stdout, ResetColor)
+    }
+
+    // A pane's name and modified state on the row above its text in a split. The
+    // focused pane's header is drawn like the status bar, the other one dimmed.
+    fn draw_pane_header(
+        &self,
+        stdout: &mut io::Stdout,
+        x: u16,
+        y: u16,
+        width: u16,
+        pane_idx: usize,
+    ) -> io::Result<()> {
+        let Some(pane) = self.panes.get(pane_idx) else {
+            return Ok(());
+        };
+        let indicator = Self::modified_indicator(pane);
+        let name = Self::pane_name(pane, (width as usize).saturating_sub(indicator.len() + 1));
+        let header = printable(&format!(" {}{}", name, indicator));
+        let (header, _) = clip_line(&header, width as usize);
+        let padding = (width as usize).saturating_sub(display_width(header));
+
+        queue!(stdout, cursor::MoveTo(x, y))?;
+        if pane_idx == self.panes.active_index() {
             queue!(
                 stdout,
-                SetBackgroundColor(Color::Yellow),
-                SetForegroundColor(Color::Black),
-                Print(&line[idx..idx + query.len()]),
-                ResetColor
+                SetBackgroundColor(Color::DarkGrey),
+                SetForegroundColor(Color::White),
+                SetAttribute(Attribute::Bold)
             )?;
-            last_end = idx + query.len();
-        }
-        if last_end < line.len() {
-            queue!(stdout, Print(&line[last_end..]))?;
+        } else {
+            queue!(stdout, SetForegroundColor(Color::DarkGrey))?;
         }
-        Ok(())
+        queue!(
+            stdout,
+            Print(header),
+            Print(" ".repeat(padding)),
+            SetAttribute(Attribute::Reset),
+            ResetColor
+        )
     }
 
-    fn draw_status_bar(&self, stdout: &mut io::Stdout, height: u16) -> io::Result<()> {
+    fn draw_status_bar(&mut self, stdout: &mut io::Stdout, width: u16, height: u16) -> io::Result<()> {
+        self.drawn_clock = self.clock_text();
+        let clock = self.drawn_clock.as_ref().map(|time| format!(" {} ", time)).unwrap_or_default();
         let pane = self.active_pane();
-        let filename = pane
-            .current_file
-            .as_ref()
-            .and_then(|p| p.file_name())
-            .and_then(|n| n.to_str())
-            .unwrap_or("[No Name]");
 
-        let modified_indicator = if pane.modified { " [+]" } else { "" };
+        let modified_indicator = Self::modified_indicator(pane);
+        let branch = pane
+            .git_branch
+            .as_ref()
+            .map(|b| format!(" | ⎇ {}", b))
+            .unwrap_or_default();
         let split_indicator = match self.split_mode {
             SplitMode::None => "",
             SplitMode::Horizontal => " [H-Split]",
             SplitMode::Vertical => " [V-Split]",
         };
 
+        let details = format!(
+            "{} | Pane {}/{} | Line {}/{} Col {}{}{}{}{}",
+            branch,
+            self.panes.active_index() + 1,
+            self.panes.count(),
+            pane.cursor.y + 1,
+            pane.line_count(),
+            pane.cursor.x + 1,
+            modified_indicator,
+            if pane.bom { " [BOM]" } else { "" },
+            if pane.is_symlink { " [symlink]" } else { "" },
+            split_indicator
+        );
+        // The path gets whatever room the rest of the bar leaves, and the details are
+        // cut short before they reach the clock at the right edge
+        let clock_width = clock.chars().count();
+        let path_width = (width as usize).saturating_sub(details.chars().count() + 1 + clock_width);
+        let filename = Self::pane_name(pane, path_width);
+        let info = format!(" {}{}", filename, details);
+        let (info, _) = clip_line(&info, (width as usize).saturating_sub(clock_width));
+
         queue!(
             stdout,
             cursor::MoveTo(0, height - 2),
             SetBackgroundColor(Color::DarkGrey),
             SetForegroundColor(Color::White),
-            Print(format!(
-                " {} | Pane {}/{} | Line {}/{} Col {}{}{}",
-                filename,
-                self.active_pane + 1,
-                self.panes.len(),
-                pane.cursor.y + 1,
-                pane.buffer.line_count(),
-                pane.cursor.x + 1,
-                modified_indicator,
-                split_indicator
-            )),
-            terminal::Clear(ClearType::UntilNewLine),
-            ResetColor
+            Print(info),
+            terminal::Clear(ClearType::UntilNewLine)
         )?;
+        if !clock.is_empty() && clock_width <= width as usize {
+            queue!(stdout, cursor::MoveTo(width - clock_width as u16, height - 2), Print(&clock))?;
+        }
+        queue!(stdout, ResetColor)?;
 
         Ok(())
     }
@@ -1097,16 +2362,47 @@ impl Editor {
             EditorMode::Normal => {
                 if let Some(msg) = &self.message {
                     queue!(stdout, Print(msg))?;
+                } else if let Some(grep) = self.grep.as_ref().filter(|g| g.is_running()) {
+                    let progress = format!("Search for '{}': {} (Esc:Cancel)", grep.pattern, grep.status_text());
+                    queue!(stdout, Print(progress))?;
                 } else {
                     queue!(
                         stdout,
-                        Print("^Q:Quit ^S:Save ^O:Open ^F:Search ^N:Next ^Z:Undo ^Y:Redo ^H:HSplit ^K:VSplit ^W:NextPane ^X:CloseSplit ^L:LineNum")
+                        Print("^Q:Quit ^S:Save ^O:Open ^F:Search ^R:Back ^N:Next ^B:Prev ^Z:Undo ^Y:Redo ^H:HSplit ^K:VSplit ^W:NextPane ^X:CloseSplit ^L:LineNum")
                     )?;
                 }
             }
-            EditorMode::Search | EditorMode::SavePrompt | EditorMode::OpenPrompt | EditorMode::GotoLinePrompt => {
+            EditorMode::Search
+            | EditorMode::ReplacePrompt
+            | EditorMode::SavePrompt
+            | EditorMode::OpenPrompt
+            | EditorMode::InsertFilePrompt
+            | EditorMode::WriteLinesPrompt
+            | EditorMode::GotoLinePrompt
+            | EditorMode::ShellFilter
+            | EditorMode::FuzzyFind
+            | EditorMode::GrepPrompt => {
+                if let Some(msg) = &self.message {
+                    queue!(stdout, Print(format!("{}{}", msg, printable(&self.input_buffer))))?;
+                }
+            }
+            EditorMode::Messages => {
+                queue!(stdout, Print("Up/Down/PgUp/PgDn:Scroll Esc:Close"))?;
+            }
+            EditorMode::RunOutput => {
+                queue!(stdout, Print("Up/Down/PgUp/PgDn:Scroll Esc:Close (stops the program)"))?;
+            }
+            EditorMode::FileBrowser => {
+                queue!(stdout, Print("Enter:Open Backspace:Up .:Hidden files Esc:Close"))?;
+            }
+            EditorMode::GrepResults => {
+                let running = self.grep.as_ref().is_some_and(GrepSearch::is_running);
+                let esc = if running { "Esc:Cancel search" } else { "Esc:Close" };
+                queue!(stdout, Print(format!("Enter:Open Up/Down/PgUp/PgDn:Select {}", esc)))?;
+            }
+            EditorMode::Confirm => {
                 if let Some(msg) = &self.message {
-                    queue!(stdout, Print(format!("{}{}", msg, self.input_buffer)))?;
+                    queue!(stdout, Print(msg))?;
                 }
             }
         }
@@ -1115,607 +2411,1125 @@ impl Editor {
         Ok(())
     }
 
-    fn position_cursor(&self, stdout: &mut io::Stdout, width: u16, height: u16) -> io::Result<()> {
+    // Screen column of a pane's cursor within its line, before horizontal scrolling.
+    // Control characters and elastic tabs before the cursor take more than one column.
+    fn cursor_col(&self, pane: &Pane) -> usize {
+        let padding = self.tab_padding(pane, pane.cursor.y..pane.cursor.y + 1);
+        let pad = padding.first().map_or(&[][..], Vec::as_slice);
+        pane.buffer().get_line(pane.cursor.y).map_or(pane.cursor.x, |line| {
+            let col = expanded_index(line, pad, byte_index(line, pane.cursor.x));
+            display_width(&expand_tabs(line, pad)[..col])
+        })
+    }
+
+    fn position_cursor(&self, stdout: &mut io::Stdout, height: u16) -> io::Result<()> {
         match self.mode {
             EditorMode::Normal => {
                 let pane = self.active_pane();
                 let line_num_width = self.get_line_number_width();
-                let screen_y = pane.cursor.y.saturating_sub(pane.offset_y);
+                let screen_y = pane.cursor_screen_row();
+                let cursor_col = self.cursor_col(pane).saturating_sub(pane.offset_x);
 
-                let (cursor_x, cursor_y) = match self.split_mode {
-                    SplitMode::None => {
-                        (line_num_width + pane.cursor.x, screen_y)
-                    }
-                    SplitMode::Horizontal => {
-                        let split_height = (height - 3) / 2;
-                        if self.active_pane == 0 {
-                            (line_num_width + pane.cursor.x, screen_y)
-                        } else {
-                            (line_num_width + pane.cursor.x, split_height as usize + 1 + screen_y)
-                        }
-                    }
-                    SplitMode::Vertical => {
-                        let split_width = width / 2;
-                        if self.active_pane == 0 {
-                            (line_num_width + pane.cursor.x, screen_y)
-                        } else {
-                            (split_width as usize + 1 + line_num_width + pane.cursor.x, screen_y)
-                        }
-                    }
-                };
+                let (area_x, area_y, _, _) =
+                    self.text_areas().get(self.panes.active_index()).copied().unwrap_or_default();
+                let cursor_x = area_x as usize + line_num_width + cursor_col;
+                let cursor_y = area_y as usize + screen_y;
 
                 queue!(
                     stdout,
                     cursor::MoveTo(cursor_x as u16, cursor_y as u16),
+                    cursor::SetCursorStyle::BlinkingBar,
+                    cursor::Show
+                )?;
+            }
+            EditorMode::Messages
+            | EditorMode::RunOutput
+            | EditorMode::FileBrowser
+            | EditorMode::GrepResults => {
+                queue!(stdout, cursor::Hide)?;
+            }
+            EditorMode::Confirm => {
+                let prompt_len = self.message.as_deref().map(display_width).unwrap_or(0);
+                queue!(
+                    stdout,
+                    cursor::MoveTo(prompt_len as u16, height - 1),
+                    cursor::SetCursorStyle::SteadyUnderScore,
                     cursor::Show
                 )?;
             }
-            EditorMode::Search | EditorMode::SavePrompt | EditorMode::OpenPrompt | EditorMode::GotoLinePrompt => {
-                let prompt_len = self.message.as_ref().map(|m| m.len()).unwrap_or(0);
+            EditorMode::Search
+            | EditorMode::ReplacePrompt
+            | EditorMode::SavePrompt
+            | EditorMode::OpenPrompt
+            | EditorMode::InsertFilePrompt
+            | EditorMode::WriteLinesPrompt
+            | EditorMode::GotoLinePrompt
+            | EditorMode::ShellFilter
+            | EditorMode::FuzzyFind
+            | EditorMode::GrepPrompt => {
+                let prompt_len = self.message.as_deref().map(display_width).unwrap_or(0);
                 queue!(
                     stdout,
-                    cursor::MoveTo((prompt_len + self.input_buffer.len()) as u16, height - 1),
+                    cursor::MoveTo((prompt_len + display_width(&self.input_buffer[..self.input_cursor()])) as u16, height - 1),
+                    cursor::SetCursorStyle::SteadyUnderScore,
                     cursor::Show
                 )?;
             }
         }
-
+
+        Ok(())
+    }
+
+    fn process_keypress(&mut self) -> Result<(), EditorError> {
+        let event = event::read()?;
+
+        match event {
+            Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+                let result = match self.mode {
+                    EditorMode::Normal => self.process_normal_mode(key_event),
+                    EditorMode::Search => self.process_search_mode(key_event),
+                    EditorMode::ReplacePrompt => {
+                        self.process_replace_prompt(key_event);
+                        Ok(())
+                    }
+                    EditorMode::SavePrompt => self.process_save_prompt(key_event),
+                    EditorMode::OpenPrompt => self.process_open_prompt(key_event),
+                    EditorMode::InsertFilePrompt => {
+                        self.process_insert_file_prompt(key_event);
+                        Ok(())
+                    }
+                    EditorMode::WriteLinesPrompt => {
+                        self.process_write_lines_prompt(key_event);
+                        Ok(())
+                    }
+                    EditorMode::GotoLinePrompt => self.process_goto_line_prompt(key_event),
+                    EditorMode::Messages | EditorMode::RunOutput => {
+                        self.process_overlay_keys(key_event);
+                        Ok(())
+                    }
+                    EditorMode::Confirm => self.process_confirm(key_event),
+                    EditorMode::ShellFilter => {
+                        self.process_shell_filter_prompt(key_event);
+                        Ok(())
+                    }
+                    EditorMode::FileBrowser => {
+                        self.process_file_browser(key_event);
+                        Ok(())
+                    }
+                    EditorMode::FuzzyFind => {
+                        self.process_fuzzy_find(key_event);
+                        Ok(())
+                    }
+                    EditorMode::GrepPrompt => {
+                        self.process_grep_prompt(key_event);
+                        Ok(())
+                    }
+                    EditorMode::GrepResults => {
+                        self.process_grep_results(key_event);
+                        Ok(())
+                    }
+                };
+                // A failed command is reported and editing carries on
+                if let Err(e) = result {
+                    self.set_error(e.to_string());
+                }
+            }
+            Event::Resize(width, height) => {
+                self.screen_size = (width, height);
+                self.fit_panes();
+                self.needs_full_redraw = true;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    // Replace the word before the cursor with the snippet it triggers for this language
+    fn expand_snippet(&mut self) {
+        let pane = self.active_pane();
+        let language = pane.highlighter.language().name();
+        let Some(trigger) = pane.word_before_cursor() else {
+            self.message = Some("No snippet trigger before the cursor".to_string());
+            return;
+        };
+        let Some(body) = self.config.snippet(language, &trigger).map(str::to_string) else {
+            self.message = Some(format!("No {} snippet for \"{}\"", language, trigger));
+            return;
+        };
+        let visible_lines = self.visible_lines();
+        let pane = self.active_pane_mut();
+        pane.expand_snippet(&trigger, &body);
+        pane.adjust_scroll(visible_lines);
+        self.message = None;
+        self.needs_full_redraw = true;
+    }
+
+    // The key after Alt-V, inserted as the character it stands for rather than run as a command
+    fn insert_literal(&mut self, key_event: KeyEvent) {
+        self.literal_next = false;
+        let c = match key_event.code {
+            KeyCode::Char(c) if key_event.modifiers.contains(KeyModifiers::CONTROL) && c.is_ascii_alphabetic() => {
+                (c.to_ascii_lowercase() as u8 - b'a' + 1) as char
+            }
+            KeyCode::Char(c) => c,
+            KeyCode::Tab => '\t',
+            KeyCode::Enter => '\r',
+            KeyCode::Esc => '\x1b',
+            KeyCode::Backspace => '\x7f',
+            _ => {
+                self.message = Some("No character for that key".to_string());
+                return;
+            }
+        };
+        self.active_pane_mut().insert_str_at_cursor(&c.to_string());
+        self.message = None;
+        self.needs_full_redraw = true;
+    }
+
+    fn process_normal_mode(&mut self, key_event: KeyEvent) -> Result<(), EditorError> {
+        let action = keymap::normal_mode_action(key_event);
+        if action.is_some_and(|a| a.edits_buffer()) && self.active_pane().read_only {
+            self.message = Some("Buffer is read-only".to_string());
+            return Ok(());
+        }
+        if let Some(action) = action.filter(|a| a.replaces_selection() && !self.literal_next)
+            && let Some((start, end)) = self.active_pane().selection_bounds()
+            && start != end
+        {
+            self.active_pane_mut().replace_text(start, end, "");
+            self.needs_full_redraw = true;
+            if action == Action::Backspace {
+                return Ok(());
+            }
+        }
+        if !action.is_some_and(|a| a.keeps_selection()) && self.active_pane().selection_start.is_some() {
+            self.active_pane_mut().selection_start = None;
+            self.needs_full_redraw = true;
+        }
+        // Any key that isn't a vertical move sets a new goal column
+        if !action.is_some_and(|a| a.is_vertical()) {
+            self.active_pane_mut().goal_col = None;
+        }
+        if self.literal_next {
+            self.insert_literal(key_event);
+            return Ok(());
+        }
+        match action {
+            Some(action) => self.apply_action(action),
+            None => Ok(()),
+        }
+    }
+
+    fn apply_action(&mut self, action: Action) -> Result<(), EditorError> {
+        match action {
+            Action::Cancel => {
+                if let Some(grep) = self.grep.as_mut().filter(|g| g.is_running()) {
+                    grep.cancel();
+                    self.message = Some(format!("Search cancelled after {} matches", grep.len()));
+                }
+            }
+            Action::ToggleLock => {
+                let pane = self.active_pane_mut();
+                pane.locked = !pane.locked;
+                self.message = Some(if pane.locked { "Pane locked" } else { "Pane unlocked" }.to_string());
+                self.needs_full_redraw = true;
+            }
+            Action::DescribeChar => {
+                let pane = self.active_pane();
+                let line = pane.buffer().get_line(pane.cursor.y).cloned().unwrap_or_default();
+                self.message = Some(describe_char(&line, byte_index(&line, pane.cursor.x)));
+            }
+            Action::InsertLiteral => {
+                self.literal_next = true;
+                self.message = Some("Insert literal: press a key".to_string());
+            }
+            Action::ExpandSnippet => self.expand_snippet(),
+            Action::Quit => {
+                if self.active_pane().is_modified() && !self.quit_warning_shown {
+                    self.set_error("File modified! Press Ctrl-Q again to quit".to_string());
+                    self.quit_warning_shown = true;
+                } else {
+                    self.should_quit = true;
+                }
+            }
+            Action::Save => {
+                if let Err(e) = self.save_file() {
+                    self.set_error(format!("Error saving: {}", e));
+                }
+            }
+            Action::OpenPrompt => {
+                self.mode = EditorMode::OpenPrompt;
+                self.open_history.reset();
+                self.input_buffer.clear();
+                self.message = Some("Open file: ".to_string());
+                self.needs_full_redraw = true;
+            }
+            Action::InsertFilePrompt => {
+                self.mode = EditorMode::InsertFilePrompt;
+                self.open_history.reset();
+                self.input_buffer.clear();
+                self.message = Some("Insert file: ".to_string());
+                self.needs_full_redraw = true;
+            }
+            Action::WriteLinesPrompt => {
+                self.mode = EditorMode::WriteLinesPrompt;
+                self.save_history.reset();
+                self.input_buffer.clear();
+                self.message = Some(match self.active_pane().selected_rows() {
+                    Some(rows) => format!("Write lines {}-{} to: ", rows.start() + 1, rows.end() + 1),
+                    None => "Write lines (START,END FILE): ".to_string(),
+                });
+                self.needs_full_redraw = true;
+            }
+            Action::Search => self.start_search(false),
+            Action::ReplacePrompt => {
+                self.mode = EditorMode::ReplacePrompt;
+                self.replace_query = None;
+                self.search_history.reset();
+                self.input_buffer.clear();
+                self.message = Some("Replace: ".to_string());
+                self.needs_full_redraw = true;
+            }
+            Action::SearchBackward => self.start_search(true),
+            Action::GotoLinePrompt => {
+                self.mode = EditorMode::GotoLinePrompt;
+                self.input_buffer.clear();
+                self.message = Some("Go to line (or N%): ".to_string());
+                self.needs_full_redraw = true;
+            }
+            Action::FindNext => self.find_next(false)?,
+            Action::FindPrev => self.find_next(true)?,
+            Action::ToggleLineNumbers => self.toggle_line_numbers(),
+            Action::ToggleFocusMode => self.toggle_focus_mode(),
+            Action::ToggleZenMode => self.toggle_zen_mode(),
+            Action::ToggleBracketCheck => self.toggle_bracket_check(),
+            Action::NextPane => self.next_pane(),
+            Action::SwapPanes => self.swap_panes(),
+            Action::ConvertIndentation { to_spaces } => self.convert_indentation(to_spaces),
+            Action::NormalizeIndentation => {
+                let language = self.active_pane().highlighter.language();
+                let settings = self.config.language_settings(language.name());
+                self.convert_indentation(settings.use_spaces.unwrap_or(self.config.use_spaces));
+            }
+            Action::Reindent => self.reindent(),
+            Action::ShellFilter => self.start_shell_filter(),
+            Action::RunFile => self.run_current_file()?,
+            Action::OpenAlternate => self.open_alternate_file(),
+            Action::ToggleTrailingNewline => self.toggle_trailing_newline(),
+            Action::ToggleBom => self.toggle_bom(),
+            Action::NextQueuedFile => {
+                if self.queued_files.is_empty() {
+                    self.message = Some("No more files queued".to_string());
+                } else {
+                    self.replace_buffer(ReplaceWith::QueuedFile, false);
+                }
+            }
+            Action::FuzzyFind => self.open_fuzzy_finder(),
+            Action::GrepPrompt => {
+                self.mode = EditorMode::GrepPrompt;
+                self.input_buffer.clear();
+                self.message = Some("Search files (empty: previous results): ".to_string());
+                self.needs_full_redraw = true;
+            }
+            Action::ShowMessages => {
+                self.mode = EditorMode::Messages;
+                self.overlay_scroll = 0;
+            }
+            Action::Split { vertical, same_buffer } => {
+                let mode = if vertical { SplitMode::Vertical } else { SplitMode::Horizontal };
+                self.split(mode, same_buffer);
+            }
+            Action::CloseSplit => self.close_split(),
+            Action::ShrinkSplit => self.resize_split(-SPLIT_RATIO_STEP),
+            Action::GrowSplit => self.resize_split(SPLIT_RATIO_STEP),
+            Action::Copy => {
+                // Copy the selection, or the current line without one
+                let pane = self.active_pane();
+                let (text, linewise) = match pane.selected_text() {
+                    Some(text) => (text, false),
+                    None => (format!("{}\n", pane.buffer().lines[pane.cursor.y]), true),
+                };
+                self.set_register(text, linewise);
+                self.message = Some(format!("{} copied", if linewise { "Line" } else { "Selection" }));
+            }
+            Action::Cut => {
+                let visible_lines = self.visible_lines();
+                let pane = self.active_pane_mut();
+                let (text, linewise) = pane.cut();
+                pane.adjust_scroll(visible_lines);
+                self.set_register(text, linewise);
+                self.message = Some(format!("{} cut", if linewise { "Line" } else { "Selection" }));
+                self.needs_full_redraw = true;
+            }
+            Action::Paste => {
+                // Text copied in another program since wins over the editor's own
+                let system = self.clipboard.as_mut().and_then(|clipboard| clipboard.get_text().ok());
+                let (text, linewise) = match system {
+                    Some(text) if !text.is_empty() && text != self.register.text => (text.replace('\r', ""), false),
+                    _ => (self.register.text.clone(), self.register.linewise),
+                };
+                if text.is_empty() {
+                    self.message = Some("Nothing to paste".to_string());
+                } else {
+                    let visible_lines = self.visible_lines();
+                    let pane = self.active_pane_mut();
+                    if linewise {
+                        pane.cursor.x = 0;
+                    }
+                    pane.insert_str_at_cursor(&text);
+                    pane.adjust_scroll(visible_lines);
+                    self.needs_full_redraw = true;
+                }
+            }
+            Action::ToggleSpellCheck => self.toggle_spell_check(),
+            Action::NextChange => self.jump_to_change(true),
+            Action::PrevChange => self.jump_to_change(false),
+            Action::NextHunk => self.jump_to_hunk(true),
+            Action::PrevHunk => self.jump_to_hunk(false),
+            Action::NextMisspelling => self.next_misspelling(),
+            Action::AddWordToDictionary => self.add_word_to_dictionary(),
+            Action::ToggleFold => {
+                if self.active_pane_mut().toggle_fold() {
+                    self.needs_full_redraw = true;
+                } else {
+                    self.message = Some("Nothing indented under this line to fold".to_string());
+                }
+            }
+            // Editing and motion only touch the active pane
+            _ => {
+                let visible_lines = self.visible_lines();
+                if self.panes.active_mut().apply(action, visible_lines, &self.config) {
+                    self.needs_full_redraw = true;
+                }
+                if action.is_typing() {
+                    self.message = None;
+                } else if action == Action::SelectAll {
+                    self.message = Some("Selected all".to_string());
+                }
+            }
+        }
         Ok(())
     }
 
-    fn process_keypress(&mut self) -> io::Result<()> {
-        let event = event::read()?;
-
-        match event {
-            Event::Key(key_event) => {
-                if key_event.kind == KeyEventKind::Press {
-                    match self.mode {
-                        EditorMode::Normal => self.process_normal_mode(key_event)?,
-                        EditorMode::Search => self.process_search_mode(key_event)?,
-                        EditorMode::SavePrompt => self.process_save_prompt(key_event)?,
-                        EditorMode::OpenPrompt => self.process_open_prompt(key_event)?,
-                        EditorMode::GotoLinePrompt => self.process_goto_line_prompt(key_event)?,
-                    }
+    fn process_overlay_keys(&mut self, key_event: KeyEvent) {
+        let (_, height) = self.screen_size;
+        let page = height.saturating_sub(3) as usize;
+        match key_event.code {
+            KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => {
+                if matches!(self.mode, EditorMode::RunOutput)
+                    && let Some(mut running) = self.running.take()
+                {
+                    running.kill();
+                    self.message = Some(format!("{}: {}", running.title, running.status_text()));
                 }
-            }
-            Event::Resize(_, _) => {
+                self.mode = EditorMode::Normal;
                 self.needs_full_redraw = true;
             }
+            KeyCode::Up => self.overlay_scroll = self.overlay_scroll.saturating_sub(1),
+            KeyCode::Down => self.overlay_scroll += 1,
+            KeyCode::PageUp => self.overlay_scroll = self.overlay_scroll.saturating_sub(page),
+            KeyCode::PageDown => self.overlay_scroll += page,
             _ => {}
         }
-
-        Ok(())
+        // Keep the scroll offset within the list so Up responds immediately at the end
+        let len = self.overlay_content().map(|(_, lines)| lines.len()).unwrap_or(0);
+        self.overlay_scroll = self.overlay_scroll.min(len.saturating_sub(page));
     }
 
+    fn process_file_browser(&mut self, key_event: KeyEvent) {
+        let (_, height) = self.screen_size;
+        let page = height.saturating_sub(3) as usize;
+        let Some(browser) = self.file_browser.as_mut() else {
+            self.mode = EditorMode::Normal;
+            return;
+        };
+        let mut result = Ok(());
+        match key_event.code {
+            KeyCode::Esc | KeyCode::Char('q') => {
+                self.file_browser = None;
+                self.mode = EditorMode::Normal;
+                self.needs_full_redraw = true;
+                return;
+            }
+            KeyCode::Up => browser.move_selection(-1),
+            KeyCode::Down => browser.move_selection(1),
+            KeyCode::PageUp => browser.move_selection(-(page as isize)),
+            KeyCode::PageDown => browser.move_selection(page as isize),
+            KeyCode::Home => browser.move_selection(isize::MIN),
+            KeyCode::End => browser.move_selection(isize::MAX),
+            KeyCode::Backspace | KeyCode::Left => result = browser.go_up(),
+            KeyCode::Char('.') => result = browser.toggle_hidden(),
+            KeyCode::Enter | KeyCode::Right => match browser.activate() {
+                Ok(Some(path)) => {
+                    self.file_browser = None;
+                    self.mode = EditorMode::Normal;
+                    let name = path.display().to_string();
+                    self.replace_buffer(ReplaceWith::File { name, line: None, col: 0 }, false);
+                    return;
+                }
+                Ok(None) => {}
+                Err(e) => result = Err(e),
+            },
+            _ => {}
+        }
 
+        let selected = browser.selected;
+        self.scroll_overlay_to(selected, page);
+        if let Err(e) = result {
+            self.set_error(format!("Cannot open folder: {}", e));
+        }
+    }
 
-    fn process_normal_mode(&mut self, key_event: KeyEvent) -> io::Result<()> {
-        match key_event {
-            KeyEvent {
-                code: KeyCode::Tab,
-                ..
-            } => {
-                let pane = self.active_pane_mut();
-                // Insert 4 spaces (or you can use a real tab character '\t')
-                for _ in 0..4 {
-                    let command = EditCommand::InsertChar {
-                        row: pane.cursor.y,
-                        col: pane.cursor.x,
-                        ch: ' ',
-                    };
-                    pane.execute_command(command);
-                    pane.cursor.x += 1;
-                }
+    // Keep the selected entry of a picker overlay on screen
+    fn scroll_overlay_to(&mut self, selected: usize, page: usize) {
+        if selected < self.overlay_scroll {
+            self.overlay_scroll = selected;
+        } else if selected >= self.overlay_scroll + page {
+            self.overlay_scroll = selected + 1 - page;
+        }
+    }
+
+    fn process_fuzzy_find(&mut self, key_event: KeyEvent) {
+        let (_, height) = self.screen_size;
+        let page = height.saturating_sub(3) as usize;
+        let Some(finder) = self.fuzzy_finder.as_mut() else {
+            self.mode = EditorMode::Normal;
+            return;
+        };
+        match key_event.code {
+            KeyCode::Esc => {
+                self.fuzzy_finder = None;
+                self.mode = EditorMode::Normal;
                 self.message = None;
+                self.needs_full_redraw = true;
+                return;
             }
-            KeyEvent {
-                code: KeyCode::Char('q'),
-                modifiers: KeyModifiers::CONTROL,
-                ..
-            } => {
-                if self.active_pane().modified && !self.quit_warning_shown {
-                    self.message = Some("File modified! Press Ctrl-Q again to quit".to_string());
-                    self.quit_warning_shown = true;
-                } else {
-                    self.should_quit = true;
+            KeyCode::Enter => {
+                let path = finder.selected_path().map(str::to_string);
+                self.fuzzy_finder = None;
+                self.mode = EditorMode::Normal;
+                self.message = None;
+                self.needs_full_redraw = true;
+                if let Some(name) = path {
+                    self.replace_buffer(ReplaceWith::File { name, line: None, col: 0 }, false);
+                }
+                return;
+            }
+            KeyCode::Up => finder.move_selection(-1),
+            KeyCode::Down => finder.move_selection(1),
+            KeyCode::PageUp => finder.move_selection(-(page as isize)),
+            KeyCode::PageDown => finder.move_selection(page as isize),
+            _ => {
+                if self.edit_input(key_event)
+                    && let Some(finder) = self.fuzzy_finder.as_mut()
+                {
+                    finder.filter(&self.input_buffer);
                 }
             }
-            KeyEvent {
-                code: KeyCode::Char('s'),
-                modifiers: KeyModifiers::CONTROL,
-                ..
-            } => {
-                self.save_file()?;
-            }
-            KeyEvent {
-                code: KeyCode::Char('o'),
-                modifiers: KeyModifiers::CONTROL,
-                ..
-            } => {
-                self.mode = EditorMode::OpenPrompt;
-                self.input_buffer.clear();
-                self.message = Some("Open file: ".to_string());
+        }
+        let selected = self.fuzzy_finder.as_ref().map_or(0, |f| f.selected);
+        self.scroll_overlay_to(selected, page);
+    }
+
+    fn process_grep_prompt(&mut self, key_event: KeyEvent) {
+        match key_event.code {
+            KeyCode::Enter => {
+                if !self.input_buffer.is_empty() {
+                    let root = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+                    self.grep = Some(GrepSearch::start(&root, &self.input_buffer));
+                }
+                // An empty pattern brings back the previous results
+                if self.grep.is_some() {
+                    self.mode = EditorMode::GrepResults;
+                    self.overlay_scroll = 0;
+                    self.message = None;
+                } else {
+                    self.mode = EditorMode::Normal;
+                    self.message = Some("Search cancelled".to_string());
+                }
                 self.needs_full_redraw = true;
             }
-            KeyEvent {
-                code: KeyCode::Char('f'),
-                modifiers: KeyModifiers::CONTROL,
-                ..
-            } => {
-                self.start_search();
-            }
-            KeyEvent {
-                code: KeyCode::Char('g'),
-                modifiers: KeyModifiers::CONTROL,
-                ..
-            } => {
-                self.mode = EditorMode::GotoLinePrompt;
-                self.input_buffer.clear();
-                self.message = Some("Go to line: ".to_string());
+            KeyCode::Esc => {
+                self.mode = EditorMode::Normal;
+                self.message = Some("Search cancelled".to_string());
                 self.needs_full_redraw = true;
             }
-            KeyEvent {
-                code: KeyCode::Char('n'),
-                modifiers: KeyModifiers::CONTROL,
-                ..
-            } => {
-                self.find_next();
-            }
-            KeyEvent {
-                code: KeyCode::Char('l'),
-                modifiers: KeyModifiers::CONTROL,
-                ..
-            } => {
-                self.toggle_line_numbers();
-            }
-            KeyEvent {
-                code: KeyCode::Char('w'),
-                modifiers: KeyModifiers::CONTROL,
-                ..
-            } => {
-                self.next_pane();
-            }
-            KeyEvent {
-                code: KeyCode::Char('h'),
-                modifiers: KeyModifiers::CONTROL,
-                ..
-            } => {
-                self.split_horizontal();
-            }
-            KeyEvent {
-                code: KeyCode::Char('k'),
-                modifiers: KeyModifiers::CONTROL,
-                ..
-            } => {
-                self.split_vertical();
+            _ => {
+                self.edit_input(key_event);
             }
-            KeyEvent {
-                code: KeyCode::Char('y'),
-                modifiers: KeyModifiers::CONTROL,
-                ..
-            } => {
-                self.active_pane_mut().redo();
+        }
+    }
+
+    fn process_grep_results(&mut self, key_event: KeyEvent) {
+        let (_, height) = self.screen_size;
+        let page = height.saturating_sub(3) as usize;
+        let Some(grep) = self.grep.as_mut() else {
+            self.mode = EditorMode::Normal;
+            return;
+        };
+        match key_event.code {
+            // The first Esc stops a running search so the matches so far can be browsed
+            KeyCode::Esc if grep.is_running() => {
+                grep.cancel();
                 self.needs_full_redraw = true;
             }
-            KeyEvent {
-                code: KeyCode::Char('z'),
-                modifiers: KeyModifiers::CONTROL,
-                ..
-            } => {
-                self.active_pane_mut().undo();
+            KeyCode::Esc | KeyCode::Char('q') => {
+                self.mode = EditorMode::Normal;
                 self.needs_full_redraw = true;
+                return;
             }
-            KeyEvent {
-                code: KeyCode::Char('c'),
-                modifiers: KeyModifiers::CONTROL,
-                ..
-            } => {
-                // Copy current line
-                let line_content = {
-                    let pane = self.active_pane();
-                    pane.buffer.get_line(pane.cursor.y).cloned()
+            KeyCode::Enter => {
+                let Some(found) = grep.get(grep.selected) else {
+                    return;
+                };
+                self.mode = EditorMode::Normal;
+                let next = ReplaceWith::File {
+                    name: found.path,
+                    line: Some(found.line + 1),
+                    col: found.col,
                 };
+                self.replace_buffer(next, false);
+                return;
+            }
+            KeyCode::Up => grep.move_selection(-1),
+            KeyCode::Down => grep.move_selection(1),
+            KeyCode::PageUp => grep.move_selection(-(page as isize)),
+            KeyCode::PageDown => grep.move_selection(page as isize),
+            _ => {}
+        }
+        let selected = grep.selected;
+        self.scroll_overlay_to(selected, page);
+    }
 
-                if let Some(line) = line_content {
-                     if let Some(clipboard) = &mut self.clipboard {
-                        let _ = clipboard.set_text(line);
-                        self.message = Some("Line copied to clipboard".to_string());
-                    }
+    fn process_shell_filter_prompt(&mut self, key_event: KeyEvent) {
+        match key_event.code {
+            KeyCode::Enter => {
+                self.mode = EditorMode::Normal;
+                if !self.input_buffer.is_empty() {
+                    let command_line = self.input_buffer.clone();
+                    self.run_shell_filter(&command_line);
                 }
+                self.needs_full_redraw = true;
             }
-            KeyEvent {
-                code: KeyCode::Char('x'),
-                modifiers: KeyModifiers::CONTROL,
-                ..
-            } => {
-                self.close_split();
-            }
-            KeyEvent {
-                code: KeyCode::Char('v'),
-                modifiers: KeyModifiers::CONTROL,
-                ..
-            } => {
-                // Paste
-                if let Some(clipboard) = &mut self.clipboard {
-                    if let Ok(text) = clipboard.get_text() {
-                         let pane = self.active_pane_mut();
-                         for ch in text.chars() {
-                            if ch == '\n' {
-                                let command = EditCommand::InsertNewline {
-                                    row: pane.cursor.y,
-                                    col: pane.cursor.x,
-                                };
-                                pane.execute_command(command);
-                                pane.cursor.y += 1;
-                                pane.cursor.x = 0;
-                            } else if ch != '\r' {
-                                let command = EditCommand::InsertChar {
-                                    row: pane.cursor.y,
-                                    col: pane.cursor.x,
-                                    ch,
-                                };
-                                pane.execute_command(command);
-                                pane.cursor.x += 1;
-                            }
-                         }
-                         self.needs_full_redraw = true;
-                    }
-                }
+            KeyCode::Esc => {
+                self.mode = EditorMode::Normal;
+                self.message = Some("Command cancelled".to_string());
+                self.needs_full_redraw = true;
             }
-            KeyEvent {
-                code: KeyCode::Char(c),
-                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
-                ..
-            } => {
-                let pane = self.active_pane_mut();
-                let command = EditCommand::InsertChar {
-                    row: pane.cursor.y,
-                    col: pane.cursor.x,
-                    ch: c,
-                };
-                pane.execute_command(command);
-                pane.cursor.x += 1;
-                self.message = None;
+            _ => {
+                self.edit_input(key_event);
             }
-            KeyEvent {
-                code: KeyCode::Enter,
-                ..
-            } => {
-                let (_, height) = terminal::size()?;
-                let visible_lines = self.calculate_visible_lines(height);
-                let pane = self.active_pane_mut();
-                
-                // Auto-indentation logic
-                let current_row = pane.cursor.y;
-                let current_line = pane.buffer.get_line(current_row).cloned().unwrap_or_default();
-                let indent: String = current_line.chars().take_while(|c| c.is_whitespace()).collect();
-                let should_indent = current_line.trim_end().ends_with('{');
-                
-                let command = EditCommand::InsertNewline {
-                    row: pane.cursor.y,
-                    col: pane.cursor.x,
-                };
-                pane.execute_command(command);
-                pane.cursor.y += 1;
-                pane.cursor.x = 0;
-                
-                // Apply indentation
-                for c in indent.chars() {
-                    let command = EditCommand::InsertChar {
-                        row: pane.cursor.y,
-                        col: pane.cursor.x,
-                        ch: c,
-                    };
-                    pane.execute_command(command);
-                    pane.cursor.x += 1;
+        }
+    }
+
+    fn process_confirm(&mut self, key_event: KeyEvent) -> Result<(), EditorError> {
+        let answer = match key_event.code {
+            KeyCode::Char(c) => c.to_ascii_lowercase(),
+            KeyCode::Esc => 'c',
+            _ => return Ok(()),
+        };
+        let valid = match self.pending_confirm {
+            Some(ConfirmAction::SaveAndRun) => "yn",
+            Some(ConfirmAction::ReplaceBuffer(_)) => "sdc",
+            Some(ConfirmAction::OpenLargeFile(_)) => "yn",
+            Some(ConfirmAction::ReplaceMatch(_)) => "ynaq",
+            None => "",
+        };
+        if !valid.contains(answer) && answer != 'c' {
+            return Ok(());
+        }
+        self.mode = EditorMode::Normal;
+        self.needs_full_redraw = true;
+        let Some(action) = self.pending_confirm.take() else {
+            return Ok(());
+        };
+        match (action, answer) {
+            (ConfirmAction::SaveAndRun, 'y') => {
+                self.save_file()?;
+                self.run_current_file()?;
+            }
+            (ConfirmAction::ReplaceBuffer(next), 'd') => self.replace_buffer(next, true),
+            (ConfirmAction::OpenLargeFile(next), 'y') => self.load_replacement(next),
+            (ConfirmAction::ReplaceMatch(state), answer) => self.answer_replace(state, answer),
+            (ConfirmAction::ReplaceBuffer(next), 's') => {
+                if self.active_pane().current_file.is_none() {
+                    self.message = Some("Buffer has no file name; save it with Ctrl-S first".to_string());
+                    return Ok(());
                 }
-                
-                // Add extra indentation if needed
-                if should_indent {
-                    for _ in 0..4 {
-                         let command = EditCommand::InsertChar {
-                            row: pane.cursor.y,
-                            col: pane.cursor.x,
-                            ch: ' ',
-                        };
-                        pane.execute_command(command);
-                        pane.cursor.x += 1;
-                    }
+                if let Err(e) = self.save_file() {
+                    self.set_error(format!("Error saving: {}", e));
+                    return Ok(());
                 }
+                self.replace_buffer(next, true);
+            }
+            _ => self.message = Some("Cancelled".to_string()),
+        }
+        Ok(())
+    }
 
-                pane.adjust_scroll(visible_lines);
-                self.message = None;
-                self.needs_full_redraw = true;
+    // Up/Down in a prompt: replace the input with an older or newer entry of its history
+    fn recall_history(&mut self, older: bool) {
+        let history = match self.mode {
+            EditorMode::Search | EditorMode::ReplacePrompt => &mut self.search_history,
+            EditorMode::OpenPrompt | EditorMode::InsertFilePrompt => &mut self.open_history,
+            EditorMode::SavePrompt | EditorMode::WriteLinesPrompt => &mut self.save_history,
+            _ => return,
+        };
+        let entry = if older { history.older(&self.input_buffer) } else { history.newer() };
+        if let Some(entry) = entry {
+            self.input_buffer = entry.to_string();
+            self.input_cursor = self.input_buffer.len();
+        }
+    }
+
+    // Edit position in `input_buffer`, clamped to the text so clearing the buffer
+    // needs no cursor bookkeeping
+    fn input_cursor(&self) -> usize {
+        let mut pos = self.input_cursor.min(self.input_buffer.len());
+        while !self.input_buffer.is_char_boundary(pos) {
+            pos -= 1;
+        }
+        pos
+    }
+
+    // Line editing shared by the prompts. Returns true if the text changed.
+    fn edit_input(&mut self, key_event: KeyEvent) -> bool {
+        let pos = self.input_cursor();
+        let prev_len = self.input_buffer[..pos].chars().next_back().map_or(0, char::len_utf8);
+        let next_len = self.input_buffer[pos..].chars().next().map_or(0, char::len_utf8);
+        match key_event.code {
+            KeyCode::Char(c) if !key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.input_buffer.insert(pos, c);
+                self.input_cursor = pos + c.len_utf8();
+                true
+            }
+            KeyCode::Backspace if prev_len > 0 => {
+                self.input_buffer.remove(pos - prev_len);
+                self.input_cursor = pos - prev_len;
+                true
+            }
+            KeyCode::Delete if next_len > 0 => {
+                self.input_buffer.remove(pos);
+                self.input_cursor = pos;
+                true
+            }
+            KeyCode::Left => {
+                self.input_cursor = pos - prev_len;
+                false
+            }
+            KeyCode::Right => {
+                self.input_cursor = pos + next_len;
+                false
+            }
+            KeyCode::Home => {
+                self.input_cursor = 0;
+                false
+            }
+            KeyCode::End => {
+                self.input_cursor = self.input_buffer.len();
+                false
+            }
+            _ => false,
+        }
+    }
+
+    fn process_search_mode(&mut self, key_event: KeyEvent) -> Result<(), EditorError> {
+        // Alt-V then Enter puts a line break in the query
+        if self.literal_next {
+            self.literal_next = false;
+            if key_event.code == KeyCode::Enter {
+                let pos = self.input_cursor();
+                self.input_buffer.insert(pos, '\n');
+                self.input_cursor = pos + 1;
+                return Ok(());
             }
-            KeyEvent {
-                code: KeyCode::Backspace,
-                ..
-            } => {
-                let (_, height) = terminal::size()?;
-                let visible_lines = self.calculate_visible_lines(height);
-                let pane = self.active_pane_mut();
-                if pane.cursor.x > 0 {
-                    if let Some(ch) = pane.buffer.get_line(pane.cursor.y).and_then(|line| {
-                        if pane.cursor.x > 0 {
-                            line.chars().nth(pane.cursor.x - 1)
-                        } else {
-                            None
-                        }
-                    }) {
-                        let command = EditCommand::DeleteChar {
-                            row: pane.cursor.y,
-                            col: pane.cursor.x - 1,
-                            ch,
-                        };
-                        pane.execute_command(command);
-                        pane.cursor.x -= 1;
-                    }
-                } else if pane.cursor.y > 0 {
-                let prev_line_len = pane
-                    .buffer
-                    .get_line(pane.cursor.y - 1)
-                    .map(|l| l.len())
-                    .unwrap_or(0);
-                if let Some(deleted_line) = pane.buffer.get_line(pane.cursor.y).map(|l| l.clone())
-                {
-                    let command = EditCommand::DeleteNewline {
-                        row: pane.cursor.y,
-                        deleted_line,
-                    };
-                    pane.execute_command(command);
-                    pane.cursor.y -= 1;
-                    pane.cursor.x = prev_line_len;
-                    pane.adjust_scroll(visible_lines);
-                    self.needs_full_redraw = true; // Add this line
-                }
+        }
+        match key_event.code {
+            KeyCode::Char('v') if key_event.modifiers.contains(KeyModifiers::ALT) => self.literal_next = true,
+            KeyCode::Char('w') if key_event.modifiers.contains(KeyModifiers::ALT) => {
+                self.search_whole_word = !self.search_whole_word;
+                self.message = Some(self.search_prompt());
+            }
+            KeyCode::Char('r') if key_event.modifiers.contains(KeyModifiers::ALT) => {
+                self.search_kind = match self.search_kind {
+                    SearchKind::Literal => SearchKind::Regex,
+                    SearchKind::Regex => SearchKind::Literal,
+                };
+                self.message = Some(self.search_prompt());
             }
-                self.message = None;
+            KeyCode::Enter => {
+                self.search_history.push(&self.input_buffer);
+                self.mode = EditorMode::Normal;
+                self.needs_full_redraw = true;
+                let backward = self.search_backward;
+                self.active_pane_mut().search_backward = backward;
+                self.perform_search(backward)?;
             }
-            KeyEvent {
-                code: KeyCode::Left,
-                ..
-            } => {
-                let (_, height) = terminal::size()?;
-                let visible_lines = self.calculate_visible_lines(height);
-                let pane = self.active_pane_mut();
-                if pane.cursor.x > 0 {
-                    pane.cursor.x -= 1;
-                } else if pane.cursor.y > 0 {
-                    pane.cursor.y -= 1;
-                    pane.cursor.x = pane.buffer.get_line(pane.cursor.y).map(|l| l.len()).unwrap_or(0);
-                    pane.adjust_scroll(visible_lines);
-                }
+            KeyCode::Esc => {
+                self.mode = EditorMode::Normal;
+                self.message = Some("Search cancelled".to_string());
+                self.needs_full_redraw = true;
             }
-            KeyEvent {
-                code: KeyCode::Right,
-                ..
-            } => {
-                let (_, height) = terminal::size()?;
-                let visible_lines = self.calculate_visible_lines(height);
-                let pane = self.active_pane_mut();
-                if let Some(line) = pane.buffer.get_line(pane.cursor.y) {
-                    if pane.cursor.x < line.len() {
-                        pane.cursor.x += 1;
-                    } else if pane.cursor.y < pane.buffer.line_count() - 1 {
-                        pane.cursor.y += 1;
-                        pane.cursor.x = 0;
-                        pane.adjust_scroll(visible_lines);
-                    }
-                }
+            KeyCode::Up => self.recall_history(true),
+            KeyCode::Down => self.recall_history(false),
+            _ => {
+                self.edit_input(key_event);
             }
-            KeyEvent {
-                code: KeyCode::Up,
-                ..
-            } => {
-                let (_, height) = terminal::size()?;
-                let visible_lines = self.calculate_visible_lines(height);
-                let pane = self.active_pane_mut();
-                if pane.cursor.y > 0 {
-                    pane.cursor.y -= 1;
-                    let line_len = pane.buffer.get_line(pane.cursor.y).map(|l| l.len()).unwrap_or(0);
-                    if pane.cursor.x > line_len {
-                        pane.cursor.x = line_len;
-                    }
-                    pane.adjust_scroll(visible_lines);
+        }
+        Ok(())
+    }
+
+    fn process_replace_prompt(&mut self, key_event: KeyEvent) {
+        match key_event.code {
+            KeyCode::Enter => match self.replace_query.take() {
+                None if self.input_buffer.is_empty() => {
+                    self.mode = EditorMode::Normal;
+                    self.message = Some("Replace cancelled".to_string());
+                    self.needs_full_redraw = true;
                 }
-            }
-            KeyEvent {
-                code: KeyCode::Down,
-                ..
-            } => {
-                let (_, height) = terminal::size()?;
-                let visible_lines = self.calculate_visible_lines(height);
-                let pane = self.active_pane_mut();
-                if pane.cursor.y < pane.buffer.line_count() - 1 {
-                    pane.cursor.y += 1;
-                    let line_len = pane.buffer.get_line(pane.cursor.y).map(|l| l.len()).unwrap_or(0);
-                    if pane.cursor.x > line_len {
-                        pane.cursor.x = line_len;
-                    }
-                    pane.adjust_scroll(visible_lines);
+                None => {
+                    self.search_history.push(&self.input_buffer);
+                    self.message = Some(format!("Replace {} with: ", printable(&self.input_buffer)));
+                    self.replace_query = Some(std::mem::take(&mut self.input_buffer));
+                }
+                Some(from) => {
+                    self.mode = EditorMode::Normal;
+                    self.needs_full_redraw = true;
+                    let to = std::mem::take(&mut self.input_buffer);
+                    self.start_replace(from, to);
                 }
+            },
+            KeyCode::Esc => {
+                self.mode = EditorMode::Normal;
+                self.replace_query = None;
+                self.message = Some("Replace cancelled".to_string());
+                self.needs_full_redraw = true;
             }
-            KeyEvent {
-                code: KeyCode::Home,
-                ..
-            } => {
-                self.active_pane_mut().cursor.x = 0;
+            // History holds search terms, not replacements
+            KeyCode::Up if self.replace_query.is_none() => self.recall_history(true),
+            KeyCode::Down if self.replace_query.is_none() => self.recall_history(false),
+            _ => {
+                self.edit_input(key_event);
             }
-            KeyEvent {
-                code: KeyCode::End,
-                ..
-            } => {
-                let pane = self.active_pane_mut();
-                if let Some(line) = pane.buffer.get_line(pane.cursor.y) {
-                    pane.cursor.x = line.len();
+        }
+    }
+
+    fn process_save_prompt(&mut self, key_event: KeyEvent) -> Result<(), EditorError> {
+        match key_event.code {
+            KeyCode::Enter => {
+                self.save_history.push(&self.input_buffer);
+                if !self.input_buffer.is_empty()
+                    && let Err(e) = self.save_file_as(self.input_buffer.clone())
+                {
+                    self.set_error(format!("Error saving: {}", e));
                 }
+                self.mode = EditorMode::Normal;
+                self.needs_full_redraw = true;
             }
-            KeyEvent {
-                code: KeyCode::PageUp,
-                ..
-            } => {
-                let (_, height) = terminal::size()?;
-                let visible_lines = self.calculate_visible_lines(height);
-                let pane = self.active_pane_mut();
-                pane.cursor.y = pane.cursor.y.saturating_sub(visible_lines);
-                pane.adjust_scroll(visible_lines);
+            KeyCode::Esc => {
+                self.mode = EditorMode::Normal;
+                self.message = Some("Save cancelled".to_string());
+                self.needs_full_redraw = true;
             }
-            KeyEvent {
-                code: KeyCode::PageDown,
-                ..
-            } => {
-                let (_, height) = terminal::size()?;
-                let visible_lines = self.calculate_visible_lines(height);
-                let pane = self.active_pane_mut();
-                pane.cursor.y = (pane.cursor.y + visible_lines).min(pane.buffer.line_count() - 1);
-                pane.adjust_scroll(visible_lines);
+            KeyCode::Up => self.recall_history(true),
+            KeyCode::Down => self.recall_history(false),
+            _ => {
+                self.edit_input(key_event);
             }
-            _ => {}
         }
         Ok(())
     }
 
-    fn process_search_mode(&mut self, key_event: KeyEvent) -> io::Result<()> {
+    fn process_open_prompt(&mut self, key_event: KeyEvent) -> Result<(), EditorError> {
         match key_event.code {
             KeyCode::Enter => {
-                self.perform_search();
+                // Set first: opening a folder switches to the file browser
                 self.mode = EditorMode::Normal;
+                self.open_history.push(&self.input_buffer);
+                if !self.input_buffer.is_empty() {
+                    let name = self.input_buffer.clone();
+                    self.replace_buffer(ReplaceWith::File { name, line: None, col: 0 }, false);
+                }
                 self.needs_full_redraw = true;
             }
             KeyCode::Esc => {
                 self.mode = EditorMode::Normal;
-                self.message = Some("Search cancelled".to_string());
+                self.message = Some("Open cancelled".to_string());
                 self.needs_full_redraw = true;
             }
-            KeyCode::Backspace => {
-                self.input_buffer.pop();
+            KeyCode::Up => self.recall_history(true),
+            KeyCode::Down => self.recall_history(false),
+            _ => {
+                self.edit_input(key_event);
             }
-            KeyCode::Char(c) => {
-                self.input_buffer.push(c);
-            }
-            _ => {}
         }
         Ok(())
     }
 
-    fn process_save_prompt(&mut self, key_event: KeyEvent) -> io::Result<()> {
+    fn process_insert_file_prompt(&mut self, key_event: KeyEvent) {
         match key_event.code {
             KeyCode::Enter => {
+                self.mode = EditorMode::Normal;
+                self.open_history.push(&self.input_buffer);
                 if !self.input_buffer.is_empty() {
-                    if let Err(e) = self.save_file_as(self.input_buffer.clone()) {
-                        self.message = Some(format!("Error saving: {}", e));
+                    let name = self.input_buffer.clone();
+                    if let Err(e) = self.insert_file(&name) {
+                        self.set_error(format!("Error inserting {}: {}", name, e));
                     }
                 }
-                self.mode = EditorMode::Normal;
                 self.needs_full_redraw = true;
             }
             KeyCode::Esc => {
                 self.mode = EditorMode::Normal;
-                self.message = Some("Save cancelled".to_string());
+                self.message = Some("Insert cancelled".to_string());
                 self.needs_full_redraw = true;
             }
-            KeyCode::Backspace => {
-                self.input_buffer.pop();
-            }
-            KeyCode::Char(c) => {
-                self.input_buffer.push(c);
+            KeyCode::Up => self.recall_history(true),
+            KeyCode::Down => self.recall_history(false),
+            _ => {
+                self.edit_input(key_event);
             }
-            _ => {}
         }
-        Ok(())
     }
 
-    fn process_open_prompt(&mut self, key_event: KeyEvent) -> io::Result<()> {
+    fn process_write_lines_prompt(&mut self, key_event: KeyEvent) {
         match key_event.code {
             KeyCode::Enter => {
+                self.mode = EditorMode::Normal;
+                self.save_history.push(&self.input_buffer);
                 if !self.input_buffer.is_empty() {
-                    if let Err(e) = self.open_file(self.input_buffer.clone()) {
-                        self.message = Some(format!("Error opening: {}", e));
+                    let input = self.input_buffer.clone();
+                    if let Err(e) = self.write_lines(&input) {
+                        self.set_error(format!("Error writing lines: {}", e));
                     }
                 }
-                self.mode = EditorMode::Normal;
                 self.needs_full_redraw = true;
             }
             KeyCode::Esc => {
                 self.mode = EditorMode::Normal;
-                self.message = Some("Open cancelled".to_string());
+                self.message = Some("Write cancelled".to_string());
                 self.needs_full_redraw = true;
             }
-            KeyCode::Backspace => {
-                self.input_buffer.pop();
-            }
-            KeyCode::Char(c) => {
-                self.input_buffer.push(c);
+            KeyCode::Up => self.recall_history(true),
+            KeyCode::Down => self.recall_history(false),
+            _ => {
+                self.edit_input(key_event);
             }
-            _ => {}
         }
-        Ok(())
     }
 
-    fn process_goto_line_prompt(&mut self, key_event: KeyEvent) -> io::Result<()> {
+    fn process_goto_line_prompt(&mut self, key_event: KeyEvent) -> Result<(), EditorError> {
         match key_event.code {
             KeyCode::Enter => {
-                if !self.input_buffer.is_empty() {
-                     if let Ok(line_num) = self.input_buffer.parse::<usize>() {
-                         let target = line_num.saturating_sub(1);
-                         let (_, height) = terminal::size()?;
-                         let visible_lines = self.calculate_visible_lines(height);
-                         
-                         let mut success = false;
-                         let mut out_of_range = false;
-
-                         {
-                             let pane = self.active_pane_mut();
-                             if target < pane.buffer.line_count() {
-                                 pane.cursor.y = target;
-                                 pane.cursor.x = 0;
-                                 pane.adjust_scroll(visible_lines);
-                                 success = true;
-                             } else {
-                                 out_of_range = true;
-                             }
-                         }
-
-                         if success {
-                             self.message = Some(format!("Went to line {}", line_num));
-                         } else if out_of_range {
-                             self.message = Some("Line number out of range".to_string());
-                         }
-                     } else {
-                         self.message = Some("Invalid line number".to_string());
-                     }
-                }
                 self.mode = EditorMode::Normal;
                 self.needs_full_redraw = true;
+                if let Some(percent) = self.input_buffer.strip_suffix('%') {
+                    // `N%` goes that far through the buffer, a bare `%` to the middle
+                    let percent = match percent {
+                        "" => 50,
+                        n => n
+                            .parse::<usize>()
+                            .map_err(|_| EditorError::Parse("Invalid percentage".to_string()))?,
+                    };
+                    let visible_lines = self.visible_lines();
+                    let pane = self.active_pane_mut();
+                    pane.cursor.y = line_at_percent(percent, pane.line_count());
+                    pane.cursor.x = 0;
+                    pane.adjust_scroll(visible_lines);
+                    self.message = Some(format!("Went to {}% (line {})", percent.min(100), pane.cursor.y + 1));
+                } else if !self.input_buffer.is_empty() {
+                    let line_num = self
+                        .input_buffer
+                        .parse::<usize>()
+                        .map_err(|_| EditorError::Parse("Invalid line number".to_string()))?;
+                    let visible_lines = self.visible_lines();
+                    let pane = self.active_pane_mut();
+                    let target = line_num.saturating_sub(1);
+                    if target >= pane.line_count() {
+                        return Err(EditorError::Parse("Line number out of range".to_string()));
+                    }
+                    pane.cursor.y = target;
+                    pane.cursor.x = 0;
+                    pane.adjust_scroll(visible_lines);
+                    self.message = Some(format!("Went to line {}", line_num));
+                }
             }
             KeyCode::Esc => {
                 self.mode = EditorMode::Normal;
                 self.message = Some("Goto line cancelled".to_string());
                 self.needs_full_redraw = true;
             }
-            KeyCode::Backspace => {
-                self.input_buffer.pop();
-            }
-            KeyCode::Char(c) => {
-                if c.is_numeric() {
-                    self.input_buffer.push(c);
-                }
+            KeyCode::Char(c) if !c.is_numeric() && c != '%' => {}
+            _ => {
+                self.edit_input(key_event);
             }
-            _ => {}
         }
         Ok(())
     }
 }
 
-fn main() -> io::Result<()> {
+// "Bad regex: " and the reason from a regex error. The last line of the error is the
+// reason; the lines before it point into the pattern.
+fn bad_regex(e: &regex::Error) -> String {
+    let reason = e.to_string();
+    format!("Bad regex: {}", reason.lines().last().unwrap_or_default().trim_start_matches("error: "))
+}
+
+// Print buffer text in `color`, drawing control characters in caret notation
+fn print_text(stdout: &mut io::Stdout, text: &str, color: Color) -> io::Result<()> {
+    let mut rest = text;
+    while let Some((idx, ch)) = rest.char_indices().find(|&(_, ch)| control_notation(ch).is_some()) {
+        queue!(stdout, SetForegroundColor(color), Print(&rest[..idx]))?;
+        if let Some(notation) = control_notation(ch) {
+            queue!(stdout, SetForegroundColor(CONTROL_CHAR_COLOR), Print(notation))?;
+        }
+        rest = &rest[idx + ch.len_utf8()..];
+    }
+    queue!(stdout, SetForegroundColor(color), Print(rest))
+}
+
+// Refuse FIFOs, sockets and devices, which could block or never end. A path that
+// doesn't exist yet is fine.
+fn check_regular_file(path: &Path) -> Result<(), EditorError> {
+    match fs::metadata(path) {
+        Ok(meta) if !meta.is_file() => Err(EditorError::NotAFile(path.display().to_string())),
+        _ => Ok(()),
+    }
+}
+
+fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_symlink())
+}
+
+// Possible counterpart files for `path`: the same name with a partner extension,
+// and for Rust sources, the matching file under the crate's tests/ (or src/) directory
+fn alternate_files(path: &Path, config: &Config) -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+    let Some(ext) = path.extension().and_then(|e| e.to_str()) else {
+        return candidates;
+    };
+    for partner in config.alternate_extensions(ext) {
+        candidates.push(path.with_extension(partner));
+    }
+
+    if ext == "rs" && config.rust_test_alternate {
+        let file_name = path.file_name().unwrap_or_default();
+        // Walk up to the nearest src/ or tests/ directory and swap it for the other
+        for dir in path.ancestors().skip(1) {
+            let counterpart = match dir.file_name().and_then(|n| n.to_str()) {
+                Some("src") => "tests",
+                Some("tests") => "src",
+                _ => continue,
+            };
+            if let Some(root) = dir.parent() {
+                candidates.push(root.join(counterpart).join(file_name));
+            }
+            break;
+        }
+    }
+    candidates
+}
+
+fn main() {
+    if let Err(e) = try_main() {
+        eprintln!("axis: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn try_main() -> Result<(), EditorError> {
+    let args = match cli::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("axis: {}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if args.help {
+        print!("{}", cli::USAGE);
+        return Ok(());
+    }
+    let language = match args.language.as_deref() {
+        Some(name) => match Language::from_name(name) {
+            Some(language) => Some(language),
+            None => {
+                eprintln!("axis: unknown language: {}\n\n{}", name, cli::USAGE);
+                std::process::exit(2);
+            }
+        },
+        None => None,
+    };
+
     let mut editor = Editor::new();
+    if let Some(width) = args.tab_width {
+        editor.config.tab_width = width;
+    }
+    if args.no_line_numbers {
+        editor.show_line_numbers = false;
+        editor.config.show_line_numbers = false;
+        for (_, settings) in &mut editor.config.languages {
+            settings.show_line_numbers = None;
+        }
+    }
+
+    // Piped input becomes the first buffer. Keyboard input still works because
+    // crossterm falls back to /dev/tty when stdin isn't a terminal.
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        let mut content = String::new();
+        stdin.lock().read_to_string(&mut content).map_err(|e| match e.kind() {
+            io::ErrorKind::InvalidData => EditorError::Decode("standard input".to_string()),
+            _ => EditorError::Io(e),
+        })?;
+        if !content.is_empty() {
+            editor.open_stdin(content);
+        }
+    }
+
+    if !args.files.is_empty() {
+        editor.open_initial_files(args.files);
+    } else if editor.config.restore_last_session
+        && editor.active_pane().buffer_name.is_none()
+        && !args.batch
+        && let Some(session) = Session::load()
+    {
+        editor.restore_session(session);
+    }
 
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() > 1 {
-        if let Err(e) = editor.open_file(args[1].clone()) {
-            eprintln!("Error opening file: {}", e);
+    // Options given explicitly win over what was detected from the files
+    for pane in editor.panes.iter_mut() {
+        pane.read_only = args.read_only;
+        if let Some(width) = args.tab_width {
+            pane.tab_width = width;
+        }
+        if let Some(language) = language {
+            pane.highlighter = Rc::new(SyntaxHighlighter::new(language));
+        }
+    }
+    editor.detect_languages = language.is_none();
+
+    if let Some(file) = args.commands {
+        let commands = fs::read_to_string(&file)
+            .map_err(|e| e.to_string())
+            .and_then(|text| script::parse(&text));
+        let commands = match commands {
+            Ok(commands) => commands,
+            Err(e) => {
+                eprintln!("axis: {}: {}", file, e);
+                std::process::exit(2);
+            }
+        };
+        if let Err(e) = editor.run_script(&commands) {
+            // A batch run that couldn't finish its edits must not write a half-edited file
+            if args.batch {
+                eprintln!("axis: {}: {}", file, e);
+                std::process::exit(1);
+            }
+            editor.set_error(e);
         }
     }
 
+    editor.batch = args.batch;
     editor.run()
-}
\ No newline at end of file
+}
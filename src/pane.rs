@@ -0,0 +1,1473 @@
+use crate::buffer::{byte_index, char_index, end_of_text, EditCommand, SearchKind, SearchOptions, SearchPattern, TextBuffer};
+use crate::config::{Config, TrailingNewline};
+use crate::display::elastic_tab_padding;
+use crate::git::LineStatus;
+use crate::highlight::{Highlighter, HighlighterRegistry, Language, SyntaxHighlighter};
+use crate::keymap::Action;
+use regex::Regex;
+use std::cell::{Ref, RefCell, RefMut};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::{Range, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+pub const BOM: char = '\u{FEFF}';
+
+// `x` counts characters, not bytes
+#[derive(Clone)]
+pub struct Cursor {
+    pub x: usize,
+    pub y: usize,
+}
+
+// Text with its edit history. Panes showing the same buffer share one, so an edit
+// or undo in either is seen by both.
+#[derive(Default)]
+struct Document {
+    buffer: TextBuffer,
+    undo_stack: Vec<EditCommand>,
+    redo_stack: Vec<EditCommand>,
+    modified: bool,
+}
+
+// Cloning a pane gives a second view of the same document with its own cursor
+#[derive(Clone)]
+pub struct Pane {
+    doc: Rc<RefCell<Document>>,
+    pub cursor: Cursor,
+    pub offset_y: usize,
+    // Screen columns scrolled out of view on the left, for lines wider than the pane
+    pub offset_x: usize,
+    pub current_file: Option<PathBuf>,
+    pub search_query: String,
+    // Whether the search query is a regex, for highlighting its matches
+    pub search_kind: SearchKind,
+    // Whether the search only matched whole words, for highlighting the same matches
+    pub search_whole_word: bool,
+    pub last_search_pos: Option<(usize, usize)>,
+    // The last search went up the buffer; find-next keeps going that way
+    pub search_backward: bool,
+    // Shared between views of a document, and between panes of the same language
+    pub highlighter: Rc<dyn Highlighter>,
+    pub selection_start: Option<(usize, usize)>,
+    pub trailing_newline: bool,
+    // Write a UTF-8 byte order mark before the text
+    pub bom: bool,
+    pub tab_width: usize,
+    pub use_spaces: bool,
+    // Pairs from the config that replace the language's own, see `auto_close_pairs`
+    pub auto_close_override: Option<Vec<(char, char)>>,
+    pub git_branch: Option<String>,
+    // Shown in place of a file name for text that has no file, e.g. "[stdin]"
+    pub buffer_name: Option<String>,
+    // The file is a symbolic link, so saving writes to its target
+    pub is_symlink: bool,
+    // Edits are refused and saving is disabled (-R)
+    pub read_only: bool,
+    // Opening another file here is refused, keeping e.g. a reference document in view.
+    // Editing the text is still allowed.
+    pub locked: bool,
+    // Character column to return to during a run of vertical moves across shorter lines
+    pub goal_col: Option<usize>,
+    // Rows whose indented block (see `indent_block_range`) is collapsed into them. Edits
+    // that add or remove lines open them all, as the rows no longer line up.
+    pub folds: Vec<usize>,
+    // Change markers against the committed file, refreshed on open and save
+    pub line_status: Vec<LineStatus>,
+}
+
+impl Default for Pane {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pane {
+    pub fn new() -> Self {
+    Self {
+        doc: Rc::default(),
+        cursor: Cursor { x: 0, y: 0 },
+        offset_y: 0,
+        offset_x: 0,
+        current_file: None,
+        search_query: String::new(),
+        search_kind: SearchKind::Literal,
+        search_whole_word: false,
+        last_search_pos: None,
+        search_backward: false,
+        highlighter: Rc::new(SyntaxHighlighter::new(Language::Plain)),
+        selection_start: None,
+        trailing_newline: false,
+        bom: false,
+        tab_width: 4,
+        use_spaces: true,
+        git_branch: None,
+        buffer_name: None,
+        is_symlink: false,
+        read_only: false,
+        locked: false,
+        auto_close_override: None,
+        goal_col: None,
+        folds: Vec::new(),
+        line_status: Vec::new(),
+    }
+    }
+
+    // A fresh empty pane with the configured defaults applied
+    pub fn with_config(config: &Config) -> Self {
+        let mut pane = Self::new();
+        pane.trailing_newline = config.trailing_newline == TrailingNewline::Always;
+        pane.tab_width = config.tab_width;
+        pane.use_spaces = config.use_spaces;
+        pane
+    }
+
+    // Give a pane still highlighted as plain text the language of its file, for panes
+    // filled some way other than loading a file. Returns whether the language changed.
+    pub fn detect_language(&mut self, highlighters: &HighlighterRegistry) -> bool {
+        let by_content = || {
+            let language = Language::from_content(&self.buffer().lines)?;
+            Some(Rc::new(SyntaxHighlighter::new(language)) as Rc<dyn Highlighter>)
+        };
+        let detected = self
+            .current_file
+            .as_deref()
+            .and_then(|path| highlighters.for_path(path))
+            .filter(|highlighter| highlighter.name() != "plain")
+            .or_else(by_content);
+        match detected {
+            Some(highlighter) if self.highlighter.name() == "plain" && highlighter.name() != "plain" => {
+                self.highlighter = highlighter;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Text inserted for one level of indentation
+    pub fn indent_unit(&self) -> String {
+        if self.use_spaces {
+            " ".repeat(self.tab_width)
+        } else {
+            "\t".to_string()
+        }
+    }
+
+    // Insert text, which may span lines, at the cursor as one undoable step and move
+    // the cursor past it
+    pub fn insert_str_at_cursor(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let (row, col) = (self.cursor.y, self.floor_col(self.cursor.y, self.cursor.x));
+        self.execute_command(EditCommand::InsertText { row, col, text: text.to_string() });
+        (self.cursor.y, self.cursor.x) = end_of_text(row, col, text);
+    }
+
+    // The buffer as it should be written to disk
+    pub fn file_contents(&self) -> String {
+        let mut content = Vec::new();
+        let _ = self.write_contents(&mut content);
+        String::from_utf8(content).unwrap_or_default()
+    }
+
+    // The text as it goes on disk: the BOM if the file had one, then the lines and the
+    // final newline
+    pub fn write_contents(&self, writer: &mut impl Write) -> io::Result<()> {
+        if self.bom {
+            write!(writer, "{}", BOM)?;
+        }
+        self.buffer().write_to(writer)?;
+        if self.trailing_newline {
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    // Write the file, streaming it rather than building the whole text in memory
+    pub fn save_to(&self, path: &Path) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write_contents(&mut file)?;
+        file.flush()
+    }
+
+    // Write the lines in `rows` to a file, each ending in a newline. The pane's own file
+    // and modified state are left alone.
+    pub fn write_lines(&self, rows: RangeInclusive<usize>, path: &Path) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        for line in &self.buffer().lines[rows] {
+            writeln!(file, "{}", line)?;
+        }
+        file.flush()
+    }
+
+    // Rows a selection covers as whole lines. A selection ending at the start of a
+    // later line doesn't take that line.
+    pub fn selected_rows(&self) -> Option<RangeInclusive<usize>> {
+        let (start, end) = self.selection_bounds()?;
+        let last = if end.1 == 0 && end.0 > start.0 { end.0 - 1 } else { end.0 };
+        Some(start.0..=last)
+    }
+
+    pub fn buffer(&self) -> Ref<'_, TextBuffer> {
+        Ref::map(self.doc.borrow(), |doc| &doc.buffer)
+    }
+
+    pub fn buffer_mut(&mut self) -> RefMut<'_, TextBuffer> {
+        RefMut::map(self.doc.borrow_mut(), |doc| &mut doc.buffer)
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.buffer().line_count()
+    }
+
+    // Length in characters of a line, 0 past the end
+    pub fn line_len(&self, row: usize) -> usize {
+        self.buffer().line_len(row)
+    }
+
+    pub fn floor_col(&self, row: usize, col: usize) -> usize {
+        self.buffer().floor_col(row, col)
+    }
+
+    // Show new text, e.g. a file that was opened, with a fresh history. Another pane
+    // that shared the old buffer keeps it.
+    pub fn set_buffer(&mut self, buffer: TextBuffer) {
+        self.doc = Rc::new(RefCell::new(Document { buffer, ..Document::default() }));
+        self.folds.clear();
+    }
+
+    pub fn shares_buffer_with(&self, other: &Pane) -> bool {
+        Rc::ptr_eq(&self.doc, &other.doc)
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.doc.borrow().modified
+    }
+
+    pub fn set_modified(&mut self, modified: bool) {
+        self.doc.borrow_mut().modified = modified;
+    }
+
+    pub fn undo_len(&self) -> usize {
+        self.doc.borrow().undo_stack.len()
+    }
+
+    pub fn execute_command(&mut self, command: EditCommand) {
+        let line_count = self.line_count();
+        {
+            let doc = &mut *self.doc.borrow_mut();
+            command.redo(&mut doc.buffer);
+            doc.undo_stack.push(command);
+            doc.redo_stack.clear();
+            doc.modified = true;
+        }
+        self.open_folds_if_lines_moved(line_count);
+    }
+
+    pub fn undo(&mut self) {
+        let line_count = self.line_count();
+        {
+            let doc = &mut *self.doc.borrow_mut();
+            if let Some(command) = doc.undo_stack.pop() {
+                command.undo(&mut doc.buffer);
+                doc.redo_stack.push(command);
+                doc.modified = !doc.undo_stack.is_empty();
+            }
+        }
+        self.open_folds_if_lines_moved(line_count);
+    }
+
+    pub fn redo(&mut self) {
+        let line_count = self.line_count();
+        {
+            let doc = &mut *self.doc.borrow_mut();
+            if let Some(command) = doc.redo_stack.pop() {
+                command.redo(&mut doc.buffer);
+                doc.undo_stack.push(command);
+                doc.modified = true;
+            }
+        }
+        self.open_folds_if_lines_moved(line_count);
+    }
+
+    fn open_folds_if_lines_moved(&mut self, line_count: usize) {
+        if self.line_count() != line_count {
+            self.folds.clear();
+        }
+    }
+
+    // Collapse the block the cursor line starts, or open it if it's collapsed. Returns
+    // false when the line starts no block, i.e. nothing after it is indented deeper.
+    pub fn toggle_fold(&mut self) -> bool {
+        let row = self.cursor.y;
+        if let Some(idx) = self.folds.iter().position(|&start| start == row) {
+            self.folds.remove(idx);
+            return true;
+        }
+        if indent_block_range(&self.buffer().lines, row).len() < 2 {
+            return false;
+        }
+        self.folds.push(row);
+        true
+    }
+
+    // Rows of each collapsed block, the line it's collapsed into first
+    pub fn fold_ranges(&self) -> Vec<Range<usize>> {
+        let lines = &self.buffer().lines;
+        self.folds.iter().map(|&row| indent_block_range(lines, row)).filter(|block| block.len() > 1).collect()
+    }
+
+    // The rows shown from `row` down, skipping those inside collapsed blocks
+    pub fn shown_rows(&self, row: usize) -> impl Iterator<Item = usize> + use<> {
+        let folds = self.fold_ranges();
+        let line_count = self.line_count();
+        let mut next = Some(row);
+        std::iter::from_fn(move || {
+            let row = next.filter(|&row| row < line_count)?;
+            let mut after = row + 1;
+            while let Some(block) = folds.iter().find(|block| block.start < after && after < block.end) {
+                after = block.end;
+            }
+            next = Some(after);
+            Some(row)
+        })
+    }
+
+    // The line `row` is collapsed into, or `row` itself when it's shown
+    pub fn fold_start(&self, row: usize) -> usize {
+        let folds = self.fold_ranges();
+        let mut row = row;
+        while let Some(block) = folds.iter().find(|block| block.start < row && row < block.end) {
+            row = block.start;
+        }
+        row
+    }
+
+    // Open the collapsed blocks hiding the cursor, e.g. after a search lands in one.
+    // Returns whether any were opened.
+    pub fn reveal_cursor(&mut self) -> bool {
+        let folds = self.fold_ranges();
+        let row = self.cursor.y;
+        let before = self.folds.len();
+        self.folds.retain(|&start| !folds.iter().any(|block| block.start == start && start < row && row < block.end));
+        self.folds.len() != before
+    }
+
+    // Rows shown between the top of the view and the cursor
+    pub fn cursor_screen_row(&self) -> usize {
+        if self.folds.is_empty() {
+            return self.cursor.y.saturating_sub(self.offset_y);
+        }
+        self.shown_rows(self.offset_y).take_while(|&row| row < self.cursor.y).count()
+    }
+
+    // Bring the cursor and selection back inside the text after another pane showing
+    // the same buffer made it shorter
+    pub fn clamp_cursor(&mut self) {
+        let buffer = &*self.doc.borrow();
+        let last_row = buffer.buffer.line_count().saturating_sub(1);
+        self.cursor.y = self.cursor.y.min(last_row);
+        self.cursor.x = buffer.buffer.floor_col(self.cursor.y, self.cursor.x);
+        if let Some((row, col)) = self.selection_start {
+            let row = row.min(last_row);
+            self.selection_start = Some((row, buffer.buffer.floor_col(row, col)));
+        }
+    }
+
+    // Selection as (start, end) in document order, whichever way it was made
+    pub fn selection_bounds(&self) -> Option<((usize, usize), (usize, usize))> {
+        let anchor = self.selection_start?;
+        let cursor = (self.cursor.y, self.cursor.x);
+        Some(if anchor <= cursor { (anchor, cursor) } else { (cursor, anchor) })
+    }
+
+    // The last search compiled for highlighting its matches, if it's a valid pattern
+    pub fn search_pattern(&self) -> Option<SearchPattern<'_>> {
+        SearchPattern::new(&self.search_query, self.search_kind).ok()
+    }
+
+    // Options the last search ran with, so its highlighted matches are the ones it finds
+    pub fn search_options(&self) -> SearchOptions {
+        SearchOptions { wrap: false, whole_word: self.search_whole_word, kind: self.search_kind }
+    }
+
+    // Byte range of `row` covered by the selection, for drawing
+    pub fn selection_on_row(&self, row: usize) -> Option<Range<usize>> {
+        let (start, end) = self.selection_bounds()?;
+        if row < start.0 || row > end.0 {
+            return None;
+        }
+        let buffer = self.buffer();
+        let line = buffer.get_line(row).map_or("", String::as_str);
+        let from = if row == start.0 { byte_index(line, start.1) } else { 0 };
+        let to = if row == end.0 { byte_index(line, end.1) } else { line.len() };
+        Some(from..to)
+    }
+
+    pub fn selected_text(&self) -> Option<String> {
+        self.selection_bounds()
+            .map(|(start, end)| self.buffer().text_range(start, end))
+    }
+
+    // Remove the selection, or the cursor's line without one, as one undoable step.
+    // Returns the text removed and whether it was a whole line, which then ends in a
+    // newline. The only line of a buffer is emptied rather than removed.
+    pub fn cut(&mut self) -> (String, bool) {
+        if let Some((start, end)) = self.selection_bounds() {
+            let text = self.buffer().text_range(start, end);
+            self.replace_text(start, end, "");
+            return (text, false);
+        }
+        let row = self.cursor.y;
+        let line = self.buffer().lines[row].clone();
+        let new = if self.line_count() == 1 { vec![String::new()] } else { Vec::new() };
+        self.execute_command(EditCommand::ReplaceLines { start: row, old: vec![line.clone()], new });
+        self.cursor.y = row.min(self.line_count() - 1);
+        self.cursor.x = 0;
+        (line + "\n", true)
+    }
+
+    pub fn select_all(&mut self) {
+        let last_row = self.line_count() - 1;
+        self.selection_start = Some((0, 0));
+        self.cursor.y = last_row;
+        self.cursor.x = self.line_len(last_row);
+    }
+
+    // Replace the text between two positions with `text`, which may span lines, as one
+    // undoable step. The cursor ends up after the inserted text.
+    pub fn replace_text(&mut self, start: (usize, usize), end: (usize, usize), text: &str) {
+        if start == end {
+            self.cursor.y = start.0;
+            self.cursor.x = start.1;
+            self.selection_start = None;
+            self.insert_str_at_cursor(text);
+            return;
+        }
+        let start = (start.0, self.floor_col(start.0, start.1));
+        let end = (end.0, self.floor_col(end.0, end.1));
+        let old = self.buffer().lines[start.0..=end.0].to_vec();
+        let last_old = &old[old.len() - 1];
+        let combined = format!(
+            "{}{}{}",
+            &old[0][..byte_index(&old[0], start.1)],
+            text,
+            &last_old[byte_index(last_old, end.1)..]
+        );
+        let new: Vec<String> = combined.split('\n').map(|l| l.to_string()).collect();
+
+        let inserted: Vec<&str> = text.split('\n').collect();
+        let last = inserted[inserted.len() - 1];
+        self.cursor.y = start.0 + inserted.len() - 1;
+        let last_len = last.chars().count();
+        self.cursor.x = if inserted.len() == 1 { start.1 + last_len } else { last_len };
+        self.selection_start = None;
+        self.execute_command(EditCommand::ReplaceLines { start: start.0, old, new });
+    }
+
+    // End of the match of `pattern` starting at (row, col), or (row, col) itself for an
+    // empty match or none
+    pub fn match_end(&self, row: usize, col: usize, pattern: &SearchPattern) -> (usize, usize) {
+        match pattern {
+            SearchPattern::Literal(from) => end_of_text(row, col, from),
+            SearchPattern::Regex(regex) => {
+                let buffer = self.buffer();
+                let Some(line) = buffer.get_line(row) else {
+                    return (row, col);
+                };
+                let start = byte_index(line, col);
+                let found = regex.find_at(line, start).filter(|m| m.start() == start);
+                (row, found.map_or(col, |m| char_index(line, m.end())))
+            }
+        }
+    }
+
+    // Replace the match of `pattern` at (row, col) with `to` as one undoable step. For a
+    // regex, `$1` or `${name}` in `to` stands for what that group matched. Returns the
+    // position just after the replacement, where looking for the next match resumes.
+    pub fn replace_match(&mut self, row: usize, col: usize, pattern: &SearchPattern, to: &str) -> (usize, usize) {
+        let end = self.match_end(row, col, pattern);
+        let text = match pattern {
+            SearchPattern::Literal(_) => to.to_string(),
+            SearchPattern::Regex(regex) => {
+                let mut text = String::new();
+                let line = &self.buffer().lines[row];
+                if let Some(caps) = regex.captures_at(line, byte_index(line, col)) {
+                    caps.expand(to, &mut text);
+                }
+                text
+            }
+        };
+        self.replace_text((row, col), end, &text);
+        (self.cursor.y, self.cursor.x)
+    }
+
+    // Replace the matches of `from` from (row, col) to the end of the buffer as one
+    // undoable step. Each search resumes after the last replacement, so `to` is never
+    // matched itself, and one character further after an empty match. Returns how many
+    // there were, or an error for a bad regex or a group `to` refers to that it lacks.
+    pub fn replace_from(
+        &mut self,
+        from: &str,
+        to: &str,
+        (row, col): (usize, usize),
+        options: SearchOptions,
+    ) -> Result<usize, String> {
+        let options = SearchOptions { wrap: false, ..options };
+        let pattern = SearchPattern::new(from, options.kind).map_err(|_| format!("invalid pattern: {}", from))?;
+        if let SearchPattern::Regex(regex) = &pattern {
+            check_group_refs(regex, to)?;
+        }
+        // Replacing an empty match with nothing changes nothing and adds no undo step
+        let start_len = self.undo_len();
+        let mut found = self.buffer().search(from, row, col, options);
+        let mut count = 0;
+        while let Some((row, col, _)) = found {
+            let empty = self.match_end(row, col, &pattern) == (row, col);
+            let pos = self.replace_match(row, col, &pattern, to);
+            count += 1;
+            found = if empty {
+                self.buffer().search_after(from, pos.0, pos.1, options)
+            } else {
+                self.buffer().search(from, pos.0, pos.1, options)
+            };
+        }
+        if self.undo_len() > start_len + 1 {
+            let doc = &mut *self.doc.borrow_mut();
+            let commands = doc.undo_stack.split_off(start_len);
+            doc.undo_stack.push(EditCommand::Group(commands));
+        }
+        Ok(count)
+    }
+
+    // Replace every occurrence of `from` as one undoable step. Returns how many there were.
+    pub fn replace_all(&mut self, from: &str, to: &str) -> usize {
+        if from.is_empty() {
+            return 0;
+        }
+        let old = self.buffer().lines.clone();
+        let count: usize = old.iter().map(|line| line.matches(from).count()).sum();
+        if count > 0 {
+            let new = old.iter().map(|line| line.replace(from, to)).collect();
+            self.execute_command(EditCommand::ReplaceLines { start: 0, old, new });
+            let row = self.cursor.y.min(self.line_count() - 1);
+            self.cursor.y = row;
+            self.cursor.x = self.floor_col(row, self.cursor.x);
+            self.selection_start = None;
+        }
+        count
+    }
+
+    // Replace every match of `regex` as one undoable step. `$1` or `${name}` in `template`
+    // stands for what that group matched and `$$` for a dollar sign. A reference to a
+    // group the pattern doesn't have is an error. Returns how many matches there were.
+    pub fn replace_all_regex(&mut self, regex: &Regex, template: &str) -> Result<usize, String> {
+        check_group_refs(regex, template)?;
+        let old = self.buffer().lines.clone();
+        let count: usize = old.iter().map(|line| regex.find_iter(line).count()).sum();
+        if count > 0 {
+            let new = old
+                .iter()
+                .map(|line| {
+                    regex
+                        .replace_all(line, |caps: &regex::Captures| {
+                            let mut replacement = String::new();
+                            caps.expand(template, &mut replacement);
+                            replacement
+                        })
+                        .into_owned()
+                })
+                .collect();
+            self.execute_command(EditCommand::ReplaceLines { start: 0, old, new });
+            let row = self.cursor.y.min(self.line_count() - 1);
+            self.cursor.y = row;
+            self.cursor.x = self.floor_col(row, self.cursor.x);
+            self.selection_start = None;
+        }
+        Ok(count)
+    }
+
+    // The identifier directly before the cursor, e.g. a snippet trigger
+    pub fn word_before_cursor(&self) -> Option<String> {
+        let buffer = self.buffer();
+        let line = buffer.get_line(self.cursor.y)?;
+        let before = &line[..byte_index(line, self.cursor.x)];
+        let start = before
+            .char_indices()
+            .rev()
+            .take_while(|&(_, c)| c.is_alphanumeric() || c == '_')
+            .last()
+            .map(|(idx, _)| idx)?;
+        Some(before[start..].to_string())
+    }
+
+    // Replace `trigger` before the cursor with a snippet body as one undoable step.
+    // Each `\t` in the body becomes one indent level, lines after the first get the
+    // current line's indentation, and the cursor goes to `$0` (else the end).
+    pub fn expand_snippet(&mut self, trigger: &str, body: &str) {
+        let row = self.cursor.y;
+        let Some(start) = self.cursor.x.checked_sub(trigger.chars().count()) else {
+            return;
+        };
+        let line = self.buffer().lines[row].clone();
+        let indent: String = line.chars().take_while(|c| c.is_whitespace()).take(start).collect();
+        let text = body
+            .replace('\t', &self.indent_unit())
+            .replace('\n', &format!("\n{}", indent));
+        let (text, cursor_at) = match text.find("$0") {
+            Some(idx) => (text.replacen("$0", "", 1), idx),
+            None => (text.clone(), text.len()),
+        };
+
+        self.execute_command(EditCommand::Group(vec![
+            EditCommand::DeleteText { row, col: start, text: trigger.to_string() },
+            EditCommand::InsertText { row, col: start, text: text.clone() },
+        ]));
+        (self.cursor.y, self.cursor.x) = end_of_text(row, start, &text[..cursor_at]);
+    }
+
+    // Delete from the start of the line up to the cursor
+    pub fn delete_to_line_start(&mut self) {
+        let col = self.floor_col(self.cursor.y, self.cursor.x);
+        if col > 0 {
+            let text = self.buffer().lines[self.cursor.y].chars().take(col).collect();
+            let command = EditCommand::DeleteText {
+                row: self.cursor.y,
+                col: 0,
+                text,
+            };
+            self.execute_command(command);
+            self.cursor.x = 0;
+        }
+    }
+
+    // Remove the cursor's line, keeping the column where the next line allows. The only
+    // line of a buffer is emptied instead, as a buffer always has one.
+    pub fn delete_line(&mut self) {
+        let row = self.cursor.y;
+        let content = self.buffer().lines[row].clone();
+        if self.line_count() > 1 {
+            self.execute_command(EditCommand::DeleteLine { row, content });
+            self.cursor.y = row.min(self.line_count() - 1);
+        } else if !content.is_empty() {
+            self.execute_command(EditCommand::DeleteText { row, col: 0, text: content });
+        }
+        self.cursor.x = self.floor_col(self.cursor.y, self.cursor.x);
+    }
+
+    // Bracket and quote pairs treated as a unit when editing
+    pub fn auto_close_pairs(&self) -> &[(char, char)] {
+        match &self.auto_close_override {
+            Some(pairs) => pairs,
+            None => self.highlighter.language().auto_close_pairs(),
+        }
+    }
+
+    // The empty pair the cursor sits inside, like `(|)`, as its opener and closer
+    pub fn empty_pair_at_cursor(&self) -> Option<(char, char)> {
+        let buffer = self.buffer();
+        let line = buffer.get_line(self.cursor.y)?;
+        let (before, after) = line.split_at(byte_index(line, self.cursor.x));
+        let pair = (before.chars().next_back()?, after.chars().next()?);
+        self.auto_close_pairs().contains(&pair).then_some(pair)
+    }
+
+    // Number of spaces Backspace removes at once when the cursor is in space indentation:
+    // back to the previous tab stop. None outside leading spaces.
+    pub fn soft_tab_width(&self) -> Option<usize> {
+        if !self.use_spaces || self.cursor.x == 0 {
+            return None;
+        }
+        let buffer = self.buffer();
+        let line = buffer.get_line(self.cursor.y)?;
+        if self.cursor.x > line.len() || !line.bytes().take(self.cursor.x).all(|b| b == b' ') {
+            return None;
+        }
+        let width = self.tab_width.max(1);
+        let count = match self.cursor.x % width {
+            0 => width,
+            partial => partial,
+        };
+        (count > 1).then_some(count)
+    }
+
+    // Row of the nearest line after (or before) the cursor that the gutter marks as
+    // changed, if there is one
+    pub fn next_change(&self, forward: bool) -> Option<usize> {
+        let changed = |row: &usize| self.line_status.get(*row).is_some_and(|&s| s != LineStatus::Unchanged);
+        if forward {
+            (self.cursor.y + 1..self.line_status.len()).find(changed)
+        } else {
+            (0..self.cursor.y.min(self.line_status.len())).rev().find(changed)
+        }
+    }
+
+    // Row of the nearest `@@` hunk header of a patch after (or before) the cursor
+    pub fn next_hunk(&self, forward: bool) -> Option<usize> {
+        let buffer = self.buffer();
+        let is_hunk = |row: &usize| buffer.lines[*row].starts_with("@@");
+        if forward {
+            (self.cursor.y + 1..buffer.lines.len()).find(is_hunk)
+        } else {
+            (0..self.cursor.y.min(buffer.lines.len())).rev().find(is_hunk)
+        }
+    }
+
+    // Elastic tabstop padding (see `display::elastic_tab_padding`) for each row in
+    // `rows`. Columns line up across the whole run of lines with tabs around them, not
+    // just the rows asked for, so the layout doesn't shift as the view scrolls.
+    pub fn elastic_padding(&self, rows: Range<usize>) -> Vec<Vec<usize>> {
+        let buffer = self.buffer();
+        let lines = &buffer.lines;
+        let rows = rows.start.min(lines.len())..rows.end.min(lines.len());
+        let has_tab = |idx: usize| lines[idx].contains('\t');
+        let start = (0..rows.start).rev().take_while(|&idx| has_tab(idx)).last().unwrap_or(rows.start);
+        let end = (rows.end..lines.len()).take_while(|&idx| has_tab(idx)).last().map_or(rows.end, |idx| idx + 1);
+        let block: Vec<&str> = lines[start..end].iter().map(String::as_str).collect();
+        let mut padding = elastic_tab_padding(&block, self.tab_width);
+        padding.drain(..rows.start - start);
+        padding.truncate(rows.len());
+        padding
+    }
+
+    // Insert a typed character. With auto-close on, an opener also inserts its closer,
+    // and typing a closer directly before the same character steps over it instead.
+    // Type-over applies to any matching closer, not only ones that were auto-inserted.
+    pub fn type_char(&mut self, c: char, auto_close: bool) {
+        let (next, prev) = {
+            let buffer = self.buffer();
+            let line = buffer.get_line(self.cursor.y).map(|l| l.as_str()).unwrap_or("");
+            let next = line.chars().nth(self.cursor.x);
+            let prev = self.cursor.x.checked_sub(1).and_then(|col| line.chars().nth(col));
+            (next, prev)
+        };
+
+        if auto_close && c == '/' && prev == Some('<') && self.highlighter.language() == Language::Html {
+            let tag = {
+                let buffer = self.buffer();
+                unclosed_tag(&buffer.lines, self.cursor.y, self.cursor.x - 1)
+            };
+            if let Some(tag) = tag {
+                self.close_tag(&tag, next == Some('>'));
+                return;
+            }
+        }
+
+        let pairs = self.auto_close_pairs();
+        let is_closer = pairs.iter().any(|&(_, close)| close == c);
+        if auto_close && is_closer && next == Some(c) {
+            self.cursor.x += 1;
+            return;
+        }
+
+        let closer = pairs
+            .iter()
+            .find(|&&(open, _)| open == c)
+            .map(|&(_, close)| close)
+            // Don't pair in front of text, or quotes used as apostrophes (`don't`)
+            .filter(|_| next.is_none_or(|n| n.is_whitespace() || pairs.iter().any(|&(_, close)| close == n)))
+            .filter(|&close| close != c || !prev.is_some_and(|p| p.is_alphanumeric()));
+
+        let command = EditCommand::InsertChar {
+            row: self.cursor.y,
+            col: self.cursor.x,
+            ch: c,
+        };
+        self.execute_command(command);
+        self.cursor.x += 1;
+
+        if let Some(close) = closer.filter(|_| auto_close) {
+            let command = EditCommand::InsertChar {
+                row: self.cursor.y,
+                col: self.cursor.x,
+                ch: close,
+            };
+            self.execute_command(command);
+        }
+    }
+
+    // Finish a `<` typed before the cursor as the closing tag for `tag`, undone in one step.
+    // With `has_close` the `>` auto-closed after the `<` is kept and stepped over.
+    fn close_tag(&mut self, tag: &str, has_close: bool) {
+        let (row, col) = (self.cursor.y, self.cursor.x);
+        let text = if has_close { format!("/{}", tag) } else { format!("/{}>", tag) };
+        self.execute_command(EditCommand::InsertText { row, col, text: text.clone() });
+        self.cursor.x += text.chars().count() + usize::from(has_close);
+    }
+
+    // Move to another row, keeping the column the cursor had when vertical movement began
+    pub fn move_to_row(&mut self, row: usize, visible_lines: usize) {
+        let goal = *self.goal_col.get_or_insert(self.cursor.x);
+        let row = self.fold_start(row.min(self.line_count().saturating_sub(1)));
+        let col = self.floor_col(row, goal);
+        (self.cursor.y, self.cursor.x) = (row, col);
+        self.adjust_scroll(visible_lines);
+    }
+
+    pub fn adjust_scroll(&mut self, visible_lines: usize) {
+        if self.cursor.y < self.offset_y {
+            self.offset_y = self.cursor.y;
+        } else if self.folds.is_empty() {
+            if self.cursor.y >= self.offset_y + visible_lines {
+                self.offset_y = self.cursor.y - visible_lines + 1;
+            }
+        } else if self.cursor_screen_row() >= visible_lines {
+            // Collapsed blocks take one row, so count the rows actually shown above the cursor
+            let shown: Vec<usize> = self.shown_rows(self.offset_y).take_while(|&row| row <= self.cursor.y).collect();
+            self.offset_y = shown[shown.len().saturating_sub(visible_lines)];
+        }
+    }
+
+    // Scroll sideways so the cursor, at screen column `cursor_col` of its line, is within
+    // `text_width` columns. The last column is kept free for the truncation marker.
+    pub fn adjust_scroll_x(&mut self, cursor_col: usize, text_width: usize) {
+        let visible = text_width.saturating_sub(1).max(1);
+        if cursor_col < self.offset_x {
+            self.offset_x = cursor_col;
+        } else if cursor_col >= self.offset_x + visible {
+            self.offset_x = cursor_col - visible + 1;
+        }
+    }
+
+    // Apply an editing or motion action for a pane showing `visible_lines` rows.
+    // Returns true if more than the cursor line may have changed on screen. Actions
+    // that need the editor are ignored.
+    pub fn apply(&mut self, action: Action, visible_lines: usize, config: &Config) -> bool {
+        match action {
+            Action::InsertChar(c) => self.type_char(c, config.auto_close),
+            Action::InsertTab => {
+                let indent = self.indent_unit();
+                self.insert_str_at_cursor(&indent);
+            }
+            Action::Newline => {
+                self.insert_newline(visible_lines);
+                return true;
+            }
+            Action::Backspace => return self.backspace(visible_lines),
+            Action::DeleteToLineStart => self.delete_to_line_start(),
+            Action::DeleteLine => {
+                self.delete_line();
+                self.adjust_scroll(visible_lines);
+                return true;
+            }
+            Action::Undo => {
+                self.undo();
+                return true;
+            }
+            Action::Redo => {
+                self.redo();
+                return true;
+            }
+            Action::SelectAll => {
+                self.select_all();
+                self.adjust_scroll(visible_lines);
+                return true;
+            }
+            Action::SelectLeft
+            | Action::SelectRight
+            | Action::SelectUp
+            | Action::SelectDown
+            | Action::SelectHome
+            | Action::SelectEnd => {
+                let anchor = self.selection_start.unwrap_or((self.cursor.y, self.cursor.x));
+                if let Some(motion) = action.selection_motion() {
+                    self.apply(motion, visible_lines, config);
+                }
+                self.selection_start = Some(anchor);
+                return true;
+            }
+            Action::Left => {
+                if self.cursor.x > 0 {
+                    self.cursor.x = self.floor_col(self.cursor.y, self.cursor.x) - 1;
+                } else if config.wrap_cursor && self.cursor.y > 0 {
+                    self.cursor.y = self.fold_start(self.cursor.y - 1);
+                    self.cursor.x = self.line_len(self.cursor.y);
+                    self.adjust_scroll(visible_lines);
+                }
+            }
+            Action::Right if self.cursor.y < self.line_count() => {
+                if self.cursor.x < self.line_len(self.cursor.y) {
+                    self.cursor.x += 1;
+                } else if config.wrap_cursor
+                    && let Some(row) = self.shown_rows(self.cursor.y).nth(1)
+                {
+                    self.cursor.y = row;
+                    self.cursor.x = 0;
+                    self.adjust_scroll(visible_lines);
+                }
+            }
+            Action::Up if self.cursor.y > 0 => self.move_to_row(self.cursor.y - 1, visible_lines),
+            // Down from a collapsed block goes past the lines it hides
+            Action::Down if self.cursor.y + 1 < self.line_count() => {
+                if let Some(row) = self.shown_rows(self.cursor.y).nth(1) {
+                    self.move_to_row(row, visible_lines);
+                }
+            }
+            // Paragraph motion: jump over the current block of text
+            Action::ParagraphUp => {
+                let row = self.buffer().prev_blank_line(self.cursor.y);
+                self.move_to_row(row, visible_lines);
+                return true;
+            }
+            Action::ParagraphDown => {
+                let row = self.buffer().next_blank_line(self.cursor.y);
+                self.move_to_row(row, visible_lines);
+                return true;
+            }
+            Action::ToggleFold => return self.toggle_fold(),
+            Action::BlockEnd => {
+                if let Some(row) = self.block_end(self.cursor.y) {
+                    let col = self.buffer().first_non_whitespace_col(row);
+                    (self.cursor.y, self.cursor.x) = (row, col);
+                    self.adjust_scroll(visible_lines);
+                    return true;
+                }
+            }
+            Action::Home => {
+                // Smart home: jump to the indentation first, then to column 0
+                let indent = self.buffer().first_non_whitespace_col(self.cursor.y);
+                self.cursor.x = if self.cursor.x == indent { 0 } else { indent };
+            }
+            Action::End => self.cursor.x = self.line_len(self.cursor.y),
+            Action::PageUp => {
+                let page = config.page_lines(visible_lines);
+                self.move_to_row(self.cursor.y.saturating_sub(page), visible_lines);
+            }
+            Action::PageDown => {
+                let page = config.page_lines(visible_lines);
+                self.move_to_row(self.cursor.y + page, visible_lines);
+            }
+            _ => {}
+        }
+        false
+    }
+
+    // Last row of the block the cursor is in: in Python the block a `:` line at `row`
+    // opens, otherwise the innermost block around it. None if that is `row` itself.
+    fn block_end(&self, row: usize) -> Option<usize> {
+        let lines = &self.buffer().lines;
+        let language = self.highlighter.language();
+        let own = indent_block_range(lines, row);
+        let block = if language == Language::Python && own.len() > 1 {
+            own
+        } else {
+            enclosing_block(lines, language, row)?
+        };
+        (block.end - 1 > row).then_some(block.end - 1)
+    }
+
+    // Split the line at the cursor, carrying its indentation over and adding a level
+    // after a line that opens a block
+    fn insert_newline(&mut self, visible_lines: usize) {
+        let current_line = self.buffer().get_line(self.cursor.y).cloned().unwrap_or_default();
+        let indent: String = current_line.chars().take_while(|c| c.is_whitespace()).collect();
+        let should_indent = current_line.trim_end().ends_with('{');
+
+        let command = EditCommand::InsertNewline {
+            row: self.cursor.y,
+            col: self.cursor.x,
+        };
+        self.execute_command(command);
+        self.cursor.y += 1;
+        self.cursor.x = 0;
+        self.insert_str_at_cursor(&indent);
+        if should_indent {
+            let extra = self.indent_unit();
+            self.insert_str_at_cursor(&extra);
+        }
+        self.adjust_scroll(visible_lines);
+    }
+
+    // Delete before the cursor: an empty pair as a unit, space indentation back to the
+    // previous tab stop, otherwise one character or the line break. Returns true if
+    // lines were joined.
+    fn backspace(&mut self, visible_lines: usize) -> bool {
+        if let Some((open, close)) = self.empty_pair_at_cursor() {
+            let col = self.cursor.x - 1;
+            let command = EditCommand::DeleteText {
+                row: self.cursor.y,
+                col,
+                text: format!("{}{}", open, close),
+            };
+            self.execute_command(command);
+            self.cursor.x = col;
+        } else if let Some(count) = self.soft_tab_width() {
+            let col = self.cursor.x - count;
+            let command = EditCommand::DeleteText {
+                row: self.cursor.y,
+                col,
+                text: " ".repeat(count),
+            };
+            self.execute_command(command);
+            self.cursor.x = col;
+        } else if self.cursor.x > 0 {
+            let col = self.floor_col(self.cursor.y, self.cursor.x).saturating_sub(1);
+            let ch = self.buffer().get_line(self.cursor.y).and_then(|line| line.chars().nth(col));
+            if let Some(ch) = ch {
+                let command = EditCommand::DeleteChar { row: self.cursor.y, col, ch };
+                self.execute_command(command);
+                self.cursor.x = col;
+            }
+        } else if self.cursor.y > 0 {
+            let prev_line_len = self.line_len(self.cursor.y - 1);
+            let deleted_line = self.buffer().get_line(self.cursor.y).cloned();
+            if let Some(deleted_line) = deleted_line {
+                let command = EditCommand::DeleteNewline {
+                    row: self.cursor.y,
+                    deleted_line,
+                };
+                self.execute_command(command);
+                self.cursor.y -= 1;
+                self.cursor.x = prev_line_len;
+                self.adjust_scroll(visible_lines);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+// The open panes and which one has focus. There is always at least one pane, and
+// every change keeps the focused index pointing at one of them.
+pub struct Panes {
+    panes: Vec<Pane>,
+    active: usize,
+}
+
+impl Panes {
+    pub fn new(first: Pane) -> Self {
+        Self { panes: vec![first], active: 0 }
+    }
+
+    pub fn count(&self) -> usize {
+        self.panes.len()
+    }
+
+    pub fn active_index(&self) -> usize {
+        self.active
+    }
+
+    pub fn active(&self) -> &Pane {
+        &self.panes[self.active]
+    }
+
+    pub fn active_mut(&mut self) -> &mut Pane {
+        &mut self.panes[self.active]
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&Pane> {
+        self.panes.get(idx)
+    }
+
+    pub fn get_mut(&mut self, idx: usize) -> Option<&mut Pane> {
+        self.panes.get_mut(idx)
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Pane> {
+        self.panes.iter_mut()
+    }
+
+    pub fn push(&mut self, pane: Pane) {
+        self.panes.push(pane);
+    }
+
+    // Focus pane `idx`, or the last pane if there is no such pane. Its cursor is
+    // clamped in case another view of its buffer changed the text.
+    pub fn focus(&mut self, idx: usize) {
+        self.active = idx.min(self.panes.len() - 1);
+        self.panes[self.active].clamp_cursor();
+    }
+
+    pub fn focus_next(&mut self) {
+        self.focus((self.active + 1) % self.panes.len());
+    }
+
+    // Swap the focused pane with the next one, keeping focus on the same pane
+    pub fn swap_with_next(&mut self) {
+        let other = (self.active + 1) % self.panes.len();
+        self.panes.swap(self.active, other);
+        self.focus(other);
+    }
+
+    // Close the focused pane unless it is the only one. Focus moves to the pane that
+    // took its place, or the new last pane.
+    pub fn close_active(&mut self) -> bool {
+        if self.panes.len() < 2 {
+            return false;
+        }
+        self.panes.remove(self.active);
+        self.focus(self.active);
+        true
+    }
+
+    // After the layout changes, scroll each pane as little as needed to keep its cursor
+    // in view. `heights` are the text rows each pane now has, in pane order.
+    pub fn fit_to_heights(&mut self, heights: &[usize]) {
+        for (pane, &height) in self.panes.iter_mut().zip(heights) {
+            pane.adjust_scroll(height.max(1));
+        }
+    }
+}
+
+// Row `percent` of the way through a buffer of `line_count` lines, clamped to the last
+pub fn line_at_percent(percent: usize, line_count: usize) -> usize {
+    (percent.min(100) * line_count / 100).min(line_count.saturating_sub(1))
+}
+
+// A `START,END` line range (1-based, inclusive) leading prompt input like
+// `10,20 part.rs`, and the rest of the input after it
+pub fn split_line_range(input: &str) -> Option<(usize, usize, &str)> {
+    let (range, rest) = input.trim_start().split_once(' ')?;
+    let (start, end) = range.split_once(',')?;
+    Some((start.parse().ok()?, end.parse().ok()?, rest.trim_start()))
+}
+
+// Elements that never have a closing tag in HTML
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr",
+];
+
+// Name of the innermost tag still open before (row, col), scanning back through the
+// buffer and skipping tags closed in between. Self-closing and void elements, comments,
+// doctypes and processing instructions are never open. An opening tag split over lines
+// counts as open, since its end isn't looked for past its own line.
+pub fn unclosed_tag(lines: &[String], row: usize, col: usize) -> Option<String> {
+    let mut closed: Vec<String> = Vec::new();
+    for idx in (0..=row.min(lines.len().checked_sub(1)?)).rev() {
+        let line = &lines[idx];
+        let end = if idx == row { byte_index(line, col) } else { line.len() };
+        for (name, closing) in tags_in(&line[..end]).into_iter().rev() {
+            if closing {
+                closed.push(name);
+            } else if closed.last().is_some_and(|last| last.eq_ignore_ascii_case(&name)) {
+                closed.pop();
+            } else {
+                return Some(name);
+            }
+        }
+    }
+    None
+}
+
+// Opening and closing tags in `text` as (name, is_closing), in order
+fn tags_in(text: &str) -> Vec<(String, bool)> {
+    let mut tags = Vec::new();
+    for (idx, _) in text.match_indices('<') {
+        let rest = &text[idx + 1..];
+        let closing = rest.starts_with('/');
+        let rest = rest.strip_prefix('/').unwrap_or(rest);
+        let name_len = rest
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '-' | '_' | ':' | '.')))
+            .unwrap_or(rest.len());
+        let name = &rest[..name_len];
+        if !name.starts_with(|c: char| c.is_alphabetic()) {
+            continue;
+        }
+        if !closing {
+            let self_closing = rest.find('>').is_some_and(|end| rest[..end].ends_with('/'));
+            if self_closing || VOID_ELEMENTS.contains(&name.to_ascii_lowercase().as_str()) {
+                continue;
+            }
+        }
+        tags.push((name.to_string(), closing));
+    }
+    tags
+}
+
+// Every `$N`, `$name` and `${name}` in a replacement template names a group of `regex`.
+// Names follow the regex crate: `$1a` is the group "1a", so `${1}a` is needed there.
+pub fn check_group_refs(regex: &Regex, template: &str) -> Result<(), String> {
+    let mut rest = template;
+    while let Some(idx) = rest.find('$') {
+        rest = &rest[idx + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            rest = after;
+            continue;
+        }
+        let (name, after) = match rest.strip_prefix('{').and_then(|r| r.split_once('}')) {
+            Some(braced) => braced,
+            None => {
+                let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+                rest.split_at(end)
+            }
+        };
+        // A `$` with no name after it is left as it is
+        let known = name.is_empty()
+            || match name.parse::<usize>() {
+                Ok(group) => group < regex.captures_len(),
+                Err(_) => regex.capture_names().flatten().any(|n| n == name),
+            };
+        if !known {
+            return Err(format!("no group {} in the pattern", name));
+        }
+        rest = after;
+    }
+    Ok(())
+}
+
+// Row of the first line indented with tabs when earlier lines were indented with
+// spaces, or the other way round. None when the indentation is consistent.
+pub fn mixed_indent_line(lines: &[String]) -> Option<usize> {
+    let mut style = None;
+    for (idx, line) in lines.iter().enumerate() {
+        let first = line.chars().next().filter(|&c| c == ' ' || c == '\t');
+        let Some(first) = first.filter(|_| !line.trim().is_empty()) else {
+            continue;
+        };
+        match style {
+            None => style = Some(first),
+            Some(style) if style != first => return Some(idx),
+            Some(_) => {}
+        }
+    }
+    None
+}
+
+// Guess a file's indentation from the leading whitespace of its lines.
+// Returns (use_spaces, width), or None when there is too little indentation to tell.
+pub fn detect_indent(lines: &[String]) -> Option<(bool, usize)> {
+    let mut tab_lines = 0;
+    let mut space_lines = 0;
+    // How often each increase in indentation between consecutive lines occurs
+    let mut steps = [0usize; 9];
+    let mut prev_indent = 0;
+
+    for line in lines.iter().filter(|l| !l.trim().is_empty()) {
+        if line.starts_with('\t') {
+            tab_lines += 1;
+            continue;
+        }
+        let indent = line.chars().take_while(|&c| c == ' ').count();
+        if indent > 0 {
+            space_lines += 1;
+        }
+        if indent > prev_indent && indent - prev_indent < steps.len() {
+            steps[indent - prev_indent] += 1;
+        }
+        prev_indent = indent;
+    }
+
+    if tab_lines == 0 && space_lines == 0 {
+        return None;
+    }
+    if tab_lines > space_lines {
+        return Some((false, 0));
+    }
+    (2..steps.len())
+        .filter(|&width| steps[width] > 0)
+        .max_by_key(|&width| (steps[width], std::cmp::Reverse(width)))
+        .map(|width| (true, width))
+}
+
+// Re-express a line's leading whitespace as tabs or spaces, keeping its visual width.
+// Anything after the indentation is left alone.
+pub fn retab_indent(line: &str, tab_width: usize, to_spaces: bool) -> String {
+    let indent_len = line.len() - line.trim_start_matches([' ', '\t']).len();
+    let mut width = 0;
+    for ch in line[..indent_len].chars() {
+        width = if ch == '\t' { (width / tab_width + 1) * tab_width } else { width + 1 };
+    }
+    let indent = if to_spaces {
+        " ".repeat(width)
+    } else {
+        "\t".repeat(width / tab_width) + &" ".repeat(width % tab_width)
+    };
+    indent + &line[indent_len..]
+}
+
+// Recompute the indentation of `lines` from their nesting: brackets for most languages,
+// plus block keywords for Bash. Python blocks have no closing marker, so there the
+// existing indentation decides the levels and only their width is made consistent.
+// The first non-blank line keeps its indentation as the base for the rest.
+pub fn reindent_lines(lines: &[String], language: Language, unit: &str, tab_width: usize) -> Vec<String> {
+    let base = lines
+        .iter()
+        .find(|l| !l.trim().is_empty())
+        .map(|l| &l[..l.len() - l.trim_start().len()])
+        .unwrap_or("");
+    let mut depth = 0usize;
+    // Python: widths of the enclosing blocks' indentation, outermost first
+    let mut widths: Vec<usize> = Vec::new();
+
+    lines
+        .iter()
+        .map(|line| {
+            let text = line.trim_start();
+            if text.is_empty() {
+                return String::new();
+            }
+            let level = if language == Language::Python {
+                let width = indent_width(&line[..line.len() - text.len()], tab_width);
+                while widths.last().is_some_and(|&w| w > width) {
+                    widths.pop();
+                }
+                if widths.last() != Some(&width) {
+                    widths.push(width);
+                }
+                widths.len() - 1
+            } else {
+                let (opens, closes, leading) = nesting(language, text);
+                let level = depth.saturating_sub(leading);
+                depth = (depth + opens).saturating_sub(closes);
+                level
+            };
+            format!("{}{}{}", base, unit.repeat(level), text)
+        })
+        .collect()
+}
+
+// Rows of the smallest multi-line block containing `row`, from the line that opens it
+// to the one that closes it. Blocks are found from brackets (and Bash keywords), or
+// from indentation under a `:` line in Python. None at the top level.
+pub fn enclosing_block(lines: &[String], language: Language, row: usize) -> Option<Range<usize>> {
+    if language == Language::Python {
+        return python_block(lines, row);
+    }
+    // Rows that opened the blocks still open, innermost last
+    let mut open: Vec<usize> = Vec::new();
+    let mut best: Option<Range<usize>> = None;
+    let mut consider = |start: usize, end: usize| {
+        let block = start..end + 1;
+        if end > start && block.contains(&row) && best.as_ref().is_none_or(|b| block.len() < b.len()) {
+            best = Some(block);
+        }
+    };
+    for (idx, line) in lines.iter().enumerate() {
+        let (opens, closes, leading) = nesting(language, line.trim_start());
+        for _ in 0..leading {
+            if let Some(start) = open.pop() {
+                consider(start, idx);
+            }
+        }
+        let trailing = closes - leading;
+        if opens >= trailing {
+            open.extend(std::iter::repeat_n(idx, opens - trailing));
+        } else {
+            for _ in 0..trailing - opens {
+                if let Some(start) = open.pop() {
+                    consider(start, idx);
+                }
+            }
+        }
+        // Every block around `row` has closed by now
+        if open.is_empty() && idx >= row {
+            break;
+        }
+    }
+    // Blocks left open run to the end
+    let last = lines.len().saturating_sub(1);
+    while let Some(start) = open.pop() {
+        consider(start, last);
+    }
+    best
+}
+
+fn python_block(lines: &[String], row: usize) -> Option<Range<usize>> {
+    let indent = |idx: usize| line_indent(&lines[idx]);
+    // Blank lines belong to the block of the next line with text
+    let row_indent = (row..lines.len()).find_map(indent).unwrap_or(0);
+    let start = (0..=row.min(lines.len().saturating_sub(1))).rev().find(|&idx| {
+        lines[idx].trim_end().ends_with(':') && indent(idx).is_some_and(|i| i < row_indent || idx == row)
+    })?;
+    let block = indent_block_range(lines, start);
+    (block.end > row && block.len() > 1).then_some(block)
+}
+
+// Rows of the block starting at `row`: the line itself and every following line
+// indented deeper than it, stopping before the first line that isn't. Blank lines
+// inside the block are included, trailing ones aren't. Just `row` on a blank line.
+pub fn indent_block_range(lines: &[String], row: usize) -> Range<usize> {
+    let Some(start_indent) = lines.get(row).and_then(|line| line_indent(line)) else {
+        return row..row + 1;
+    };
+    let mut end = row;
+    for (idx, line) in lines.iter().enumerate().skip(row + 1) {
+        match line_indent(line) {
+            Some(i) if i <= start_indent => break,
+            Some(_) => end = idx,
+            None => {}
+        }
+    }
+    row..end + 1
+}
+
+// Indentation width of a line, or None when it's blank
+fn line_indent(line: &str) -> Option<usize> {
+    (!line.trim().is_empty()).then(|| indent_width(&line[..line.len() - line.trim_start().len()], 8))
+}
+
+// Visual width of leading whitespace
+fn indent_width(indent: &str, tab_width: usize) -> usize {
+    indent.chars().fold(0, |width, ch| {
+        if ch == '\t' { (width / tab_width + 1) * tab_width } else { width + 1 }
+    })
+}
+
+// Blocks opened and closed on a line outside strings and comments, and how many of
+// the closes come before anything else (which dedents the line itself)
+fn nesting(language: Language, text: &str) -> (usize, usize, usize) {
+    const BASH_OPENERS: &[&str] = &["then", "do", "case"];
+    const BASH_CLOSERS: &[&str] = &["fi", "done", "esac", "elif"];
+    let (mut opens, mut closes, mut leading) = (0, 0, 0);
+    let mut at_start = true;
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let ch = chars[i];
+        let next = chars.get(i + 1).copied();
+        let comment = match language {
+            Language::Bash | Language::Python => ch == '#' && (i == 0 || chars[i - 1].is_whitespace()),
+            _ => ch == '/' && matches!(next, Some('/') | Some('*')),
+        };
+        if comment {
+            break;
+        }
+        match ch {
+            '"' | '`' | '\'' => {
+                // In Rust a quote not closing a char literal starts a lifetime
+                let lifetime = language == Language::Rust
+                    && ch == '\''
+                    && next != Some('\\')
+                    && chars.get(i + 2) != Some(&'\'');
+                if !lifetime {
+                    i += 1;
+                    while i < chars.len() && chars[i] != ch {
+                        i += if chars[i] == '\\' { 2 } else { 1 };
+                    }
+                }
+            }
+            '{' | '(' | '[' => opens += 1,
+            '}' | ')' | ']' => {
+                closes += 1;
+                leading += usize::from(at_start);
+                i += 1;
+                continue;
+            }
+            c if language == Language::Bash && (c.is_alphanumeric() || c == '_') => {
+                let end = (i..chars.len())
+                    .find(|&j| !(chars[j].is_alphanumeric() || chars[j] == '_'))
+                    .unwrap_or(chars.len());
+                let word: String = chars[i..end].iter().collect();
+                if word == "else" || BASH_CLOSERS.contains(&word.as_str()) {
+                    closes += 1;
+                    opens += usize::from(word == "else");
+                    leading += usize::from(at_start);
+                } else if BASH_OPENERS.contains(&word.as_str()) {
+                    opens += 1;
+                }
+                at_start = false;
+                i = end;
+                continue;
+            }
+            _ => {}
+        }
+        if !ch.is_whitespace() {
+            at_start = false;
+        }
+        i += 1;
+    }
+    (opens, closes, leading)
+}
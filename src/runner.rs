@@ -0,0 +1,187 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// Output beyond this many lines is dropped so a chatty program can't exhaust memory
+const MAX_OUTPUT_LINES: usize = 10_000;
+
+// A command running in the background whose output is collected as it arrives
+pub struct RunningCommand {
+    pub title: String,
+    child: Child,
+    output: Arc<Mutex<Vec<String>>>,
+    status: Option<ExitStatus>,
+    killed: bool,
+    seen_lines: usize,
+}
+
+impl RunningCommand {
+    pub fn spawn(command_line: &str, shell: Option<&str>, cwd: &Path) -> io::Result<Self> {
+        let mut child = shell_command(command_line, shell)
+            .current_dir(cwd)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let output = Arc::new(Mutex::new(Vec::new()));
+        if let Some(stdout) = child.stdout.take() {
+            collect_lines(stdout, Arc::clone(&output));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            collect_lines(stderr, Arc::clone(&output));
+        }
+
+        Ok(Self {
+            title: command_line.to_string(),
+            child,
+            output,
+            status: None,
+            killed: false,
+            seen_lines: 0,
+        })
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.status.is_none()
+    }
+
+    // Check for new output or exit. Returns true if there is something new to show.
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        if self.status.is_none()
+            && let Ok(Some(status)) = self.child.try_wait()
+        {
+            self.status = Some(status);
+            changed = true;
+        }
+        let len = self.output.lock().map(|o| o.len()).unwrap_or(0);
+        if len != self.seen_lines {
+            self.seen_lines = len;
+            changed = true;
+        }
+        changed
+    }
+
+    pub fn kill(&mut self) {
+        if self.status.is_none() {
+            let _ = self.child.kill();
+            self.status = self.child.wait().ok();
+            self.killed = true;
+        }
+    }
+
+    pub fn status_text(&self) -> String {
+        match (&self.status, self.killed) {
+            (_, true) => "killed".to_string(),
+            (None, _) => "running...".to_string(),
+            (Some(status), _) => match status.code() {
+                Some(code) => format!("exit status {}", code),
+                None => "terminated by signal".to_string(),
+            },
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = self.output.lock().map(|o| o.clone()).unwrap_or_default();
+        if lines.len() >= MAX_OUTPUT_LINES {
+            lines.push(format!("[output truncated at {} lines]", MAX_OUTPUT_LINES));
+        }
+        if !self.is_running() {
+            lines.push(format!("[{}]", self.status_text()));
+        }
+        lines
+    }
+}
+
+impl Drop for RunningCommand {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+// Run a command line through the configured shell (e.g. "bash -c"), or the platform default
+pub fn shell_command(command_line: &str, shell: Option<&str>) -> Command {
+    if let Some(mut parts) = shell.map(|s| s.split_whitespace())
+        && let Some(program) = parts.next()
+    {
+        let mut command = Command::new(program);
+        command.args(parts).arg(command_line);
+        return command;
+    }
+    if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.args(["/C", command_line]);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.args(["-c", command_line]);
+        command
+    }
+}
+
+// `text`, e.g. a file's path, quoted as one word for the shell `shell_command` runs it
+// with. cmd gets double quotes (Windows file names can't contain one), with `%` left
+// outside them so it can be escaped; sh and other Unix shells get single quotes, inside
+// which nothing is special but the quote itself.
+pub fn shell_quote(text: &str, shell: Option<&str>) -> String {
+    let program = match shell.and_then(|s| s.split_whitespace().next()) {
+        Some(program) => Path::new(program).file_stem().and_then(|s| s.to_str()).unwrap_or(program),
+        None if cfg!(windows) => "cmd",
+        None => "sh",
+    };
+    if program.eq_ignore_ascii_case("cmd") {
+        format!("\"{}\"", text.replace('%', "\"^%\""))
+    } else {
+        format!("'{}'", text.replace('\'', "'\\''"))
+    }
+}
+
+// Run a command to completion, feeding `input` to its stdin, and return its stdout.
+// A non-zero exit or any stderr output is reported as an error.
+pub fn run_filter(command_line: &str, shell: Option<&str>, input: Option<String>) -> io::Result<String> {
+    let mut child = shell_command(command_line, shell)
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Feed stdin from another thread so a command that writes a lot before
+    // reading all its input can't deadlock against us
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        thread::spawn(move || {
+            let _ = stdin.write_all(input.as_bytes());
+        });
+    }
+
+    let output = child.wait_with_output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() || !stderr.trim().is_empty() {
+        let detail = stderr.lines().next().unwrap_or("").trim();
+        let status = output
+            .status
+            .code()
+            .map(|c| format!("exit status {}", c))
+            .unwrap_or_else(|| "terminated by signal".to_string());
+        return Err(io::Error::other(if detail.is_empty() {
+            status
+        } else {
+            format!("{} ({})", detail, status)
+        }));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn collect_lines(stream: impl Read + Send + 'static, output: Arc<Mutex<Vec<String>>>) {
+    thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else { break };
+            let Ok(mut output) = output.lock() else { break };
+            if output.len() < MAX_OUTPUT_LINES {
+                output.push(line);
+            }
+        }
+    });
+}
@@ -0,0 +1,61 @@
+// Commands read from a `--commands` file and run on the first buffer at startup, one
+// per line. Blank lines and lines starting with `#` are skipped.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Command {
+    // `goto N`: move to 1-based line N
+    Goto(usize),
+    // `search TEXT`: move to the next match of TEXT
+    Search(String),
+    // `replace FROM TO`: replace every FROM with the rest of the line (which may be empty)
+    Replace { from: String, to: String },
+    // `replace-regex PATTERN TO`: like replace, with `$1` or `${name}` in TO for groups.
+    // PATTERN ends at the first space, so spaces in it are written `\s` or `[ ]`.
+    ReplaceRegex { pattern: String, to: String },
+    // `save`: write the buffer to its file
+    Save,
+}
+
+// The commands in `text` with the 1-based line each came from. An error names the
+// first line that couldn't be understood.
+pub fn parse(text: &str) -> Result<Vec<(usize, Command)>, String> {
+    let mut commands = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let command = parse_line(line).map_err(|e| format!("line {}: {}", idx + 1, e))?;
+        commands.push((idx + 1, command));
+    }
+    Ok(commands)
+}
+
+fn parse_line(line: &str) -> Result<Command, String> {
+    let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let rest = rest.trim_start();
+    match name {
+        "goto" => match rest.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(Command::Goto(n)),
+            _ => Err(format!("invalid line number: {}", rest)),
+        },
+        "search" if !rest.is_empty() => Ok(Command::Search(rest.to_string())),
+        "search" => Err("search needs the text to find".to_string()),
+        "replace" => {
+            let (from, to) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            if from.is_empty() {
+                return Err("replace needs the text to replace".to_string());
+            }
+            Ok(Command::Replace { from: from.to_string(), to: to.trim_start().to_string() })
+        }
+        "replace-regex" => {
+            let (pattern, to) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            if pattern.is_empty() {
+                return Err("replace-regex needs a pattern".to_string());
+            }
+            Ok(Command::ReplaceRegex { pattern: pattern.to_string(), to: to.trim_start().to_string() })
+        }
+        "save" if rest.is_empty() => Ok(Command::Save),
+        "save" => Err("save takes no arguments".to_string()),
+        _ => Err(format!("unknown command: {}", name)),
+    }
+}
@@ -0,0 +1,79 @@
+use crate::config;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const SESSION_FILE: &str = "session";
+
+// The files open in the panes when the editor last quit, saved in the config dir as
+//
+//     split vertical
+//     active 1
+//     file 12 4 /home/me/src/main.rs
+//
+// with one `file` line (1-based line, character column, path) per pane. Lines that
+// can't be read are skipped.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Session {
+    // Whether two panes were side by side; None for a single pane
+    pub vertical: Option<bool>,
+    // Index into `files` of the focused pane
+    pub active: usize,
+    pub files: Vec<SessionFile>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct SessionFile {
+    pub path: PathBuf,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Session {
+    pub fn parse(text: &str) -> Self {
+        let mut session = Session::default();
+        for line in text.lines() {
+            let (key, rest) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "split" => session.vertical = Some(rest == "vertical"),
+                "active" => session.active = rest.parse().unwrap_or(0),
+                "file" => {
+                    let mut parts = rest.splitn(3, ' ');
+                    let line = parts.next().and_then(|n| n.parse().ok());
+                    let col = parts.next().and_then(|n| n.parse().ok());
+                    if let (Some(line), Some(col), Some(path)) = (line, col, parts.next())
+                        && !path.is_empty()
+                    {
+                        session.files.push(SessionFile { path: PathBuf::from(path), line, col });
+                    }
+                }
+                _ => {}
+            }
+        }
+        session
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        if let Some(vertical) = self.vertical {
+            text += if vertical { "split vertical\n" } else { "split horizontal\n" };
+        }
+        text += &format!("active {}\n", self.active);
+        for file in &self.files {
+            text += &format!("file {} {} {}\n", file.line, file.col, file.path.display());
+        }
+        text
+    }
+
+    // The saved session, if there is one
+    pub fn load() -> Option<Self> {
+        let path = config::config_dir()?.join(SESSION_FILE);
+        fs::read_to_string(path).ok().map(|text| Self::parse(&text))
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let dir = config::config_dir().ok_or_else(|| io::Error::other("no config directory"))?;
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(SESSION_FILE), self.to_text())
+    }
+}
@@ -0,0 +1,106 @@
+use crate::config;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+
+const SYSTEM_DICTIONARIES: &[&str] = &["/usr/share/dict/words", "/usr/dict/words"];
+
+pub struct SpellChecker {
+    words: HashSet<String>,
+    personal_path: Option<PathBuf>,
+}
+
+impl SpellChecker {
+    // Reads the word list from the config dir (words.txt) or the system dictionary,
+    // plus the personal dictionary. This can be slow, so it runs off the UI thread.
+    pub fn load() -> Self {
+        let dir = config::config_dir();
+        let mut candidates: Vec<PathBuf> = Vec::new();
+        if let Some(dir) = &dir {
+            candidates.push(dir.join("words.txt"));
+        }
+        candidates.extend(SYSTEM_DICTIONARIES.iter().map(PathBuf::from));
+
+        let mut words = HashSet::new();
+        if let Some(content) = candidates.iter().find_map(|p| fs::read_to_string(p).ok()) {
+            Self::add_lines(&mut words, &content);
+        }
+
+        let personal_path = dir.map(|d| d.join("personal.dic"));
+        if let Some(content) = personal_path.as_ref().and_then(|p| fs::read_to_string(p).ok()) {
+            Self::add_lines(&mut words, &content);
+        }
+
+        Self { words, personal_path }
+    }
+
+    fn add_lines(words: &mut HashSet<String>, content: &str) {
+        for line in content.lines() {
+            let word = line.trim();
+            if !word.is_empty() {
+                words.insert(word.to_lowercase());
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    pub fn is_correct(&self, word: &str) -> bool {
+        // Single letters and ALLCAPS acronyms are not worth flagging
+        if word.chars().count() < 2 || word.chars().all(|c| c.is_uppercase()) {
+            return true;
+        }
+        let lower = word.to_lowercase();
+        self.words.contains(&lower)
+            || lower
+                .strip_suffix("'s")
+                .is_some_and(|stem| self.words.contains(stem))
+    }
+
+    // Byte ranges of the misspelled words in `text`
+    pub fn misspelled(&self, text: &str) -> Vec<Range<usize>> {
+        words_in(text)
+            .filter(|range| !self.is_correct(&text[range.clone()]))
+            .collect()
+    }
+
+    pub fn add_word(&mut self, word: &str) -> io::Result<()> {
+        let lower = word.to_lowercase();
+        if let Some(path) = &self.personal_path {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", lower)?;
+        }
+        self.words.insert(lower);
+        Ok(())
+    }
+}
+
+// Byte ranges of the alphabetic words in `text`; an apostrophe between letters is kept
+pub fn words_in(text: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    let mut chars = text.char_indices().peekable();
+    std::iter::from_fn(move || {
+        let (start, _) = chars.by_ref().find(|(_, c)| c.is_alphabetic())?;
+        let mut end = text.len();
+        while let Some(&(idx, c)) = chars.peek() {
+            if c.is_alphabetic() || (c == '\'' && text[idx + 1..].starts_with(char::is_alphabetic)) {
+                chars.next();
+            } else {
+                end = idx;
+                break;
+            }
+        }
+        Some(start..end)
+    })
+}
+
+// The word touching byte column `col`, if any
+pub fn word_at(text: &str, col: usize) -> Option<Range<usize>> {
+    words_in(text).find(|range| range.start <= col && col <= range.end)
+}
@@ -0,0 +1,282 @@
+use axis::buffer::{
+    byte_index, char_index, end_of_text, EditCommand, SearchKind, SearchOptions, SearchPattern, TextBuffer, LONG_LINE_LEN,
+};
+
+fn buffer(text: &str) -> TextBuffer {
+    TextBuffer::from_string(text.to_string())
+}
+
+const PLAIN: SearchOptions = SearchOptions { wrap: true, whole_word: false, kind: SearchKind::Literal };
+
+#[test]
+fn commands_undo_to_the_original_text() {
+    let mut buf = buffer("hello\nworld");
+    let commands = [
+        EditCommand::InsertChar { row: 0, col: 5, ch: '!' },
+        EditCommand::InsertNewline { row: 1, col: 2 },
+        EditCommand::DeleteText { row: 0, col: 0, text: "he".to_string() },
+        EditCommand::ReplaceLines {
+            start: 1,
+            old: vec!["wo".to_string(), "rld".to_string()],
+            new: vec!["earth".to_string()],
+        },
+        EditCommand::DeleteLine { row: 1, content: "earth".to_string() },
+        EditCommand::InsertText { row: 0, col: 4, text: "\nearth".to_string() },
+    ];
+    for command in &commands {
+        command.redo(&mut buf);
+    }
+    assert_eq!(buf.to_string(), "llo!\nearth");
+    for command in commands.iter().rev() {
+        command.undo(&mut buf);
+    }
+    assert_eq!(buf.to_string(), "hello\nworld");
+}
+
+#[test]
+fn delete_newline_joins_and_restores_lines() {
+    let mut buf = buffer("ab\ncd");
+    let command = EditCommand::DeleteNewline { row: 1, deleted_line: "cd".to_string() };
+    command.redo(&mut buf);
+    assert_eq!(buf.lines, ["abcd"]);
+    command.undo(&mut buf);
+    assert_eq!(buf.lines, ["ab", "cd"]);
+}
+
+#[test]
+fn columns_count_characters() {
+    let mut buf = buffer("héllo");
+    assert_eq!(buf.floor_col(0, 2), 2);
+    assert_eq!(buf.floor_col(0, 100), 5);
+    assert_eq!(buf.floor_col(5, 2), 0);
+    assert_eq!((byte_index("héllo", 2), byte_index("héllo", 9)), (3, 6));
+    assert_eq!(char_index("héllo", 3), 2);
+
+    // Edits after the é land after it, not inside it
+    EditCommand::InsertChar { row: 0, col: 2, ch: 'x' }.redo(&mut buf);
+    assert_eq!(buf.lines, ["héxllo"]);
+    EditCommand::DeleteChar { row: 0, col: 1, ch: 'é' }.redo(&mut buf);
+    assert_eq!(buf.lines, ["hxllo"]);
+    EditCommand::DeleteChar { row: 0, col: 1, ch: 'é' }.undo(&mut buf);
+    EditCommand::InsertNewline { row: 0, col: 2 }.redo(&mut buf);
+    assert_eq!(buf.lines, ["hé", "xllo"]);
+    assert_eq!(buf.text_range((0, 1), (1, 1)), "é\nx");
+    assert_eq!(buf.search("x", 0, 0, PLAIN), Some((1, 0, false)));
+    assert_eq!(buf.search("é", 1, 0, PLAIN), Some((0, 1, true)));
+}
+
+#[test]
+fn text_range_spans_lines() {
+    let buf = buffer("one\ntwo\nthree");
+    assert_eq!(buf.text_range((0, 1), (2, 2)), "ne\ntwo\nth");
+    assert_eq!(buf.text_range((1, 0), (1, 3)), "two");
+}
+
+#[test]
+fn search_finds_the_next_match_and_wraps() {
+    let buf = buffer("foo bar\nbarfoo");
+    assert_eq!(buf.search("bar", 0, 5, PLAIN), Some((1, 0, false)));
+    assert_eq!(buf.search("foo", 1, 4, PLAIN), Some((0, 0, true)));
+    let no_wrap = SearchOptions { wrap: false, ..PLAIN };
+    assert_eq!(buf.search("foo", 1, 4, no_wrap), None);
+    assert_eq!(buf.search("", 0, 0, PLAIN), None);
+}
+
+#[test]
+fn whole_word_search_skips_partial_words() {
+    let buf = buffer("foo bar\nbarfoo");
+    let whole_word = SearchOptions { whole_word: true, ..PLAIN };
+    assert_eq!(buf.search("bar", 1, 0, whole_word), Some((0, 4, true)));
+    assert_eq!(buf.search("foo_", 0, 0, whole_word), None);
+}
+
+#[test]
+fn search_finds_matches_overlapping_the_last_one() {
+    let buf = buffer("aaaa\nabab");
+    assert_eq!(buf.search("aa", 0, 1, PLAIN), Some((0, 1, false)));
+    assert_eq!(buf.search_after("aa", 0, 0, PLAIN), Some((0, 1, false)));
+    assert_eq!(buf.search_after("aa", 0, 2, PLAIN), Some((0, 0, true)));
+    assert_eq!(buf.search("ab", 1, 1, PLAIN), Some((1, 2, false)));
+    assert_eq!(buf.search_backward("aa", 0, 2, PLAIN), Some((0, 1, false)));
+
+    // A regex resumed mid-line still sees what's before it
+    let regex = SearchOptions { kind: SearchKind::Regex, ..PLAIN };
+    assert_eq!(buf.search("a+", 0, 1, regex), Some((0, 1, false)));
+    assert_eq!(buf.search(r"\ba", 0, 1, regex), Some((1, 0, false)));
+    assert_eq!(buf.search("^b", 1, 1, regex), None);
+}
+
+#[test]
+fn paragraph_motion_stops_at_blank_lines() {
+    let buf = buffer("a\nb\n\nc\nd\n  \ne");
+    assert_eq!(buf.next_blank_line(0), 2);
+    assert_eq!(buf.next_blank_line(2), 5);
+    assert_eq!(buf.next_blank_line(5), 6);
+    assert_eq!(buf.prev_blank_line(4), 2);
+    assert_eq!(buf.prev_blank_line(2), 0);
+}
+
+#[test]
+fn insert_text_within_a_line() {
+    let mut buf = buffer("hello world");
+    let command = EditCommand::InsertText { row: 0, col: 5, text: ", dear".to_string() };
+    command.redo(&mut buf);
+    assert_eq!(buf.lines, ["hello, dear world"]);
+    command.undo(&mut buf);
+    assert_eq!(buf.lines, ["hello world"]);
+}
+
+#[test]
+fn insert_text_with_newlines_splits_the_line() {
+    let mut buf = buffer("ab\ncd");
+    let command = EditCommand::InsertText { row: 0, col: 1, text: "1\n22\n3".to_string() };
+    command.redo(&mut buf);
+    assert_eq!(buf.lines, ["a1", "22", "3b", "cd"]);
+    command.undo(&mut buf);
+    assert_eq!(buf.lines, ["ab", "cd"]);
+
+    let command = EditCommand::InsertText { row: 1, col: 2, text: "\n".to_string() };
+    command.redo(&mut buf);
+    assert_eq!(buf.lines, ["ab", "cd", ""]);
+    command.undo(&mut buf);
+    assert_eq!(buf.lines, ["ab", "cd"]);
+}
+
+#[test]
+fn delete_text_across_lines() {
+    let mut buf = buffer("one\ntwo\nthree");
+    let command = EditCommand::DeleteText { row: 0, col: 2, text: "e\ntwo\nth".to_string() };
+    command.redo(&mut buf);
+    assert_eq!(buf.lines, ["onree"]);
+    command.undo(&mut buf);
+    assert_eq!(buf.lines, ["one", "two", "three"]);
+}
+
+#[test]
+fn end_of_text_positions() {
+    assert_eq!(end_of_text(2, 3, "abc"), (2, 6));
+    assert_eq!(end_of_text(2, 3, "abc\nde"), (3, 2));
+    assert_eq!(end_of_text(0, 5, "\n\n"), (2, 0));
+}
+
+#[test]
+fn search_after_moves_past_the_current_match() {
+    let no_wrap = SearchOptions { wrap: false, ..PLAIN };
+
+    // No matches
+    let buf = buffer("abc\ndef");
+    assert_eq!(buf.search_after("x", 0, 0, PLAIN), None);
+
+    // A single match is found again only by wrapping around to it
+    let buf = buffer("one foo\ntwo");
+    assert_eq!(buf.search_after("foo", 0, 4, PLAIN), Some((0, 4, true)));
+    assert_eq!(buf.search_after("foo", 0, 4, no_wrap), None);
+
+    // Several matches are visited in order, then the search wraps to the first
+    let buf = buffer("foo foo\nbar\nfoo");
+    assert_eq!(buf.search_after("foo", 0, 0, PLAIN), Some((0, 4, false)));
+    assert_eq!(buf.search_after("foo", 0, 4, PLAIN), Some((2, 0, false)));
+    assert_eq!(buf.search_after("foo", 2, 0, PLAIN), Some((0, 0, true)));
+    assert_eq!(buf.search_after("foo", 2, 0, no_wrap), None);
+}
+
+#[test]
+fn long_lines_are_detected() {
+    assert!(!buffer("short\nlines").has_long_lines());
+    assert!(!buffer(&"x".repeat(LONG_LINE_LEN)).has_long_lines());
+    let minified = format!("header\n{}", "x".repeat(LONG_LINE_LEN + 1));
+    assert!(buffer(&minified).has_long_lines());
+}
+
+#[test]
+fn write_to_streams_the_same_text_as_to_string() {
+    for text in ["", "one", "one\ntwo", "a\n\nb\n"] {
+        let buf = buffer(text);
+        let mut out = Vec::new();
+        buf.write_to(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), buf.to_string());
+    }
+}
+
+#[test]
+fn search_backward_finds_the_previous_match_and_wraps() {
+    let no_wrap = SearchOptions { wrap: false, ..PLAIN };
+    let buf = buffer("foo foo\nbar\nfoo");
+    assert_eq!(buf.search_backward("foo", 2, 0, PLAIN), Some((0, 4, false)));
+    assert_eq!(buf.search_backward("foo", 0, 4, PLAIN), Some((0, 0, false)));
+    assert_eq!(buf.search_backward("foo", 0, 0, PLAIN), Some((2, 0, true)));
+    assert_eq!(buf.search_backward("foo", 0, 0, no_wrap), None);
+
+    // A single match is found again only by wrapping around to it
+    let buf = buffer("one foo\ntwo");
+    assert_eq!(buf.search_backward("foo", 0, 4, PLAIN), Some((0, 4, true)));
+    assert_eq!(buf.search_backward("", 1, 0, PLAIN), None);
+}
+
+#[test]
+fn queries_with_line_breaks_match_across_lines() {
+    let buf = buffer("one two\nthree\nfour two\nthree");
+    assert_eq!(buf.search("two\nthree", 0, 0, PLAIN), Some((0, 4, false)));
+    assert_eq!(buf.search_after("two\nthree", 0, 4, PLAIN), Some((2, 5, false)));
+    assert_eq!(buf.search_after("two\nthree", 2, 5, PLAIN), Some((0, 4, true)));
+    assert_eq!(buf.search_backward("two\nthree", 2, 5, PLAIN), Some((0, 4, false)));
+    assert_eq!(buf.search("two\nthree\nfour", 0, 0, PLAIN), Some((0, 4, false)));
+    assert_eq!(buf.search("two\nfour", 0, 0, PLAIN), None);
+
+    // A leading line break matches at the end of a line
+    assert_eq!(buf.search("\nthree", 0, 0, PLAIN), Some((0, 7, false)));
+    assert_eq!(buf.search_after("\nthree", 0, 7, PLAIN), Some((2, 8, false)));
+
+    // The highlight covers the end of the first line and the start of the last
+    assert_eq!(buf.match_spans(0, &SearchPattern::Literal("two\nthree"), PLAIN), vec![4..7]);
+    assert_eq!(buf.match_spans(1, &SearchPattern::Literal("two\nthree"), PLAIN), vec![0..5]);
+    assert_eq!(buf.match_spans(1, &SearchPattern::Literal("two\nthree\nfour"), PLAIN), vec![0..5]);
+    assert_eq!(buf.match_spans(2, &SearchPattern::Literal("two\nthree\nfour"), PLAIN), vec![0..4]);
+}
+
+#[test]
+fn search_matches_regex_patterns_within_lines() {
+    let buf = buffer("let x = 10;\nfoo(42, y)\nbar");
+    let regex = SearchOptions { kind: SearchKind::Regex, ..PLAIN };
+    assert_eq!(buf.search(r"\d+", 0, 0, regex), Some((0, 8, false)));
+    // Like literal text, the next match may start inside the last one
+    assert_eq!(buf.search_after(r"\d+", 0, 8, regex), Some((0, 9, false)));
+    assert_eq!(buf.search_after(r"\d+", 0, 9, regex), Some((1, 4, false)));
+    assert_eq!(buf.search_backward(r"\d+", 2, 0, regex), Some((1, 5, false)));
+    assert_eq!(buf.search("^bar$", 0, 0, regex), Some((2, 0, false)));
+    // The same query as literal text doesn't match
+    assert_eq!(buf.search(r"\d+", 0, 0, PLAIN), None);
+
+    let whole_word = SearchOptions { whole_word: true, ..regex };
+    assert_eq!(buf.search("[a-z]", 0, 0, whole_word), Some((0, 4, false)));
+
+    // An invalid pattern finds nothing rather than failing
+    assert_eq!(buf.search("(", 0, 0, regex), None);
+    assert!(SearchPattern::new("(", SearchKind::Regex).is_err());
+
+    let pattern = SearchPattern::new(r"\d", SearchKind::Regex).unwrap();
+    assert_eq!(buf.match_spans(1, &pattern, regex), vec![4..5, 5..6]);
+    // Empty matches aren't highlighted
+    let pattern = SearchPattern::new("z*", SearchKind::Regex).unwrap();
+    assert_eq!(buf.match_spans(0, &pattern, regex), Vec::<std::ops::Range<usize>>::new());
+}
+
+#[test]
+fn match_spans_are_the_matches_search_lands_on() {
+    let buf = buffer("aaaa\nfoo food foo_bar (foo)\nwörd wörds");
+    // Overlapping matches are highlighted, as find-next stops at each of them
+    assert_eq!(buf.match_spans(0, &SearchPattern::Literal("aa"), PLAIN), vec![0..2, 1..3, 2..4]);
+    assert_eq!(buf.search_after("aa", 0, 0, PLAIN), Some((0, 1, false)));
+
+    // Whole-word search skips matches inside longer words, and so does the highlight
+    let whole_word = SearchOptions { whole_word: true, ..PLAIN };
+    assert_eq!(buf.match_spans(1, &SearchPattern::Literal("foo"), whole_word), vec![0..3, 18..21]);
+    assert_eq!(buf.match_spans(1, &SearchPattern::Literal("foo"), PLAIN).len(), 4);
+    assert_eq!(buf.match_spans(2, &SearchPattern::Literal("wörd"), whole_word), vec![0..5]);
+    let regex = SearchOptions { kind: SearchKind::Regex, ..whole_word };
+    let pattern = SearchPattern::new("fo+", SearchKind::Regex).unwrap();
+    assert_eq!(buf.match_spans(1, &pattern, regex), vec![0..3, 18..21]);
+    let spanning = SearchPattern::Literal("\nfoo food");
+    assert_eq!(buf.match_spans(1, &spanning, PLAIN), vec![0..8]);
+    assert_eq!(buf.match_spans(1, &spanning, whole_word), Vec::<std::ops::Range<usize>>::new());
+}
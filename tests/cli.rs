@@ -0,0 +1,50 @@
+use axis::cli::parse;
+
+fn args(list: &[&str]) -> impl Iterator<Item = String> {
+    list.iter().map(|s| s.to_string()).collect::<Vec<_>>().into_iter()
+}
+
+#[test]
+fn files_take_the_preceding_line_number() {
+    let parsed = parse(args(&["+3", "a.rs", "b.rs", "+10", "c.rs"])).unwrap();
+    assert_eq!(
+        parsed.files,
+        [
+            ("a.rs".to_string(), Some(3)),
+            ("b.rs".to_string(), None),
+            ("c.rs".to_string(), Some(10)),
+        ]
+    );
+}
+
+#[test]
+fn options() {
+    let parsed = parse(args(&[
+        "-R", "--no-line-numbers", "--tab-width", "2", "--lang", "python", "--commands", "fix.txt", "--batch", "x",
+    ]))
+    .unwrap();
+    assert!(parsed.read_only);
+    assert!(parsed.no_line_numbers);
+    assert_eq!(parsed.tab_width, Some(2));
+    assert_eq!(parsed.language.as_deref(), Some("python"));
+    assert_eq!(parsed.commands.as_deref(), Some("fix.txt"));
+    assert!(parsed.batch);
+    assert!(!parsed.help);
+    assert!(parse(args(&["--help"])).unwrap().help);
+}
+
+#[test]
+fn double_dash_ends_options() {
+    let parsed = parse(args(&["--", "-R", "+5"])).unwrap();
+    assert!(!parsed.read_only);
+    assert_eq!(parsed.files, [("-R".to_string(), None), ("+5".to_string(), None)]);
+}
+
+#[test]
+fn bad_arguments_are_errors() {
+    assert!(parse(args(&["--bogus"])).is_err());
+    assert!(parse(args(&["+x", "a"])).is_err());
+    assert!(parse(args(&["--tab-width", "0"])).is_err());
+    assert!(parse(args(&["--tab-width"])).is_err());
+    assert!(parse(args(&["--lang"])).is_err());
+}
@@ -0,0 +1,72 @@
+use axis::config::{Config, HighlightRules, LanguageSettings, PageScroll};
+
+#[test]
+fn language_sections_override_only_what_they_set() {
+    let config = Config::from_content(
+        "tab_width = 8\n\
+         [lang.markdown]\n\
+         show_line_numbers = false\n\
+         [lang.Python]\n\
+         tab_width = 2\n\
+         use_spaces = true\n\
+         auto_close_pairs = \"()<>x\"\n",
+    );
+    assert_eq!(config.tab_width, 8);
+    assert_eq!(
+        config.language_settings("markdown"),
+        LanguageSettings { show_line_numbers: Some(false), ..LanguageSettings::default() }
+    );
+    let python = config.language_settings("python");
+    assert_eq!((python.tab_width, python.use_spaces, python.show_line_numbers), (Some(2), Some(true), None));
+    assert_eq!(python.auto_close_pairs, Some(vec![('(', ')'), ('<', '>')]));
+    assert_eq!(config.language_settings("rust"), LanguageSettings::default());
+}
+
+#[test]
+fn highlight_sections_keep_their_rules_in_order() {
+    let config = Config::from_content(
+        "[highlight.Log]\n\
+         extensions = [\"log\", \"out\"]\n\
+         language = \"bash\"\n\
+         keyword = \"ERROR|WARN\"\n\
+         number = \"\\d+\"\n\
+         keyword = \"^\\[.*?\\]\"\n",
+    );
+    assert_eq!(
+        config.highlight_rules,
+        [HighlightRules {
+            name: "log".to_string(),
+            extensions: vec!["log".to_string(), "out".to_string()],
+            language: Some("bash".to_string()),
+            rules: vec![
+                ("keyword".to_string(), "ERROR|WARN".to_string()),
+                ("number".to_string(), "\\d+".to_string()),
+                ("keyword".to_string(), "^\\[.*?\\]".to_string()),
+            ],
+        }]
+    );
+}
+
+#[test]
+fn large_file_limit_is_in_megabytes() {
+    assert_eq!(Config::default().large_file_mb, 100);
+    assert_eq!(Config::from_content("large_file_mb = 0").large_file_mb, 0);
+    assert_eq!(Config::from_content("large_file_mb = lots").large_file_mb, 100);
+}
+
+#[test]
+fn session_restore_is_off_by_default() {
+    assert!(!Config::default().restore_last_session);
+    assert!(Config::from_content("restore_last_session = true").restore_last_session);
+}
+
+#[test]
+fn page_scroll_is_a_fraction_of_the_view_or_a_line_count() {
+    assert_eq!(Config::default().page_lines(24), 24);
+    assert_eq!(Config::from_content("page_scroll = 0.9").page_lines(10), 9);
+    assert_eq!(Config::from_content("page_scroll = 0.01").page_lines(10), 1);
+    assert_eq!(Config::from_content("page_scroll = 5").page_lines(24), 5);
+    // Out of range or unreadable values keep the full page
+    assert!(Config::from_content("page_scroll = 1.5").page_scroll == PageScroll::Fraction(1.0));
+    assert!(Config::from_content("page_scroll = half").page_scroll == PageScroll::Fraction(1.0));
+}
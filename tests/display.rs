@@ -0,0 +1,92 @@
+use axis::display::{
+    clip_line, control_notation, describe_char, display_width, elastic_tab_padding, expand_tabs, expanded_index,
+    format_clock, printable, shorten_path, skip_columns,
+};
+use std::path::Path;
+
+#[test]
+fn control_characters_use_caret_notation() {
+    assert_eq!(control_notation('\r').as_deref(), Some("^M"));
+    assert_eq!(control_notation('\x1b').as_deref(), Some("^["));
+    assert_eq!(control_notation('\x7f').as_deref(), Some("^?"));
+    assert_eq!(control_notation('\t'), None);
+    assert_eq!(control_notation('a'), None);
+    assert_eq!(printable("a\x1b[31m"), "a^[[31m");
+    assert_eq!(display_width("a\x00b"), 4);
+}
+
+#[test]
+fn clip_line_leaves_room_for_the_marker() {
+    assert_eq!(clip_line("hello", 5), ("hello", false));
+    assert_eq!(clip_line("hello!", 5), ("hell", true));
+    assert_eq!(clip_line("héllo!", 5), ("héll", true));
+    assert_eq!(clip_line("a\rbcd", 4), ("a\r", true));
+    assert_eq!(clip_line("ab\rcd", 4), ("ab", true));
+}
+
+#[test]
+fn clip_line_never_splits_a_character() {
+    // Cutting at a byte count would land inside these
+    assert_eq!(clip_line("abc→def", 5), ("abc→", true));
+    assert_eq!(clip_line("aéééééé", 4), ("aéé", true));
+    // A wide character that would cross the edge is left out whole
+    assert_eq!(clip_line("ab😀cd", 4), ("ab", true));
+    assert_eq!(clip_line("ab😀cd", 5), ("ab😀", true));
+}
+
+#[test]
+fn skip_columns_starts_on_a_whole_character() {
+    assert_eq!(skip_columns("hello", 0), 0);
+    assert_eq!(skip_columns("hello", 2), 2);
+    assert_eq!(skip_columns("héllo", 2), 3);
+    assert_eq!(skip_columns("hello", 9), 5);
+    // Control characters take two columns, so the edge can fall inside one
+    assert_eq!(skip_columns("a\x01bc", 2), 2);
+    assert_eq!(skip_columns("a\x01bc", 3), 2);
+    assert_eq!(skip_columns("a😀bc", 2), 5);
+}
+
+#[test]
+fn shorten_path_abbreviates_then_collapses_folders() {
+    let path = Path::new("/usr/local/share/project/src/main.rs");
+    assert_eq!(shorten_path(path, 40), "/usr/local/share/project/src/main.rs");
+    assert_eq!(shorten_path(path, 20), "/u/l/s/p/src/main.rs");
+    assert_eq!(shorten_path(path, 14), "/…/src/main.rs");
+    assert_eq!(shorten_path(path, 12), "main.rs");
+    assert_eq!(shorten_path(path, 5), "…n.rs");
+}
+
+#[test]
+fn clock_formats() {
+    assert_eq!(format_clock(9, 5, false), "09:05");
+    assert_eq!(format_clock(0, 30, true), "12:30 AM");
+    assert_eq!(format_clock(12, 0, true), "12:00 PM");
+    assert_eq!(format_clock(23, 59, true), "11:59 PM");
+}
+
+#[test]
+fn elastic_tabs_line_up_columns_of_neighbouring_lines() {
+    let lines = ["a\tbb\tc", "ccc\td\te", "x", "\tindent"];
+    // The blank line ends both columns, and the indent tab gets the minimum width
+    assert_eq!(elastic_tab_padding(&lines, 4), [vec![4, 2], vec![2, 3], vec![], vec![4]]);
+
+    assert_eq!(expand_tabs("a\tbb\tc", &[4, 2]), "a    bb  c");
+    assert_eq!(expand_tabs("a\tb", &[]), "a\tb");
+    // Bytes after each tab move by the spaces it turned into
+    assert_eq!(expanded_index("a\tbb\tc", &[4, 2], 1), 1);
+    assert_eq!(expanded_index("a\tbb\tc", &[4, 2], 2), 5);
+    assert_eq!(expanded_index("a\tbb\tc", &[4, 2], 5), 9);
+    assert_eq!(expanded_index("a\tbb\tc", &[], 5), 5);
+}
+
+#[test]
+fn describe_char_reports_code_point_offset_and_width() {
+    let line = "a\u{200b}é\t\x1b中";
+    assert_eq!(describe_char(line, 0), "U+0061 at byte 0 (1 byte(s)), width 1");
+    assert_eq!(describe_char(line, 1), "U+200B ZERO WIDTH SPACE at byte 1 (3 byte(s)), width 0");
+    assert_eq!(describe_char(line, 4), "U+00E9 at byte 4 (2 byte(s)), width 1");
+    assert_eq!(describe_char(line, 6), "U+0009 CHARACTER TABULATION at byte 6 (1 byte(s)), width to the next tab stop");
+    assert_eq!(describe_char(line, 7), "U+001B ESCAPE at byte 7 (1 byte(s)), width 2 as ^[");
+    assert_eq!(describe_char(line, 8), "U+4E2D at byte 8 (3 byte(s)), width 2");
+    assert_eq!(describe_char(line, 11), "End of line (byte 11)");
+}
@@ -0,0 +1,24 @@
+use axis::finder::fuzzy_score;
+
+#[test]
+fn matches_are_case_insensitive_subsequences() {
+    assert!(fuzzy_score("mr", "src/main.rs").is_some());
+    assert!(fuzzy_score("MAIN", "src/main.rs").is_some());
+    assert!(fuzzy_score("rm", "src/main.rs").is_some());
+    assert!(fuzzy_score("zz", "src/main.rs").is_none());
+    assert!(fuzzy_score("sm rs", "src/main.rs").is_some());
+}
+
+#[test]
+fn empty_query_matches_everything() {
+    assert!(fuzzy_score("", "anything").is_some());
+    assert!(fuzzy_score("", "a").unwrap() > fuzzy_score("", "a/longer/path").unwrap());
+}
+
+#[test]
+fn file_names_and_word_starts_rank_higher() {
+    let score = |candidate| fuzzy_score("main", candidate).unwrap();
+    assert!(score("src/main.rs") > score("main/other/x.rs"));
+    assert!(score("src/main.rs") > score("src/domain.rs"));
+    assert!(fuzzy_score("fb", "src/FileBrowser.rs").unwrap() > fuzzy_score("fb", "src/fab.rs").unwrap());
+}
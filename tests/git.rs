@@ -0,0 +1,28 @@
+use axis::git::{diff_lines, LineStatus::*};
+
+#[test]
+fn unchanged_lines() {
+    assert_eq!(diff_lines(&["a", "b"], &["a", "b"]), [Unchanged, Unchanged]);
+}
+
+#[test]
+fn modified_and_added_lines() {
+    assert_eq!(diff_lines(&["a", "b", "c"], &["a", "B", "c"]), [Unchanged, Modified, Unchanged]);
+    assert_eq!(diff_lines(&["a", "b", "c"], &["a", "b", "x", "c"]), [Unchanged, Unchanged, Added, Unchanged]);
+    assert_eq!(diff_lines(&["a", "b"], &["a", "X", "Y", "b"]), [Unchanged, Added, Added, Unchanged]);
+    assert_eq!(diff_lines(&["a", "b", "c"], &["a", "X", "Y", "c"]), [Unchanged, Modified, Added, Unchanged]);
+}
+
+#[test]
+fn deleted_lines_mark_the_line_above() {
+    assert_eq!(diff_lines(&["a", "b", "c"], &["a", "c"]), [DeletedBelow, Unchanged]);
+    assert_eq!(diff_lines(&["a", "b", "c"], &["b", "c"]), [DeletedBelow, Unchanged]);
+    // A modified line keeps its marker when more lines were removed after it
+    assert_eq!(diff_lines(&["a", "b", "c", "d"], &["a", "X", "d"]), [Unchanged, Modified, Unchanged]);
+}
+
+#[test]
+fn new_file_is_all_added() {
+    assert_eq!(diff_lines(&[], &["a", "b"]), [Added, Added]);
+    assert_eq!(diff_lines(&["a"], &[]), []);
+}
@@ -0,0 +1,50 @@
+use axis::grep::GrepSearch;
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("axis-grep-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    for (path, content) in files {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+    root
+}
+
+fn wait_for(grep: &GrepSearch) {
+    for _ in 0..500 {
+        if !grep.is_running() {
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("search did not finish");
+}
+
+#[test]
+fn finished_search_reports_its_matches() {
+    let root = project("done", &[("a.txt", "one\nneedle here\n"), ("src/b.rs", "needle\nNeedle\n")]);
+    let grep = GrepSearch::start(&root, "needle");
+    wait_for(&grep);
+    assert_eq!(grep.len(), 3);
+    assert!(!grep.is_cancelled());
+    assert_eq!(grep.status_text(), "3 matches");
+    let first = grep.get(0).unwrap();
+    assert_eq!((first.path.as_str(), first.line, first.col), ("a.txt", 1, 0));
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn cancelled_search_stops_and_says_so() {
+    let root = project("cancel", &[("a.txt", "needle\n")]);
+    let mut grep = GrepSearch::start(&root, "needle");
+    grep.cancel();
+    wait_for(&grep);
+    assert!(grep.is_cancelled());
+    assert!(grep.status_text().ends_with("cancelled"));
+    fs::remove_dir_all(root).unwrap();
+}
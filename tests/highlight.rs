@@ -0,0 +1,270 @@
+use axis::config::Config;
+use axis::highlight::{ConfigHighlighter, Highlighter, HighlighterRegistry, Language, SyntaxHighlighter, TokenType};
+use axis::buffer::TextBuffer;
+use axis::pane::Pane;
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
+
+fn has_token(line: &str, tokens: &[(Range<usize>, TokenType)], text: &str, token_type: TokenType) -> bool {
+    tokens.iter().any(|(span, ty)| &line[span.clone()] == text && *ty == token_type)
+}
+
+#[test]
+fn rust_keywords_strings_and_comments() {
+    let highlighter = SyntaxHighlighter::new(Language::Rust);
+    let line = "fn main() { let s = \"hi\"; } // done";
+    let tokens = highlighter.highlight_line(line);
+    assert!(has_token(line, &tokens, "fn", TokenType::Keyword));
+    assert!(has_token(line, &tokens, "main", TokenType::Function));
+    assert!(has_token(line, &tokens, "let", TokenType::Keyword));
+    assert!(has_token(line, &tokens, "\"hi\"", TokenType::String));
+    assert!(has_token(line, &tokens, "// done", TokenType::Comment));
+}
+
+#[test]
+fn comment_markers_inside_a_comment_stay_comment() {
+    let highlighter = SyntaxHighlighter::new(Language::Python);
+    let line = "x = 1  # see # 2";
+    let tokens = highlighter.highlight_line(line);
+    assert!(has_token(line, &tokens, "# see # 2", TokenType::Comment));
+    assert!(has_token(line, &tokens, "1", TokenType::Number));
+}
+
+#[test]
+fn escaped_quotes_stay_in_the_string() {
+    let highlighter = SyntaxHighlighter::new(Language::JavaScript);
+    let line = r#"s = "a\"b" + 'c'"#;
+    let tokens = highlighter.highlight_line(line);
+    assert!(has_token(line, &tokens, r#""a\"b""#, TokenType::String));
+    assert!(has_token(line, &tokens, "'c'", TokenType::String));
+}
+
+#[test]
+fn an_escaped_backslash_ends_the_string() {
+    let line = r#"let s = "a\\"; let t = "b\\\"c";"#;
+    let tokens = SyntaxHighlighter::new(Language::Rust).highlight_line(line);
+    assert!(has_token(line, &tokens, r#""a\\""#, TokenType::String));
+    assert!(has_token(line, &tokens, "let", TokenType::Keyword));
+    assert!(has_token(line, &tokens, r#""b\\\"c""#, TokenType::String));
+}
+
+#[test]
+fn rust_lifetimes_are_not_strings() {
+    let line = "fn f<'a>(s: &'a str) -> char { '\"' }";
+    let tokens = SyntaxHighlighter::new(Language::Rust).highlight_line(line);
+    assert!(has_token(line, &tokens, "str", TokenType::Type));
+    assert!(has_token(line, &tokens, "char", TokenType::Type));
+    assert!(has_token(line, &tokens, "'\"'", TokenType::String));
+
+    let line = r"let c = '\''; let d = 'x';";
+    let tokens = SyntaxHighlighter::new(Language::Rust).highlight_line(line);
+    assert!(has_token(line, &tokens, r"'\''", TokenType::String));
+    assert!(has_token(line, &tokens, "'x'", TokenType::String));
+
+    // Other languages still quote with apostrophes
+    let line = "x = 'it' + y";
+    let tokens = SyntaxHighlighter::new(Language::Python).highlight_line(line);
+    assert!(has_token(line, &tokens, "'it'", TokenType::String));
+}
+
+#[test]
+fn spans_cover_the_line_without_gaps_or_overlaps() {
+    let samples = [
+        (Language::Rust, "fn héllo(x: u32) -> String { \"ünïcode\" } /* c */"),
+        (Language::Rust, "let s = \"unterminated"),
+        (Language::C, "int main(void) { return 0; } // ok"),
+        (Language::Python, "def f(a, b): return a  # trailing"),
+        (Language::Bash, "echo \"$HOME\" 'x' # done"),
+        (Language::Java, "    public static void main(String[] args) {"),
+        (Language::JavaScript, "const x = `tmpl` + 3.14;"),
+        (Language::Markdown, "# Title with `code`"),
+        (Language::Rust, ""),
+    ];
+    for (language, line) in samples {
+        let tokens = SyntaxHighlighter::new(language).highlight_line(line);
+        let mut pos = 0;
+        for (span, _) in &tokens {
+            assert_eq!(span.start, pos, "gap or overlap in {:?}", line);
+            assert!(span.end > span.start, "empty span in {:?}", line);
+            assert!(line.is_char_boundary(span.start) && line.is_char_boundary(span.end));
+            pos = span.end;
+        }
+        assert_eq!(pos, line.len(), "spans stop short in {:?}", line);
+    }
+}
+
+#[test]
+fn prose_is_one_normal_span() {
+    let line = "fn # not code";
+    let tokens = SyntaxHighlighter::new(Language::Markdown).highlight_line(line);
+    assert_eq!(tokens.len(), 1);
+    assert!(has_token(line, &tokens, line, TokenType::Normal));
+}
+
+#[test]
+fn languages_by_extension_and_name() {
+    assert!(Language::from_extension("rs") == Language::Rust);
+    assert!(Language::from_extension("py") == Language::Python);
+    assert!(Language::from_extension("unknown") == Language::Plain);
+    assert!(Language::from_name("RUST") == Some(Language::Rust));
+    assert!(Language::from_name("cobol").is_none());
+}
+
+fn unbalanced(language: Language, text: &str) -> Vec<(usize, usize)> {
+    let lines: Vec<String> = text.lines().map(str::to_string).collect();
+    SyntaxHighlighter::new(language).unbalanced_brackets(&lines)
+}
+
+#[test]
+fn balanced_brackets_across_lines_are_fine() {
+    assert!(unbalanced(Language::Rust, "fn f(a: [u8; 2]) {\n    g(\"(\", ')');\n}").is_empty());
+    assert!(unbalanced(Language::Python, "x = [\n    (1, 2),  # )\n]").is_empty());
+    assert!(unbalanced(Language::JavaScript, "if (a) { b('}'); } // {").is_empty());
+    assert!(unbalanced(Language::Bash, "case $x in\n  a) echo \"(\" ;;\nesac").is_empty());
+    assert!(unbalanced(Language::Markdown, "a ( b").is_empty());
+}
+
+#[test]
+fn stray_and_unclosed_brackets_are_reported() {
+    // A closer that doesn't match the open bracket, then the opener left unclosed
+    assert_eq!(unbalanced(Language::Rust, "fn f() {\n    g(];\n}"), [(1, 5), (1, 6)]);
+    assert_eq!(unbalanced(Language::C, "int main() {\n  return 0;\n"), [(0, 11)]);
+    assert_eq!(unbalanced(Language::Python, "print(1))"), [(0, 8)]);
+    assert_eq!(unbalanced(Language::Java, "}"), [(0, 0)]);
+}
+
+// Marks `key = value` lines: the key as a keyword, the rest as a string
+struct KeyValueHighlighter;
+
+impl Highlighter for KeyValueHighlighter {
+    fn highlight_line(&self, line: &str) -> Vec<(Range<usize>, TokenType)> {
+        match line.find('=') {
+            Some(eq) => vec![(0..eq, TokenType::Keyword), (eq..line.len(), TokenType::String)],
+            None => vec![(0..line.len(), TokenType::Normal)],
+        }
+    }
+
+    fn language(&self) -> Language {
+        Language::Bash
+    }
+
+    fn name(&self) -> &str {
+        "keyvalue"
+    }
+}
+
+#[test]
+fn registered_highlighters_come_before_the_built_in_languages() {
+    let mut registry = HighlighterRegistry::default();
+    registry.register("ENV", Rc::new(KeyValueHighlighter));
+
+    let custom = registry.for_path(Path::new("app.env")).unwrap();
+    assert_eq!((custom.name(), custom.language().name()), ("keyvalue", "bash"));
+    let line = "PORT=80";
+    assert!(has_token(line, &custom.highlight_line(line), "PORT", TokenType::Keyword));
+    // Bracket checking comes with the trait
+    assert_eq!(custom.unbalanced_brackets(&["(=".to_string()]), [(0, 0)]);
+
+    let built_in = registry.for_path(Path::new("main.rs")).unwrap();
+    assert_eq!(built_in.name(), "rust");
+    assert_eq!(registry.for_path(Path::new("notes.txt")).unwrap().name(), "plain");
+    assert!(registry.for_path(Path::new("Makefile")).is_none());
+
+    // A plain-text pane picks the registered highlighter up from its file name
+    let mut pane = Pane::new();
+    pane.current_file = Some("app.env".into());
+    assert!(pane.detect_language(&registry));
+    assert_eq!(pane.highlighter.name(), "keyvalue");
+}
+
+const INI_RULES: &str = r#"
+[highlight.ini]
+extensions = "ini, cfg"
+comment = "[;#].*"
+string = "\"[^\"]*\""
+keyword = "^\[.*\]"
+type = "^[\w.]+"
+number = "\d+"
+"#;
+
+#[test]
+fn config_rules_give_earlier_matches_precedence() {
+    let config = Config::from_content(INI_RULES);
+    let ini = ConfigHighlighter::new(&config.highlight_rules[0]).unwrap();
+    let typed = |line: &str| -> Vec<(String, TokenType)> {
+        ini.highlight_line(line).into_iter().map(|(span, ty)| (line[span].to_string(), ty)).collect()
+    };
+    let kinds = |line: &str| typed(line).into_iter().map(|(_, ty)| ty).collect::<Vec<_>>();
+
+    let line = "port = 80 ; was 8080";
+    assert_eq!(
+        typed(line).into_iter().map(|(text, _)| text).collect::<Vec<_>>(),
+        ["port", " = ", "80", " ", "; was 8080"]
+    );
+    assert!(kinds(line)[4] == TokenType::Comment);
+    // The number in the string loses to the earlier string rule, and the string loses
+    // to the comment rule before it when a `;` starts inside the quotes
+    let line = "name = \"a 1\"";
+    assert!(kinds(line) == [TokenType::Type, TokenType::Normal, TokenType::String]);
+    let line = "name = \"a;1\"";
+    assert_eq!(
+        typed(line).into_iter().map(|(text, _)| text).collect::<Vec<_>>(),
+        ["name", " = \"a", ";1\""]
+    );
+    // A section header is claimed by the keyword rule before `type` gets to it
+    assert!(kinds("[server.main]") == [TokenType::Keyword]);
+    assert!(kinds("") == [TokenType::Normal]);
+
+    let mut registry = HighlighterRegistry::default();
+    registry.register_rules(&config.highlight_rules[0]).unwrap();
+    let highlighter = registry.for_path(Path::new("setup.cfg")).unwrap();
+    assert_eq!((highlighter.name(), highlighter.language().name()), ("ini", "plain"));
+}
+
+#[test]
+fn bad_config_rules_are_reported() {
+    let config = Config::from_content("[highlight.x]\nextensions = \"x\"\nkeyword = \"(\"\n");
+    let error = HighlighterRegistry::default().register_rules(&config.highlight_rules[0]).unwrap_err();
+    assert!(error.contains("invalid pattern for keyword"), "{}", error);
+
+    let config = Config::from_content("[highlight.x]\nsparkle = \"a\"\n[highlight.y]\nlanguage = \"cobol\"\n");
+    assert_eq!(ConfigHighlighter::new(&config.highlight_rules[0]).err().unwrap(), "unknown token type: sparkle");
+    assert_eq!(ConfigHighlighter::new(&config.highlight_rules[1]).err().unwrap(), "unknown language: cobol");
+}
+
+#[test]
+fn diff_lines_are_coloured_by_their_prefix() {
+    let highlighter = SyntaxHighlighter::new(Language::Diff);
+    let kind = |line: &str| {
+        let tokens = highlighter.highlight_line(line);
+        assert_eq!(tokens.len(), 1);
+        tokens[0].1
+    };
+    assert!(kind("diff --git a/x b/x") == TokenType::Header);
+    assert!(kind("--- a/x") == TokenType::Header);
+    assert!(kind("+++ b/x") == TokenType::Header);
+    assert!(kind("@@ -1,2 +1,3 @@ fn main() {") == TokenType::Hunk);
+    assert!(kind("+    let x = (1;") == TokenType::Inserted);
+    assert!(kind("-    let x = 1;") == TokenType::Deleted);
+    assert!(kind(" unchanged") == TokenType::Normal);
+    assert!(TokenType::Header.is_bold() && !TokenType::Inserted.is_bold());
+    // Half of a changed line pair can't be expected to balance its brackets
+    assert!(highlighter.unbalanced_brackets(&["+    let x = (1;".to_string()]).is_empty());
+}
+
+#[test]
+fn patches_are_recognised_by_extension_or_content() {
+    assert!(Language::from_extension("patch") == Language::Diff);
+    assert!(Language::from_name("diff") == Some(Language::Diff));
+    let lines = |text: &str| text.lines().map(str::to_string).collect::<Vec<_>>();
+    assert!(Language::from_content(&lines("diff --git a/x b/x\nindex 1..2")) == Some(Language::Diff));
+    assert!(Language::from_content(&lines("--- a/x\n+++ b/x\n@@ -1 +1 @@")) == Some(Language::Diff));
+    assert!(Language::from_content(&lines("--- just a rule\ntext")).is_none());
+
+    // Piped `git diff` output has no file name to go by
+    let mut pane = Pane::new();
+    pane.set_buffer(TextBuffer::from_string("diff --git a/x b/x\n".to_string()));
+    assert!(pane.detect_language(&HighlighterRegistry::default()));
+    assert_eq!(pane.highlighter.name(), "diff");
+}
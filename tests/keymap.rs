@@ -0,0 +1,349 @@
+use axis::buffer::TextBuffer;
+use axis::config::Config;
+use axis::highlight::{Language, SyntaxHighlighter};
+use axis::keymap::{normal_mode_action, Action};
+use axis::pane::Pane;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::rc::Rc;
+
+fn pane_with(text: &str) -> Pane {
+    let mut pane = Pane::new();
+    pane.set_buffer(TextBuffer::from_string(text.to_string()));
+    pane
+}
+
+fn key(code: KeyCode) -> KeyEvent {
+    KeyEvent::new(code, KeyModifiers::NONE)
+}
+
+fn ctrl(c: char) -> KeyEvent {
+    KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+}
+
+// Feed keys to a pane the way normal mode does, with a 10-line view
+fn press_with(pane: &mut Pane, keys: &[KeyEvent], config: &Config) {
+    for &key in keys {
+        let action = normal_mode_action(key).expect("key is bound");
+        pane.apply(action, 10, config);
+    }
+}
+
+fn press(pane: &mut Pane, keys: &[KeyEvent]) {
+    press_with(pane, keys, &Config::default());
+}
+
+fn type_text(text: &str) -> Vec<KeyEvent> {
+    text.chars().map(|c| key(KeyCode::Char(c))).collect()
+}
+
+#[test]
+fn keys_map_to_actions() {
+    assert_eq!(normal_mode_action(key(KeyCode::Char('a'))), Some(Action::InsertChar('a')));
+    assert_eq!(
+        normal_mode_action(KeyEvent::new(KeyCode::Char('A'), KeyModifiers::SHIFT)),
+        Some(Action::InsertChar('A'))
+    );
+    assert_eq!(normal_mode_action(ctrl('s')), Some(Action::Save));
+    assert_eq!(normal_mode_action(ctrl('b')), Some(Action::FindPrev));
+    assert_eq!(normal_mode_action(ctrl('t')), Some(Action::Cut));
+    assert_eq!(normal_mode_action(ctrl('d')), Some(Action::DeleteLine));
+    assert_eq!(normal_mode_action(ctrl('e')), None);
+    assert_eq!(
+        normal_mode_action(KeyEvent::new(KeyCode::Char('k'), KeyModifiers::ALT)),
+        Some(Action::Split { vertical: true, same_buffer: true })
+    );
+    assert_eq!(
+        normal_mode_action(KeyEvent::new(KeyCode::Char('p'), KeyModifiers::ALT)),
+        Some(Action::InsertFilePrompt)
+    );
+    assert_eq!(
+        normal_mode_action(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::ALT)),
+        Some(Action::WriteLinesPrompt)
+    );
+    assert_eq!(
+        normal_mode_action(KeyEvent::new(KeyCode::Char('?'), KeyModifiers::ALT | KeyModifiers::SHIFT)),
+        Some(Action::DescribeChar)
+    );
+    assert_eq!(
+        normal_mode_action(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::ALT)),
+        Some(Action::ToggleLock)
+    );
+    assert_eq!(
+        normal_mode_action(KeyEvent::new(KeyCode::Char('.'), KeyModifiers::ALT)),
+        Some(Action::NextHunk)
+    );
+    assert_eq!(
+        normal_mode_action(KeyEvent::new(KeyCode::Char('%'), KeyModifiers::ALT | KeyModifiers::SHIFT)),
+        Some(Action::ReplacePrompt)
+    );
+    assert_eq!(
+        normal_mode_action(KeyEvent::new(KeyCode::Char('T'), KeyModifiers::ALT | KeyModifiers::SHIFT)),
+        Some(Action::ConvertIndentation { to_spaces: false })
+    );
+    assert_eq!(
+        normal_mode_action(KeyEvent::new(KeyCode::Up, KeyModifiers::CONTROL | KeyModifiers::SHIFT)),
+        Some(Action::ShrinkSplit)
+    );
+    assert_eq!(normal_mode_action(KeyEvent::new(KeyCode::Up, KeyModifiers::CONTROL)), Some(Action::ParagraphUp));
+}
+
+#[test]
+fn typing_with_auto_indent_and_auto_close() {
+    let mut pane = pane_with("fn f() {");
+    pane.use_spaces = true;
+    pane.tab_width = 4;
+    press(&mut pane, &[key(KeyCode::End), key(KeyCode::Enter)]);
+    press(&mut pane, &type_text("g("));
+    assert_eq!(pane.buffer().lines, ["fn f() {", "    g()"]);
+    assert_eq!((pane.cursor.y, pane.cursor.x), (1, 6));
+
+    // Backspace removes the empty pair, then the g, then a whole indent level
+    press(&mut pane, &[key(KeyCode::Backspace), key(KeyCode::Backspace), key(KeyCode::Backspace)]);
+    assert_eq!(pane.buffer().lines, ["fn f() {", ""]);
+    press(&mut pane, &[key(KeyCode::Backspace)]);
+    assert_eq!(pane.buffer().lines, ["fn f() {"]);
+    assert_eq!((pane.cursor.y, pane.cursor.x), (0, 8));
+
+    press(&mut pane, &[ctrl('z')]);
+    assert_eq!(pane.buffer().lines, ["fn f() {", ""]);
+}
+
+#[test]
+fn vertical_motion_keeps_the_goal_column() {
+    let mut pane = pane_with("long line\nab\nanother long one");
+    press(&mut pane, &[key(KeyCode::End), key(KeyCode::Down)]);
+    assert_eq!((pane.cursor.y, pane.cursor.x), (1, 2));
+    press(&mut pane, &[key(KeyCode::Down)]);
+    assert_eq!((pane.cursor.y, pane.cursor.x), (2, 9));
+
+    // The goal counts characters, so a multibyte character before it doesn't shift it
+    let mut pane = pane_with("hello\nhéllo\nhello");
+    press(&mut pane, &[key(KeyCode::Right), key(KeyCode::Right), key(KeyCode::Right), key(KeyCode::Down)]);
+    assert_eq!((pane.cursor.y, pane.cursor.x), (1, 3));
+    press(&mut pane, &[key(KeyCode::Down)]);
+    assert_eq!((pane.cursor.y, pane.cursor.x), (2, 3));
+    press(&mut pane, &[key(KeyCode::Up), key(KeyCode::Up)]);
+    assert_eq!((pane.cursor.y, pane.cursor.x), (0, 3));
+    let mut pane = pane_with("héllo\nhello");
+    press(&mut pane, &[key(KeyCode::End), key(KeyCode::Down)]);
+    assert_eq!((pane.cursor.y, pane.cursor.x), (1, 5));
+    press(&mut pane, &[key(KeyCode::Up)]);
+    assert_eq!((pane.cursor.y, pane.cursor.x), (0, 5));
+
+    // Right at the end of a line wraps to the next; Left at the start wraps back
+    let mut pane = pane_with("ab\ncd");
+    press(&mut pane, &[key(KeyCode::End), key(KeyCode::Right)]);
+    assert_eq!((pane.cursor.y, pane.cursor.x), (1, 0));
+    press(&mut pane, &[key(KeyCode::Left)]);
+    assert_eq!((pane.cursor.y, pane.cursor.x), (0, 2));
+}
+
+#[test]
+fn cursor_stops_at_line_ends_without_wrap_cursor() {
+    let config = Config { wrap_cursor: false, ..Config::default() };
+    let mut pane = pane_with("ab\ncd");
+    press_with(&mut pane, &[key(KeyCode::End), key(KeyCode::Right)], &config);
+    assert_eq!((pane.cursor.y, pane.cursor.x), (0, 2));
+    press_with(&mut pane, &[key(KeyCode::Down), key(KeyCode::Home), key(KeyCode::Left)], &config);
+    assert_eq!((pane.cursor.y, pane.cursor.x), (1, 0));
+}
+
+#[test]
+fn arrows_and_editing_step_over_multibyte_characters() {
+    // Columns count characters, so é is one column like the rest
+    let mut pane = pane_with("héllo");
+    press(&mut pane, &[key(KeyCode::Right), key(KeyCode::Right)]);
+    assert_eq!(pane.cursor.x, 2);
+    press(&mut pane, &[key(KeyCode::Left)]);
+    assert_eq!(pane.cursor.x, 1);
+
+    press(&mut pane, &type_text("x"));
+    assert_eq!(pane.buffer().lines, ["hxéllo"]);
+    press(&mut pane, &[key(KeyCode::Right)]);
+    press(&mut pane, &type_text("y"));
+    assert_eq!(pane.buffer().lines, ["hxéyllo"]);
+
+    // Backspace removes the whole character before the cursor, and redo does the same
+    press(&mut pane, &[key(KeyCode::Left), key(KeyCode::Backspace)]);
+    assert_eq!(pane.buffer().lines, ["hxyllo"]);
+    assert_eq!(pane.cursor.x, 2);
+    pane.undo();
+    assert_eq!(pane.buffer().lines, ["hxéyllo"]);
+    pane.redo();
+    assert_eq!(pane.buffer().lines, ["hxyllo"]);
+}
+
+#[test]
+fn editing_keys_are_known_before_they_run() {
+    // A read-only pane refuses these up front, so e.g. a shell filter never starts
+    for key in [ctrl('d'), ctrl('t'), ctrl('v'), ctrl('z'), key(KeyCode::Backspace), key(KeyCode::Char('x'))] {
+        assert!(normal_mode_action(key).is_some_and(|a| a.edits_buffer()), "{:?}", key);
+    }
+    assert!(Action::ShellFilter.edits_buffer() && Action::ReplacePrompt.edits_buffer());
+    for action in [Action::Copy, Action::Search, Action::Save, Action::SelectAll, Action::WriteLinesPrompt] {
+        assert!(!action.edits_buffer(), "{:?}", action);
+    }
+}
+
+#[test]
+fn ctrl_d_deletes_the_line_and_undo_restores_it() {
+    let mut pane = pane_with("first line\nab\nthird");
+    press(&mut pane, &[key(KeyCode::End), ctrl('d')]);
+    assert_eq!(pane.buffer().lines, ["ab", "third"]);
+    // The cursor stays on the row, clamped to the shorter line now there
+    assert_eq!((pane.cursor.y, pane.cursor.x), (0, 2));
+    press(&mut pane, &[ctrl('z')]);
+    assert_eq!(pane.buffer().lines, ["first line", "ab", "third"]);
+
+    // Deleting the last line moves up to the new last one
+    press(&mut pane, &[key(KeyCode::Down), key(KeyCode::Down), ctrl('d')]);
+    assert_eq!(pane.buffer().lines, ["first line", "ab"]);
+    assert_eq!(pane.cursor.y, 1);
+
+    // The only line is emptied rather than removed
+    let mut pane = pane_with("only");
+    press(&mut pane, &[ctrl('d')]);
+    assert_eq!(pane.buffer().lines, [""]);
+    assert_eq!(pane.cursor.x, 0);
+    press(&mut pane, &[ctrl('z')]);
+    assert_eq!(pane.buffer().lines, ["only"]);
+}
+
+#[test]
+fn typing_a_closing_tag_completes_its_name() {
+    let mut pane = pane_with("<div><span>x</span>");
+    pane.highlighter = Rc::new(SyntaxHighlighter::new(Language::Html));
+    press(&mut pane, &[key(KeyCode::End)]);
+    press(&mut pane, &type_text("</"));
+    assert_eq!(pane.buffer().lines, ["<div><span>x</span></div>"]);
+    assert_eq!(pane.cursor.x, 25);
+
+    // The completion is undone along with the slash that triggered it, leaving the
+    // `<` and the `>` paired with it
+    press(&mut pane, &[ctrl('z')]);
+    assert_eq!(pane.buffer().lines, ["<div><span>x</span><>"]);
+}
+
+#[test]
+fn auto_close_pairs_depend_on_the_language() {
+    let mut rust = pane_with("");
+    rust.highlighter = Rc::new(SyntaxHighlighter::new(Language::Rust));
+    press(&mut rust, &type_text("f<' ("));
+    assert_eq!(rust.buffer().lines, ["f<' ()"]);
+
+    let mut js = pane_with("");
+    js.highlighter = Rc::new(SyntaxHighlighter::new(Language::JavaScript));
+    press(&mut js, &type_text("x = '"));
+    assert_eq!(js.buffer().lines, ["x = ''"]);
+
+    // Pairs from the config replace the language's own
+    js.auto_close_override = Some(vec![('<', '>')]);
+    press(&mut js, &[key(KeyCode::End)]);
+    press(&mut js, &type_text(" <("));
+    assert_eq!(js.buffer().lines, ["x = '' <(>"]);
+}
+
+#[test]
+fn block_end_jumps_past_nested_python_blocks() {
+    let mut pane = pane_with("def f():\n    if x:\n        y()\n\n    return 1\nz = 2");
+    pane.highlighter = Rc::new(SyntaxHighlighter::new(Language::Python));
+    let block_end = KeyEvent::new(KeyCode::Char('j'), KeyModifiers::ALT);
+    // From the header to the end of the block it opens
+    press(&mut pane, &[block_end]);
+    assert_eq!((pane.cursor.y, pane.cursor.x), (4, 4));
+    // From inside a nested block to the end of that block
+    pane.cursor.y = 2;
+    press(&mut pane, &[block_end]);
+    assert_eq!(pane.cursor.y, 2);
+    pane.cursor.y = 1;
+    press(&mut pane, &[block_end]);
+    assert_eq!((pane.cursor.y, pane.cursor.x), (2, 8));
+    // At the top level there is nowhere to go
+    pane.cursor.y = 5;
+    press(&mut pane, &[block_end]);
+    assert_eq!(pane.cursor.y, 5);
+}
+
+#[test]
+fn folding_collapses_nested_python_blocks() {
+    let text = "def f():\n    if x:\n        y()\n\n    return 1\nz = 2";
+    let mut pane = pane_with(text);
+    let fold = KeyEvent::new(KeyCode::Char('-'), KeyModifiers::ALT);
+    // The inner block first, then the function around it
+    pane.cursor.y = 1;
+    press(&mut pane, &[fold]);
+    assert_eq!(pane.fold_ranges(), vec![1..3]);
+    assert_eq!(pane.shown_rows(0).collect::<Vec<_>>(), [0, 1, 3, 4, 5]);
+    press(&mut pane, &[key(KeyCode::Down)]);
+    assert_eq!(pane.cursor.y, 3);
+    pane.cursor.y = 0;
+    press(&mut pane, &[fold]);
+    assert_eq!(pane.shown_rows(0).collect::<Vec<_>>(), [0, 5]);
+    press(&mut pane, &[key(KeyCode::Down)]);
+    assert_eq!((pane.cursor.y, pane.cursor_screen_row()), (5, 1));
+    press(&mut pane, &[key(KeyCode::Up)]);
+    assert_eq!(pane.cursor.y, 0);
+
+    // Opening the outer fold shows the inner one still collapsed
+    press(&mut pane, &[fold]);
+    assert_eq!(pane.shown_rows(0).collect::<Vec<_>>(), [0, 1, 3, 4, 5]);
+    // A line with nothing indented under it has nothing to fold
+    pane.cursor.y = 4;
+    assert!(!pane.toggle_fold());
+
+    // Landing inside a fold opens it; adding a line opens them all
+    pane.cursor.y = 2;
+    assert!(pane.reveal_cursor());
+    assert!(pane.folds.is_empty());
+    pane.cursor.y = 1;
+    press(&mut pane, &[fold, key(KeyCode::End), key(KeyCode::Enter)]);
+    assert!(pane.folds.is_empty());
+}
+
+#[test]
+fn scrolling_counts_collapsed_blocks_as_one_row() {
+    let text = format!("a:\n{}\nb\nc", vec!["    x"; 20].join("\n"));
+    let mut pane = pane_with(&text);
+    pane.toggle_fold();
+    // 23 lines shown as 3 all fit in a 3-row view; a 2-row one scrolls by one shown row
+    pane.cursor.y = 22;
+    pane.adjust_scroll(3);
+    assert_eq!(pane.offset_y, 0);
+    pane.adjust_scroll(2);
+    assert_eq!(pane.offset_y, 21);
+    pane.cursor.y = 0;
+    pane.adjust_scroll(2);
+    press(&mut pane, &[key(KeyCode::Down), key(KeyCode::Down)]);
+    assert_eq!((pane.cursor.y, pane.offset_y), (22, 0));
+}
+
+#[test]
+fn page_keys_move_by_the_configured_step() {
+    let text = vec!["x"; 40].join("\n");
+    let mut pane = pane_with(&text);
+    press(&mut pane, &[key(KeyCode::PageDown)]);
+    assert_eq!(pane.cursor.y, 10);
+    let config = Config::from_content("page_scroll = 0.8");
+    press_with(&mut pane, &[key(KeyCode::PageDown)], &config);
+    assert_eq!(pane.cursor.y, 18);
+    press_with(&mut pane, &[key(KeyCode::PageUp)], &Config::from_content("page_scroll = 3"));
+    assert_eq!(pane.cursor.y, 15);
+}
+
+#[test]
+fn shift_motions_extend_a_selection_from_the_cursor() {
+    let shift = |code| KeyEvent::new(code, KeyModifiers::SHIFT);
+    let mut pane = pane_with("one\ntwo\nthree");
+    pane.cursor.x = 1;
+    press(&mut pane, &[shift(KeyCode::Right), shift(KeyCode::Down)]);
+    assert_eq!(pane.selection_start, Some((0, 1)));
+    assert_eq!(pane.selected_text().as_deref(), Some("ne\ntw"));
+    // Going back past the anchor selects backwards from it
+    press(&mut pane, &[shift(KeyCode::Up), shift(KeyCode::Home)]);
+    assert_eq!((pane.cursor.y, pane.cursor.x), (0, 0));
+    assert_eq!(pane.selected_text().as_deref(), Some("o"));
+    press(&mut pane, &[shift(KeyCode::End)]);
+    assert_eq!(pane.selected_text().as_deref(), Some("ne"));
+    assert!(normal_mode_action(shift(KeyCode::Left)).is_some_and(|a| a.keeps_selection()));
+    assert!(!Action::Left.keeps_selection() && Action::InsertChar('x').replaces_selection());
+}
@@ -0,0 +1,629 @@
+use axis::buffer::{EditCommand, SearchKind, SearchOptions, SearchPattern, TextBuffer};
+use axis::git::LineStatus;
+use axis::highlight::{HighlighterRegistry, Language, SyntaxHighlighter};
+use axis::pane::{
+    detect_indent, enclosing_block, indent_block_range, line_at_percent, mixed_indent_line, reindent_lines, retab_indent,
+    split_line_range, unclosed_tag, Pane, Panes,
+};
+use regex::Regex;
+use std::fs;
+use std::rc::Rc;
+
+fn pane_with(text: &str, row: usize, col: usize) -> Pane {
+    let mut pane = Pane::new();
+    pane.set_buffer(TextBuffer::from_string(text.to_string()));
+    pane.cursor.y = row;
+    pane.cursor.x = col;
+    pane
+}
+
+fn lines(text: &str) -> Vec<String> {
+    text.lines().map(str::to_string).collect()
+}
+
+#[test]
+fn backspace_in_space_indent_removes_a_whole_level() {
+    let mut pane = pane_with("        x", 0, 8);
+    pane.tab_width = 4;
+    let count = pane.soft_tab_width().expect("cursor is in the indentation");
+    assert_eq!(count, 4);
+    pane.execute_command(EditCommand::DeleteText {
+        row: 0,
+        col: 8 - count,
+        text: " ".repeat(count),
+    });
+    assert_eq!(pane.buffer().lines, ["    x"]);
+
+    // Off a tab stop it goes back to the previous one
+    pane.cursor.x = 3;
+    assert_eq!(pane.soft_tab_width(), Some(3));
+}
+
+#[test]
+fn soft_tab_width_only_applies_in_leading_spaces() {
+    let mut pane = pane_with("  a  ", 0, 5);
+    assert_eq!(pane.soft_tab_width(), None);
+    pane.cursor.x = 1;
+    assert_eq!(pane.soft_tab_width(), None);
+    let mut pane = pane_with("    ", 0, 4);
+    pane.use_spaces = false;
+    assert_eq!(pane.soft_tab_width(), None);
+}
+
+#[test]
+fn delete_to_line_start_handles_multibyte_text() {
+    let mut pane = pane_with("héllo wörld", 0, 1);
+    pane.delete_to_line_start();
+    assert_eq!(pane.buffer().lines, ["éllo wörld"]);
+    assert_eq!(pane.cursor.x, 0);
+
+    let mut pane = pane_with("héllo wörld", 0, 6);
+    pane.delete_to_line_start();
+    assert_eq!(pane.buffer().lines, ["wörld"]);
+    pane.undo();
+    assert_eq!(pane.buffer().lines, ["héllo wörld"]);
+}
+
+#[test]
+fn typed_pairs_close_and_step_over() {
+    let mut pane = pane_with("", 0, 0);
+    pane.type_char('(', true);
+    assert_eq!(pane.buffer().lines, ["()"]);
+    assert_eq!(pane.empty_pair_at_cursor(), Some(('(', ')')));
+    pane.type_char(')', true);
+    assert_eq!(pane.buffer().lines, ["()"]);
+    assert_eq!(pane.cursor.x, 2);
+    assert_eq!(pane.empty_pair_at_cursor(), None);
+
+    // A quote after a letter is an apostrophe
+    let mut pane = pane_with("don", 0, 3);
+    pane.type_char('\'', true);
+    assert_eq!(pane.buffer().lines, ["don'"]);
+}
+
+#[test]
+fn undo_and_redo_track_modified() {
+    let mut pane = pane_with("", 0, 0);
+    pane.type_char('h', false);
+    pane.type_char('i', false);
+    assert!(pane.is_modified());
+    pane.undo();
+    pane.undo();
+    assert_eq!(pane.buffer().lines, [""]);
+    assert!(!pane.is_modified());
+    pane.redo();
+    assert_eq!(pane.buffer().lines, ["h"]);
+    assert!(pane.is_modified());
+}
+
+#[test]
+fn detect_indent_reads_the_common_step() {
+    assert_eq!(detect_indent(&lines("fn a() {\n    b();\n    if c {\n        d();\n    }\n}")), Some((true, 4)));
+    assert_eq!(detect_indent(&lines("a:\n  b:\n    c: 1\n  d: 2")), Some((true, 2)));
+    assert_eq!(detect_indent(&lines("{\n\tx\n\t{\n\t\ty\n\t}\n}")), Some((false, 0)));
+    assert_eq!(detect_indent(&lines("no\nindent")), None);
+}
+
+#[test]
+fn mixed_indent_line_finds_the_first_switch() {
+    assert_eq!(mixed_indent_line(&lines("def f():\n    x = 1\n\ty = 2\n    z = 3")), Some(2));
+    assert_eq!(mixed_indent_line(&lines("a\n\tb\n\t\tc\n  d")), Some(3));
+    // Blank lines of whitespace don't count, and neither does one style on its own
+    assert_eq!(mixed_indent_line(&lines("a\n\tb\n    \n\tc")), None);
+    assert_eq!(mixed_indent_line(&lines("a\n  b\n    c")), None);
+    assert_eq!(mixed_indent_line(&lines("")), None);
+}
+
+#[test]
+fn retab_indent_keeps_the_visual_width() {
+    assert_eq!(retab_indent("\t  x", 4, true), "      x");
+    assert_eq!(retab_indent("      x", 4, false), "\t  x");
+    assert_eq!(retab_indent("x\t", 4, true), "x\t");
+}
+
+#[test]
+fn inserting_text_is_one_undo_step() {
+    let mut pane = pane_with("xy", 0, 1);
+    pane.insert_str_at_cursor("fn a() {\n    é\n}");
+    assert_eq!(pane.buffer().lines, ["xfn a() {", "    é", "}y"]);
+    assert_eq!((pane.cursor.y, pane.cursor.x), (2, 1));
+    assert_eq!(pane.undo_len(), 1);
+    pane.undo();
+    assert_eq!(pane.buffer().lines, ["xy"]);
+}
+
+#[test]
+fn word_before_cursor_is_the_trailing_identifier() {
+    assert_eq!(pane_with("let x = foo_1", 0, 13).word_before_cursor().as_deref(), Some("foo_1"));
+    assert_eq!(pane_with("a(fn", 0, 4).word_before_cursor().as_deref(), Some("fn"));
+    assert_eq!(pane_with("fn ", 0, 3).word_before_cursor().as_deref(), None);
+    assert_eq!(pane_with("", 0, 0).word_before_cursor().as_deref(), None);
+}
+
+#[test]
+fn snippets_expand_with_indentation_and_cursor() {
+    let mut pane = pane_with("    fn", 0, 6);
+    pane.tab_width = 4;
+    pane.expand_snippet("fn", "fn $0() {\n\t\n}");
+    assert_eq!(pane.buffer().lines, ["    fn () {", "        ", "    }"]);
+    assert_eq!((pane.cursor.y, pane.cursor.x), (0, 7));
+
+    // Trigger and body are one undo step
+    pane.undo();
+    assert_eq!(pane.buffer().lines, ["    fn"]);
+    pane.redo();
+    assert_eq!(pane.buffer().lines.len(), 3);
+}
+
+#[test]
+fn snippets_use_tabs_when_the_pane_does() {
+    let mut pane = pane_with("\tif", 0, 3);
+    pane.use_spaces = false;
+    pane.expand_snippet("if", "if x:\n\tpass");
+    assert_eq!(pane.buffer().lines, ["\tif x:", "\t\tpass"]);
+    // Without `$0` the cursor ends after the snippet
+    assert_eq!((pane.cursor.y, pane.cursor.x), (1, 6));
+}
+
+#[test]
+fn reindent_follows_braces_outside_strings_and_comments() {
+    let old = lines("fn a<'a>(s: &'a str) {\nif x {\nlet c = '{';\n} else {\n// }\nf(\"}\");\n\n}\n}");
+    let new = reindent_lines(&old, Language::Rust, "    ", 4);
+    assert_eq!(
+        new,
+        lines("fn a<'a>(s: &'a str) {\n    if x {\n        let c = '{';\n    } else {\n        // }\n        f(\"}\");\n\n    }\n}")
+    );
+}
+
+#[test]
+fn reindent_keeps_the_base_indent_of_a_selection() {
+    let old = lines("\t\tif (a) {\n  b();\n      }");
+    assert_eq!(reindent_lines(&old, Language::C, "\t", 4), lines("\t\tif (a) {\n\t\t\tb();\n\t\t}"));
+}
+
+#[test]
+fn reindent_uses_keywords_in_bash() {
+    let old = lines("if x; then\necho a\nelse\nfor i in 1; do\necho $i # done\ndone\nfi");
+    assert_eq!(
+        reindent_lines(&old, Language::Bash, "  ", 2),
+        lines("if x; then\n  echo a\nelse\n  for i in 1; do\n    echo $i # done\n  done\nfi")
+    );
+}
+
+#[test]
+fn reindent_normalizes_python_widths() {
+    let old = lines("def f():\n   if x:\n\tpass\n   return 1");
+    assert_eq!(
+        reindent_lines(&old, Language::Python, "    ", 8),
+        lines("def f():\n    if x:\n        pass\n    return 1")
+    );
+}
+
+#[test]
+fn selection_rows_cover_the_selected_bytes() {
+    let mut pane = pane_with("abc\ndéf\nghi", 2, 1);
+    pane.selection_start = Some((0, 1));
+    assert_eq!(pane.selection_on_row(0), Some(1..3));
+    assert_eq!(pane.selection_on_row(1), Some(0..4));
+    assert_eq!(pane.selection_on_row(2), Some(0..1));
+
+    // Selecting backwards gives the same ranges
+    pane.selection_start = Some((2, 1));
+    pane.cursor.y = 0;
+    pane.cursor.x = 1;
+    assert_eq!(pane.selection_on_row(1), Some(0..4));
+    assert_eq!(pane.selection_on_row(3), None);
+
+    // Selections are kept in characters and drawn in bytes
+    pane.selection_start = Some((1, 2));
+    (pane.cursor.y, pane.cursor.x) = (1, 3);
+    assert_eq!(pane.selection_on_row(1), Some(3..4));
+    assert_eq!(pane.selected_text().as_deref(), Some("f"));
+}
+
+#[test]
+fn closing_panes_keeps_focus_in_range() {
+    let mut panes = Panes::new(pane_with("a", 0, 0));
+    assert!(!panes.close_active());
+    panes.push(pane_with("b", 0, 0));
+    panes.push(pane_with("c", 0, 0));
+
+    // Closing the last pane focuses the new last one
+    panes.focus(2);
+    assert!(panes.close_active());
+    assert_eq!(panes.active_index(), 1);
+    assert_eq!(panes.active().buffer().lines, ["b"]);
+
+    // Closing the first focuses the one that took its place
+    panes.focus(0);
+    assert!(panes.close_active());
+    assert_eq!((panes.count(), panes.active_index()), (1, 0));
+    assert_eq!(panes.active().buffer().lines, ["b"]);
+
+    panes.focus(5);
+    assert_eq!(panes.active_index(), 0);
+}
+
+#[test]
+fn swapping_panes_keeps_focus_on_the_same_buffer() {
+    let mut panes = Panes::new(pane_with("a", 0, 0));
+    panes.push(pane_with("b", 0, 0));
+    panes.swap_with_next();
+    assert_eq!(panes.active_index(), 1);
+    assert_eq!(panes.active().buffer().lines, ["a"]);
+    panes.focus_next();
+    assert_eq!(panes.active().buffer().lines, ["b"]);
+}
+
+#[test]
+fn cloned_panes_share_text_and_history() {
+    let mut first = pane_with("one\ntwo\nthree", 2, 5);
+    let mut second = first.clone();
+    assert!(second.shares_buffer_with(&first));
+    second.cursor.y = 0;
+    second.cursor.x = 0;
+
+    first.insert_str_at_cursor("!");
+    assert_eq!(second.buffer().lines[2], "three!");
+    assert!(second.is_modified());
+    // Cursors are independent
+    assert_eq!((second.cursor.y, second.cursor.x), (0, 0));
+
+    // Either view can undo the other's edit
+    second.undo();
+    assert_eq!(first.buffer().lines[2], "three");
+    assert!(!first.is_modified());
+
+    // Opening other text in one view leaves the other alone
+    second.set_buffer(TextBuffer::from_string("other".to_string()));
+    assert!(!second.shares_buffer_with(&first));
+    assert_eq!(first.buffer().lines.len(), 3);
+}
+
+#[test]
+fn focusing_a_view_clamps_its_cursor_to_the_shared_text() {
+    let mut panes = Panes::new(pane_with("a\nbb\nccc", 2, 3));
+    panes.push(panes.active().clone());
+    panes.focus(0);
+    let pane = panes.active_mut();
+    pane.selection_start = Some((1, 0));
+    pane.replace_text((1, 0), (2, 3), "");
+
+    panes.focus(1);
+    assert_eq!((panes.active().cursor.y, panes.active().cursor.x), (1, 0));
+}
+
+#[test]
+fn file_contents_adds_the_bom_and_final_newline() {
+    let mut pane = pane_with("a\nb", 0, 0);
+    assert_eq!(pane.file_contents(), "a\nb");
+    pane.bom = true;
+    pane.trailing_newline = true;
+    assert_eq!(pane.file_contents(), "\u{FEFF}a\nb\n");
+}
+
+#[test]
+fn layout_changes_keep_cursors_on_screen_without_jumping() {
+    let text = vec!["x"; 100].join("\n");
+    let mut pane = pane_with(&text, 30, 0);
+    pane.offset_y = 15;
+    let mut panes = Panes::new(pane);
+    panes.push(pane_with(&text, 2, 0));
+
+    // Halving the height scrolls just enough to keep the cursor on the last row
+    panes.fit_to_heights(&[10, 10]);
+    assert_eq!(panes.get(0).unwrap().offset_y, 21);
+    assert_eq!(panes.get(1).unwrap().offset_y, 0);
+
+    // Closing the other pane leaves the survivor where it was
+    panes.focus(1);
+    assert!(panes.close_active());
+    panes.fit_to_heights(&[20]);
+    let pane = panes.active();
+    assert_eq!(pane.offset_y, 21);
+    assert!((pane.offset_y..pane.offset_y + 20).contains(&pane.cursor.y));
+}
+
+#[test]
+fn horizontal_scroll_keeps_the_cursor_column_in_view() {
+    let mut pane = pane_with(&"x".repeat(100), 0, 0);
+    // With 20 columns, 19 show text and the last is left for the truncation marker
+    pane.adjust_scroll_x(18, 20);
+    assert_eq!(pane.offset_x, 0);
+    pane.adjust_scroll_x(19, 20);
+    assert_eq!(pane.offset_x, 1);
+    pane.adjust_scroll_x(60, 20);
+    assert_eq!(pane.offset_x, 42);
+    // Moving back left scrolls only once the cursor passes the left edge
+    pane.adjust_scroll_x(50, 20);
+    assert_eq!(pane.offset_x, 42);
+    pane.adjust_scroll_x(5, 20);
+    assert_eq!(pane.offset_x, 5);
+}
+
+#[test]
+fn enclosing_block_is_the_smallest_around_the_row() {
+    let code = lines("fn a() {\n    let x = 1;\n    if x {\n        b();\n    }\n}\n\nfn c() {}");
+    assert_eq!(enclosing_block(&code, Language::Rust, 3), Some(2..5));
+    assert_eq!(enclosing_block(&code, Language::Rust, 1), Some(0..6));
+    assert_eq!(enclosing_block(&code, Language::Rust, 5), Some(0..6));
+    // Top level, and a block that opens and closes on one line
+    assert_eq!(enclosing_block(&code, Language::Rust, 6), None);
+    assert_eq!(enclosing_block(&code, Language::Rust, 7), None);
+
+    let code = lines("def f():\n    if x:\n        y()\n\n    z()\nw()");
+    assert_eq!(enclosing_block(&code, Language::Python, 2), Some(1..3));
+    assert_eq!(enclosing_block(&code, Language::Python, 4), Some(0..5));
+    assert_eq!(enclosing_block(&code, Language::Python, 5), None);
+}
+
+#[test]
+fn indent_block_range_follows_nested_python_indentation() {
+    let code = lines(
+        "class A:\n    def f(self):\n        if x:\n            y()\n\n        return 1\n\n    def g(self):\n        pass\n\nb = A()",
+    );
+    assert_eq!(indent_block_range(&code, 0), 0..9);
+    assert_eq!(indent_block_range(&code, 1), 1..6);
+    assert_eq!(indent_block_range(&code, 2), 2..4);
+    assert_eq!(indent_block_range(&code, 7), 7..9);
+    // A line with nothing deeper under it, a blank line, and the last line
+    assert_eq!(indent_block_range(&code, 3), 3..4);
+    assert_eq!(indent_block_range(&code, 4), 4..5);
+    assert_eq!(indent_block_range(&code, 10), 10..11);
+}
+
+#[test]
+fn percentages_map_to_rows_within_the_buffer() {
+    assert_eq!(line_at_percent(0, 200), 0);
+    assert_eq!(line_at_percent(50, 200), 100);
+    assert_eq!(line_at_percent(100, 200), 199);
+    assert_eq!(line_at_percent(250, 200), 199);
+    assert_eq!(line_at_percent(50, 1), 0);
+}
+
+#[test]
+fn unclosed_tag_skips_closed_and_void_elements() {
+    let html: Vec<String> = ["<div class=\"a\">", "  <p>text</p><br>", "  <img src=\"x\"/>", "  <ul><li>one</li>", "  "]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    assert_eq!(unclosed_tag(&html, 4, 2).as_deref(), Some("ul"));
+    assert_eq!(unclosed_tag(&html, 3, 2).as_deref(), Some("div"));
+    assert_eq!(unclosed_tag(&html, 1, 9).as_deref(), Some("p"));
+    assert_eq!(unclosed_tag(&html, 0, 0), None);
+    let closed = vec!["<b></b>".to_string()];
+    assert_eq!(unclosed_tag(&closed, 0, 7), None);
+}
+
+#[test]
+fn split_pane_gets_the_language_of_its_file() {
+    let registry = HighlighterRegistry::default();
+    let mut panes = Panes::new(pane_with("fn main() {}", 0, 0));
+    let mut split = Pane::new();
+    split.set_buffer(TextBuffer::from_string("def f(): pass".to_string()));
+    split.current_file = Some("script.py".into());
+    panes.push(split);
+    let split = panes.get_mut(1).unwrap();
+    assert_eq!(split.highlighter.language().name(), "plain");
+    assert!(split.detect_language(&registry));
+    assert_eq!(split.highlighter.language().name(), "python");
+    assert!(!split.detect_language(&registry));
+
+    // A language already chosen is left alone, as is a file of no known type
+    let mut pane = Pane::new();
+    pane.current_file = Some("notes.txt".into());
+    assert!(!pane.detect_language(&registry));
+    pane.highlighter = Rc::new(SyntaxHighlighter::new(Language::Rust));
+    pane.current_file = Some("script.py".into());
+    assert!(!pane.detect_language(&registry));
+    assert_eq!(pane.highlighter.language().name(), "rust");
+}
+
+#[test]
+fn regex_replace_expands_capture_groups() {
+    let mut pane = pane_with("let width = 10;\nlet height = 200;\nnone here", 1, 12);
+    let assignment = Regex::new(r"let (?<name>\w+) = (\d+)").unwrap();
+    assert_eq!(pane.replace_all_regex(&assignment, "const ${name}: u32 = $2 /* $$ */"), Ok(2));
+    assert_eq!(
+        pane.buffer().lines,
+        ["const width: u32 = 10 /* $ */;", "const height: u32 = 200 /* $ */;", "none here"]
+    );
+    pane.undo();
+    assert_eq!(pane.buffer().lines, ["let width = 10;", "let height = 200;", "none here"]);
+
+    // Replacements of different lengths on one line don't disturb each other
+    let mut pane = pane_with("a1 bb22 ccc333", 0, 0);
+    assert_eq!(pane.replace_all_regex(&Regex::new(r"([a-z]+)(\d+)").unwrap(), "${2}-$1"), Ok(3));
+    assert_eq!(pane.buffer().lines, ["1-a 22-bb 333-ccc"]);
+    assert_eq!(pane.replace_all_regex(&Regex::new("x").unwrap(), "y"), Ok(0));
+
+    // Groups the pattern doesn't have are refused before anything changes
+    let digits = Regex::new(r"(\d)").unwrap();
+    assert_eq!(pane.replace_all_regex(&digits, "$2"), Err("no group 2 in the pattern".to_string()));
+    assert_eq!(pane.replace_all_regex(&digits, "${word}"), Err("no group word in the pattern".to_string()));
+    assert_eq!(pane.replace_all_regex(&digits, "$1a"), Err("no group 1a in the pattern".to_string()));
+    assert_eq!(pane.buffer().lines, ["1-a 22-bb 333-ccc"]);
+}
+
+#[test]
+fn replace_all_is_one_undoable_step() {
+    let mut pane = pane_with("a-b\nb\nab-a", 2, 4);
+    assert_eq!(pane.replace_all("a", "xyz"), 3);
+    assert_eq!(pane.buffer().lines, ["xyz-b", "b", "xyzb-xyz"]);
+    assert_eq!(pane.replace_all("q", "r"), 0);
+    pane.undo();
+    assert_eq!(pane.buffer().lines, ["a-b", "b", "ab-a"]);
+}
+
+#[test]
+fn elastic_padding_lines_up_with_rows_outside_the_range() {
+    let pane = pane_with("x\na\tb\ncc\td\ny", 0, 0);
+    // Row 1 widens the column to fit "cc", though only row 2 is asked for
+    assert_eq!(pane.elastic_padding(2..3), [vec![2]]);
+    assert_eq!(pane.elastic_padding(1..2), [vec![3]]);
+    assert_eq!(pane.elastic_padding(0..10), [vec![], vec![3], vec![2], vec![]]);
+}
+
+#[test]
+fn next_change_skips_unchanged_lines() {
+    let mut pane = pane_with("a\nb\nc\nd\ne", 2, 0);
+    pane.line_status = vec![LineStatus::Unchanged; 5];
+    pane.line_status[0] = LineStatus::Modified;
+    pane.line_status[4] = LineStatus::Added;
+    assert_eq!(pane.next_change(true), Some(4));
+    assert_eq!(pane.next_change(false), Some(0));
+    pane.cursor.y = 4;
+    assert_eq!(pane.next_change(true), None);
+    pane.cursor.y = 0;
+    assert_eq!(pane.next_change(false), None);
+    // The cursor line itself doesn't count
+    pane.line_status[1] = LineStatus::DeletedBelow;
+    assert_eq!(pane.next_change(true), Some(1));
+}
+
+#[test]
+fn next_hunk_finds_hunk_headers() {
+    let mut pane = pane_with("--- a\n+++ b\n@@ -1 +1 @@\n-x\n+y\n@@ -9 +9 @@\n z", 3, 0);
+    assert_eq!(pane.next_hunk(true), Some(5));
+    assert_eq!(pane.next_hunk(false), Some(2));
+    pane.cursor.y = 5;
+    assert_eq!(pane.next_hunk(true), None);
+    pane.cursor.y = 2;
+    assert_eq!(pane.next_hunk(false), None);
+}
+
+#[test]
+fn line_range_prefix_is_split_from_the_file_name() {
+    assert_eq!(split_line_range("10,20 part.rs"), Some((10, 20, "part.rs")));
+    assert_eq!(split_line_range("3,3   my notes.md"), Some((3, 3, "my notes.md")));
+    assert_eq!(split_line_range("part.rs"), None);
+    assert_eq!(split_line_range("a,b part.rs"), None);
+    assert_eq!(split_line_range("1,2 "), Some((1, 2, "")));
+}
+
+#[test]
+fn selection_covers_whole_lines_but_not_one_it_only_reaches() {
+    let mut pane = pane_with("a\nb\nc\nd", 1, 1);
+    assert_eq!(pane.selected_rows(), None);
+    pane.selection_start = Some((1, 1));
+    pane.cursor.y = 3;
+    pane.cursor.x = 0;
+    assert_eq!(pane.selected_rows(), Some(1..=2));
+    pane.cursor.x = 1;
+    assert_eq!(pane.selected_rows(), Some(1..=3));
+    // An empty selection still covers its line
+    pane.cursor.y = 1;
+    assert_eq!(pane.selected_rows(), Some(1..=1));
+}
+
+#[test]
+fn write_lines_leaves_the_buffer_on_its_file() {
+    let path = std::env::temp_dir().join(format!("axis-write-lines-{}", std::process::id()));
+    let mut pane = pane_with("one\ntwo\nthree", 0, 0);
+    pane.insert_str_at_cursor("x");
+    pane.write_lines(1..=2, &path).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "two\nthree\n");
+    assert!(pane.is_modified());
+    assert_eq!(pane.current_file, None);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn cut_takes_the_selection_or_the_whole_line() {
+    let mut pane = pane_with("one\ntwo\nthree", 1, 2);
+    assert_eq!(pane.cut(), ("two\n".to_string(), true));
+    assert_eq!(pane.buffer().lines, ["one", "three"]);
+    assert_eq!((pane.cursor.y, pane.cursor.x), (1, 0));
+    pane.undo();
+    assert_eq!(pane.buffer().lines, ["one", "two", "three"]);
+
+    pane.selection_start = Some((0, 1));
+    (pane.cursor.y, pane.cursor.x) = (1, 1);
+    assert_eq!(pane.cut(), ("ne\nt".to_string(), false));
+    assert_eq!(pane.buffer().lines, ["owo", "three"]);
+    assert_eq!((pane.cursor.y, pane.cursor.x), (0, 1));
+
+    // The last line left is emptied, not removed
+    let mut pane = pane_with("only", 0, 3);
+    assert_eq!(pane.cut(), ("only\n".to_string(), true));
+    assert_eq!(pane.buffer().lines, [""]);
+    pane.undo();
+    assert_eq!(pane.buffer().lines, ["only"]);
+}
+
+#[test]
+fn replacing_matches_keeps_columns_right_when_lengths_differ() {
+    let mut pane = pane_with("a-a-a\nxa", 0, 0);
+    // Longer: the search resumes after the replacement, which contains the query
+    assert_eq!(pane.replace_match(0, 2, &SearchPattern::Literal("a"), "aaa"), (0, 5));
+    assert_eq!(pane.buffer().lines, ["a-aaa-a", "xa"]);
+    let options = SearchOptions { wrap: true, whole_word: false, kind: SearchKind::Literal };
+    assert_eq!(pane.replace_from("a", "aa", (0, 5), options), Ok(2));
+    assert_eq!(pane.buffer().lines, ["a-aaa-aa", "xaa"]);
+    // Shorter
+    let mut pane = pane_with("foo foo\nfoo", 0, 0);
+    assert_eq!(pane.replace_from("foo", "f", (0, 4), options), Ok(2));
+    assert_eq!(pane.buffer().lines, ["foo f", "f"]);
+    assert_eq!((pane.cursor.y, pane.cursor.x), (1, 1));
+    // All of them go in one undo
+    pane.undo();
+    assert_eq!(pane.buffer().lines, ["foo foo", "foo"]);
+}
+
+#[test]
+fn replacing_regex_matches_expands_groups_per_match() {
+    let mut pane = pane_with("a=1, b=22\nc=333", 0, 0);
+    let regex = Regex::new(r"(\w)=(\d+)").unwrap();
+    let pattern = SearchPattern::Regex(regex);
+    assert_eq!(pane.match_end(0, 5, &pattern), (0, 9));
+    assert_eq!(pane.replace_match(0, 5, &pattern, "$2=$1"), (0, 9));
+    assert_eq!(pane.buffer().lines, ["a=1, 22=b", "c=333"]);
+    let options = SearchOptions { wrap: false, whole_word: false, kind: SearchKind::Regex };
+    assert_eq!(pane.replace_from(r"(\w)=(\d+)", "${1}:$2", (0, 0), options), Ok(2));
+    assert_eq!(pane.buffer().lines, ["a:1, 22=b", "c:333"]);
+    // Checked before anything is replaced
+    assert_eq!(pane.replace_from(r"(\w):", "$2", (0, 0), options), Err("no group 2 in the pattern".to_string()));
+    assert_eq!(pane.buffer().lines, ["a:1, 22=b", "c:333"]);
+    // Empty matches move on rather than replacing the same spot forever
+    let mut pane = pane_with("ab", 0, 0);
+    assert_eq!(pane.replace_from("x*", "-", (0, 0), options), Ok(2));
+    assert_eq!(pane.buffer().lines, ["-a-b"]);
+
+    // Match positions are character columns on either side of a multibyte character
+    let mut pane = pane_with("é=1 ü=2", 0, 0);
+    let pattern = SearchPattern::Regex(Regex::new(r"(\w)=(\d)").unwrap());
+    assert_eq!(pane.match_end(0, 4, &pattern), (0, 7));
+    assert_eq!(pane.replace_match(0, 4, &pattern, "$2$1"), (0, 6));
+    assert_eq!(pane.buffer().lines, ["é=1 2ü"]);
+}
+
+#[test]
+fn replacing_empty_matches_with_nothing_keeps_earlier_history() {
+    let options = SearchOptions { wrap: false, whole_word: false, kind: SearchKind::Regex };
+    let mut pane = pane_with("abc", 0, 0);
+    assert_eq!(pane.replace_from("x*", "", (0, 0), options), Ok(3));
+    assert_eq!(pane.buffer().lines, ["abc"]);
+    assert_eq!(pane.undo_len(), 0);
+
+    let mut pane = pane_with("one\ntwo", 0, 0);
+    assert_eq!(pane.replace_from("^", "", (0, 0), options), Ok(2));
+    assert_eq!(pane.buffer().lines, ["one", "two"]);
+
+    // Only the replacements that changed something are grouped, not the edit before them
+    let mut pane = pane_with("aab", 0, 0);
+    pane.replace_text((0, 3), (0, 3), "!");
+    assert_eq!(pane.replace_from("a*", "", (0, 0), options), Ok(3));
+    assert_eq!(pane.buffer().lines, ["b!"]);
+    pane.undo();
+    assert_eq!(pane.buffer().lines, ["aab!"]);
+    pane.undo();
+    assert_eq!(pane.buffer().lines, ["aab"]);
+}
+
+#[test]
+fn replacing_whole_words_skips_longer_words() {
+    let mut pane = pane_with("cat catalog cat", 0, 0);
+    let options = SearchOptions { wrap: false, whole_word: true, kind: SearchKind::Literal };
+    assert_eq!(pane.replace_from("cat", "dog", (0, 0), options), Ok(2));
+    assert_eq!(pane.buffer().lines, ["dog catalog dog"]);
+}
@@ -0,0 +1,16 @@
+use axis::runner::{run_filter, shell_quote};
+
+#[test]
+fn quoted_paths_reach_the_shell_unchanged() {
+    assert_eq!(shell_quote("a b.sh", Some("sh -c")), "'a b.sh'");
+    assert_eq!(shell_quote("it's.sh", Some("bash -c")), "'it'\\''s.sh'");
+    assert_eq!(shell_quote(r"C:\50%\a b.py", Some("cmd /C")), r#""C:\50"^%"\a b.py""#);
+
+    // Nothing in the name is run or expanded, including a `'` ending the quotes early
+    if cfg!(unix) {
+        for name in ["$(echo hi).sh", "a \"b\" `c` \\d.sh", "it's $HOME.sh"] {
+            let output = run_filter(&format!("printf %s {}", shell_quote(name, None)), None, None).unwrap();
+            assert_eq!(output, name);
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use axis::script::{parse, Command};
+
+#[test]
+fn commands_are_parsed_with_their_line_numbers() {
+    let text = "# tidy up\ngoto 10\n\nsearch foo bar\nreplace a  b c\nreplace x\nsave\nreplace-regex (\\w+)=(\\d) $2 $1\n";
+    assert_eq!(
+        parse(text).unwrap(),
+        [
+            (2, Command::Goto(10)),
+            (4, Command::Search("foo bar".to_string())),
+            (5, Command::Replace { from: "a".to_string(), to: "b c".to_string() }),
+            (6, Command::Replace { from: "x".to_string(), to: String::new() }),
+            (7, Command::Save),
+            (8, Command::ReplaceRegex { pattern: r"(\w+)=(\d)".to_string(), to: "$2 $1".to_string() }),
+        ]
+    );
+}
+
+#[test]
+fn errors_name_the_offending_line() {
+    assert_eq!(parse("goto 1\njump 3").unwrap_err(), "line 2: unknown command: jump");
+    assert_eq!(parse("goto x").unwrap_err(), "line 1: invalid line number: x");
+    assert_eq!(parse("\nsearch").unwrap_err(), "line 2: search needs the text to find");
+    assert_eq!(parse("save now").unwrap_err(), "line 1: save takes no arguments");
+    assert_eq!(parse("replace-regex").unwrap_err(), "line 1: replace-regex needs a pattern");
+}
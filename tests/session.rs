@@ -0,0 +1,26 @@
+use axis::session::{Session, SessionFile};
+use std::path::PathBuf;
+
+#[test]
+fn session_round_trips_through_text() {
+    let session = Session {
+        vertical: Some(false),
+        active: 1,
+        files: vec![
+            SessionFile { path: PathBuf::from("/src/main.rs"), line: 12, col: 4 },
+            SessionFile { path: PathBuf::from("/notes/to do.md"), line: 1, col: 0 },
+        ],
+    };
+    assert_eq!(Session::parse(&session.to_text()), session);
+
+    let single = Session { vertical: None, active: 0, files: vec![session.files[0].clone()] };
+    assert_eq!(Session::parse(&single.to_text()), single);
+}
+
+#[test]
+fn unreadable_session_lines_are_skipped() {
+    let session = Session::parse("split vertical\nfile x 3 /a.rs\nfile 2 3\nbogus\nfile 2 3 /b.rs\n");
+    assert_eq!(session.vertical, Some(true));
+    assert_eq!(session.active, 0);
+    assert_eq!(session.files, vec![SessionFile { path: PathBuf::from("/b.rs"), line: 2, col: 3 }]);
+}